@@ -0,0 +1,193 @@
+#ifndef XML2ABX_H
+#define XML2ABX_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result codes, one per [`ConversionError`] variant.
+ */
+typedef enum xml2abx_error {
+  XML2ABX_ERROR_OK = 0,
+  XML2ABX_ERROR_XML_PARSING = 1,
+  XML2ABX_ERROR_IO = 2,
+  XML2ABX_ERROR_STRING_TOO_LONG = 3,
+  XML2ABX_ERROR_BINARY_DATA_TOO_LONG = 4,
+  XML2ABX_ERROR_INVALID_HEX = 5,
+  XML2ABX_ERROR_INVALID_BASE64 = 6,
+  XML2ABX_ERROR_UTF8 = 7,
+  XML2ABX_ERROR_ATTRIBUTE = 8,
+  XML2ABX_ERROR_INVALID_MAGIC = 9,
+  XML2ABX_ERROR_UNKNOWN_TOKEN = 10,
+  XML2ABX_ERROR_INVALID_INTERNED_INDEX = 11,
+  XML2ABX_ERROR_MALFORMED = 12,
+  XML2ABX_ERROR_INVALID_SELECTOR = 13,
+  XML2ABX_ERROR_UNKNOWN_TYPE = 14,
+  XML2ABX_ERROR_INVALID_VALUE = 15,
+  XML2ABX_ERROR_MISSING_ATTRIBUTE = 16,
+  XML2ABX_ERROR_JSON = 17,
+  XML2ABX_ERROR_SERDE = 18,
+  XML2ABX_ERROR_BACKUP = 19,
+  /**
+   * A required pointer was NULL.
+   */
+  XML2ABX_ERROR_INVALID_ARGUMENT = 100,
+  /**
+   * The library panicked; this is a bug.
+   */
+  XML2ABX_ERROR_PANIC = 101,
+} xml2abx_error;
+
+/**
+ * A `BinaryXmlSerializer` writing to memory or to a file.
+ */
+typedef struct xml2abx_serializer xml2abx_serializer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last error on this thread, or NULL. The string
+ * stays valid until the next failing call on the same thread.
+ */
+const char *xml2abx_last_error_message(void);
+
+/**
+ * Releases a buffer returned by this library.
+ */
+void xml2abx_free_buffer(uint8_t *data, size_t len);
+
+/**
+ * Converts `xml_len` bytes of XML into a newly allocated ABX buffer.
+ */
+enum xml2abx_error xml2abx_convert_buffer(const char *xml,
+                                          size_t xml_len,
+                                          bool preserve_whitespace,
+                                          uint8_t **out,
+                                          size_t *out_len);
+
+/**
+ * Converts the XML file at `input_path` into an ABX file at `output_path`.
+ */
+enum xml2abx_error xml2abx_convert_file(const char *input_path,
+                                        const char *output_path,
+                                        bool preserve_whitespace);
+
+/**
+ * Creates a serializer that collects the ABX in memory; retrieve it with
+ * [`xml2abx_serializer_finish`]. Never returns NULL.
+ */
+struct xml2abx_serializer *xml2abx_serializer_new_buffer(bool preserve_whitespace);
+
+/**
+ * Creates a serializer writing to the file at `path`.
+ */
+enum xml2abx_error xml2abx_serializer_new_file(const char *path,
+                                               bool preserve_whitespace,
+                                               struct xml2abx_serializer **out);
+
+/**
+ * Flushes and releases the serializer. For an in-memory serializer the ABX
+ * is returned through `out`/`out_len` when they are not NULL. The handle is
+ * released even if flushing fails.
+ */
+enum xml2abx_error xml2abx_serializer_finish(struct xml2abx_serializer *serializer,
+                                             uint8_t **out,
+                                             size_t *out_len);
+
+/**
+ * Releases a serializer without flushing it.
+ */
+void xml2abx_serializer_free(struct xml2abx_serializer *serializer);
+
+enum xml2abx_error xml2abx_serializer_start_document(struct xml2abx_serializer *serializer);
+
+enum xml2abx_error xml2abx_serializer_end_document(struct xml2abx_serializer *serializer);
+
+enum xml2abx_error xml2abx_serializer_start_tag(struct xml2abx_serializer *serializer,
+                                                const char *name);
+
+enum xml2abx_error xml2abx_serializer_end_tag(struct xml2abx_serializer *serializer,
+                                              const char *name);
+
+enum xml2abx_error xml2abx_serializer_attribute(struct xml2abx_serializer *serializer,
+                                                const char *name,
+                                                const char *value);
+
+enum xml2abx_error xml2abx_serializer_attribute_interned(struct xml2abx_serializer *serializer,
+                                                         const char *name,
+                                                         const char *value);
+
+enum xml2abx_error xml2abx_serializer_text(struct xml2abx_serializer *serializer, const char *text);
+
+enum xml2abx_error xml2abx_serializer_cdsect(struct xml2abx_serializer *serializer,
+                                             const char *text);
+
+enum xml2abx_error xml2abx_serializer_comment(struct xml2abx_serializer *serializer,
+                                              const char *text);
+
+enum xml2abx_error xml2abx_serializer_docdecl(struct xml2abx_serializer *serializer,
+                                              const char *text);
+
+enum xml2abx_error xml2abx_serializer_ignorable_whitespace(struct xml2abx_serializer *serializer,
+                                                           const char *text);
+
+enum xml2abx_error xml2abx_serializer_entity_ref(struct xml2abx_serializer *serializer,
+                                                 const char *text);
+
+enum xml2abx_error xml2abx_serializer_attribute_int(struct xml2abx_serializer *serializer,
+                                                    const char *name,
+                                                    int32_t value);
+
+enum xml2abx_error xml2abx_serializer_attribute_int_hex(struct xml2abx_serializer *serializer,
+                                                        const char *name,
+                                                        int32_t value);
+
+enum xml2abx_error xml2abx_serializer_attribute_long(struct xml2abx_serializer *serializer,
+                                                     const char *name,
+                                                     int64_t value);
+
+enum xml2abx_error xml2abx_serializer_attribute_long_hex(struct xml2abx_serializer *serializer,
+                                                         const char *name,
+                                                         int64_t value);
+
+enum xml2abx_error xml2abx_serializer_attribute_float(struct xml2abx_serializer *serializer,
+                                                      const char *name,
+                                                      float value);
+
+enum xml2abx_error xml2abx_serializer_attribute_double(struct xml2abx_serializer *serializer,
+                                                       const char *name,
+                                                       double value);
+
+enum xml2abx_error xml2abx_serializer_attribute_boolean(struct xml2abx_serializer *serializer,
+                                                        const char *name,
+                                                        bool value);
+
+/**
+ * `data` may be NULL.
+ */
+enum xml2abx_error xml2abx_serializer_processing_instruction(struct xml2abx_serializer *serializer,
+                                                             const char *target,
+                                                             const char *data);
+
+enum xml2abx_error xml2abx_serializer_attribute_bytes_hex(struct xml2abx_serializer *serializer,
+                                                          const char *name,
+                                                          const uint8_t *value,
+                                                          size_t value_len);
+
+enum xml2abx_error xml2abx_serializer_attribute_bytes_base64(struct xml2abx_serializer *serializer,
+                                                             const char *name,
+                                                             const uint8_t *value,
+                                                             size_t value_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* XML2ABX_H */
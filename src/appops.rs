@@ -0,0 +1,380 @@
+//! App-op modes (`/data/system/appops.xml`).
+//!
+//! The root `<app-ops v="N">` holds per-uid modes and per-package modes.
+//! Packages come in two layouts: older releases group them by uid,
+//!
+//! ```xml
+//! <app-ops v="1">
+//!     <uid n="10123"><op n="63" m="1" /></uid>
+//!     <pkg n="com.foo"><uid n="10123"><op n="26" m="1" /></uid></pkg>
+//! </app-ops>
+//! ```
+//!
+//! while `AppOpsCheckingServiceImpl` groups them by user instead:
+//!
+//! ```xml
+//! <app-ops v="1">
+//!     <user n="0"><pkg n="com.foo"><op n="26" m="1" /></pkg></user>
+//! </app-ops>
+//! ```
+//!
+//! [`AppOpsFile`] reads both and sets package modes in whichever layout the
+//! file already uses. On write, the attributes the service stores typed
+//! (`n` and `m` as ints, `<st>` times as longs, ...) are re-encoded;
+//! [`AppOpsFile::bump_version`] increments `v` as the `appops` command does
+//! after every edit.
+//!
+//! ```rust
+//! use xml2abx::appops::{AppOpsFile, MODE_IGNORED};
+//!
+//! let mut file = AppOpsFile::from_bytes(br#"<app-ops v="1"><user n="0" /></app-ops>"#).unwrap();
+//! file.set_package_mode("com.foo", 26, MODE_IGNORED, None, 0).unwrap();
+//! let packages = file.packages().unwrap();
+//! assert_eq!(packages[0].ops[0].mode, Some(MODE_IGNORED));
+//! ```
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element};
+use crate::value::AttributeValue;
+use std::io::Write;
+
+pub const TAG_APP_OPS: &str = "app-ops";
+pub const TAG_UID: &str = "uid";
+pub const TAG_PACKAGE: &str = "pkg";
+pub const TAG_USER: &str = "user";
+pub const TAG_OP: &str = "op";
+
+pub const MODE_ALLOWED: i32 = 0;
+pub const MODE_IGNORED: i32 = 1;
+pub const MODE_ERRORED: i32 = 2;
+pub const MODE_DEFAULT: i32 = 3;
+pub const MODE_FOREGROUND: i32 = 4;
+
+// Attribute types as `AppOpsService` writes them, by element.
+const ATTRIBUTE_TYPES: &[(&str, &str, &str)] = &[
+    (TAG_UID, "n", "int"),
+    (TAG_UID, "p", "boolean"),
+    (TAG_PACKAGE, "n", "string"),
+    (TAG_USER, "n", "int"),
+    (TAG_OP, "n", "int"),
+    (TAG_OP, "m", "int"),
+    (TAG_OP, "t", "long"),
+    (TAG_OP, "r", "long"),
+    (TAG_OP, "d", "long"),
+    (TAG_OP, "pu", "int"),
+    (TAG_OP, "pp", "string"),
+    ("st", "n", "long"),
+    ("st", "t", "long"),
+    ("st", "r", "long"),
+    ("st", "d", "long"),
+    ("st", "pu", "int"),
+    ("st", "pp", "string"),
+    ("st", "pc", "string"),
+];
+
+/// Parses a mode given by `AppOpsManager.modeToName` name or number.
+pub fn parse_mode(text: &str) -> Result<i32, ConversionError> {
+    Ok(match text {
+        "allow" => MODE_ALLOWED,
+        "ignore" => MODE_IGNORED,
+        "deny" | "error" => MODE_ERRORED,
+        "default" => MODE_DEFAULT,
+        "foreground" => MODE_FOREGROUND,
+        _ => text
+            .parse()
+            .map_err(|_| ConversionError::InvalidValue("app-op mode", text.to_string()))?,
+    })
+}
+
+/// `AppOpsManager.modeToName`.
+pub fn mode_name(mode: i32) -> Option<&'static str> {
+    Some(match mode {
+        MODE_ALLOWED => "allow",
+        MODE_IGNORED => "ignore",
+        MODE_ERRORED => "deny",
+        MODE_DEFAULT => "default",
+        MODE_FOREGROUND => "foreground",
+        _ => return None,
+    })
+}
+
+/// One `<op>`. A missing `m` means the op's default mode, which depends on
+/// the op and is not known here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpMode {
+    pub op: i32,
+    pub mode: Option<i32>,
+}
+
+/// The ops of a top-level `<uid>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UidOps {
+    pub uid: i32,
+    pub ops: Vec<OpMode>,
+}
+
+/// The ops of a package, under one uid or user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageOps {
+    pub package: String,
+    /// Set in the uid-grouped layout.
+    pub uid: Option<i32>,
+    /// Set in the user-grouped layout.
+    pub user: Option<i32>,
+    pub ops: Vec<OpMode>,
+}
+
+/// An app-ops document being edited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppOpsFile {
+    document: AbxDocument,
+}
+
+impl AppOpsFile {
+    /// Fails unless the root element is `<app-ops>`.
+    pub fn from_document(document: AbxDocument) -> Result<Self, ConversionError> {
+        match document.root() {
+            Some(root) if root.name == TAG_APP_OPS => Ok(Self { document }),
+            Some(root) => Err(ConversionError::Malformed(format!(
+                "expected <{}> root, found <{}>",
+                TAG_APP_OPS, root.name
+            ))),
+            None => Err(ConversionError::Malformed(
+                "document has no root element".to_string(),
+            )),
+        }
+    }
+
+    /// Loads ABX or text XML.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ConversionError> {
+        Self::from_document(AbxDocument::from_bytes(data)?)
+    }
+
+    /// The `v` attribute, if present.
+    pub fn version(&self) -> Result<Option<i32>, ConversionError> {
+        self.root()
+            .attribute("v")
+            .map(AttributeValue::as_int)
+            .transpose()
+    }
+
+    pub fn set_version(&mut self, version: i32) {
+        self.root_mut()
+            .set_attribute("v", AttributeValue::Int(version));
+    }
+
+    /// Increments `v`, returning the new value. Files without one are left
+    /// alone.
+    pub fn bump_version(&mut self) -> Result<Option<i32>, ConversionError> {
+        let version = self.version()?.map(|v| v + 1);
+        if let Some(version) = version {
+            self.set_version(version);
+        }
+        Ok(version)
+    }
+
+    /// Modes set per uid, in file order.
+    pub fn uids(&self) -> Result<Vec<UidOps>, ConversionError> {
+        self.root()
+            .elements()
+            .filter(|e| e.name == TAG_UID)
+            .map(|e| {
+                Ok(UidOps {
+                    uid: int_attribute(e, "n")?,
+                    ops: read_ops(e)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Modes set per package, in file order, from either layout.
+    pub fn packages(&self) -> Result<Vec<PackageOps>, ConversionError> {
+        let mut packages = Vec::new();
+        for element in self.root().elements() {
+            match element.name.as_str() {
+                TAG_PACKAGE => {
+                    let package = name_attribute(element)?;
+                    for uid in element.elements().filter(|e| e.name == TAG_UID) {
+                        packages.push(PackageOps {
+                            package: package.clone(),
+                            uid: Some(int_attribute(uid, "n")?),
+                            user: None,
+                            ops: read_ops(uid)?,
+                        });
+                    }
+                }
+                TAG_USER => {
+                    let user = int_attribute(element, "n")?;
+                    for pkg in element.elements().filter(|e| e.name == TAG_PACKAGE) {
+                        packages.push(PackageOps {
+                            package: name_attribute(pkg)?,
+                            uid: None,
+                            user: Some(user),
+                            ops: read_ops(pkg)?,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(packages)
+    }
+
+    /// Whether packages are grouped by user rather than by uid. Files with no
+    /// packages yet count as user-grouped, the current layout.
+    pub fn is_user_layout(&self) -> bool {
+        let root = self.root();
+        root.child(TAG_USER).is_some() || root.child(TAG_PACKAGE).is_none()
+    }
+
+    /// Sets the mode of `op` for `package`, adding whatever elements are
+    /// missing, and returns the previous mode.
+    ///
+    /// In the uid-grouped layout `uid` picks the package's `<uid>`; it may
+    /// be omitted when the package has exactly one. In the user-grouped
+    /// layout the package is looked up under `user`.
+    pub fn set_package_mode(
+        &mut self,
+        package: &str,
+        op: i32,
+        mode: i32,
+        uid: Option<i32>,
+        user: i32,
+    ) -> Result<Option<i32>, ConversionError> {
+        let ops = if self.is_user_layout() {
+            let user = find_or_push(self.root_mut(), TAG_USER, AttributeValue::Int(user));
+            find_or_push(
+                user,
+                TAG_PACKAGE,
+                AttributeValue::String(package.to_string()),
+            )
+        } else {
+            let uid = match uid {
+                Some(uid) => uid,
+                None => {
+                    let uids: Vec<i32> = self
+                        .packages()?
+                        .iter()
+                        .filter(|p| p.package == package)
+                        .filter_map(|p| p.uid)
+                        .collect();
+                    match uids.as_slice() {
+                        [only] => *only,
+                        _ => {
+                            return Err(ConversionError::Malformed(format!(
+                                "{} has {} uids, one must be given",
+                                package,
+                                uids.len()
+                            )));
+                        }
+                    }
+                }
+            };
+            let pkg = find_or_push(
+                self.root_mut(),
+                TAG_PACKAGE,
+                AttributeValue::String(package.to_string()),
+            );
+            find_or_push(pkg, TAG_UID, AttributeValue::Int(uid))
+        };
+        let op = find_or_push(ops, TAG_OP, AttributeValue::Int(op));
+        let previous = op.attribute("m").map(AttributeValue::as_int).transpose()?;
+        op.set_attribute("m", AttributeValue::Int(mode));
+        Ok(previous)
+    }
+
+    /// The document as `AppOpsService` would write it: whitespace dropped
+    /// and the attributes it stores typed re-encoded.
+    pub fn to_document(&self) -> Result<AbxDocument, ConversionError> {
+        let version = self.version()?;
+        let mut document = self.document.clone();
+        document.strip_whitespace();
+        let root = document.root_mut().expect("checked in from_document");
+        if let Some(v) = version {
+            root.set_attribute("v", AttributeValue::Int(v));
+        }
+        for element in root.elements_mut() {
+            retype(element)?;
+        }
+        Ok(document)
+    }
+
+    pub fn write_abx<W: Write>(&self, writer: W) -> Result<(), ConversionError> {
+        self.to_document()?.write_abx(writer)
+    }
+
+    /// Writes [`Self::to_document`] as text XML, indented when `pretty`.
+    pub fn write_xml<W: Write>(&self, writer: W, pretty: bool) -> Result<(), ConversionError> {
+        self.to_document()?.write_xml(writer, pretty)
+    }
+
+    pub fn to_abx_bytes(&self) -> Result<Vec<u8>, ConversionError> {
+        self.to_document()?.to_abx_bytes()
+    }
+
+    fn root(&self) -> &Element {
+        self.document.root().expect("checked in from_document")
+    }
+
+    fn root_mut(&mut self) -> &mut Element {
+        self.document.root_mut().expect("checked in from_document")
+    }
+}
+
+fn read_ops(element: &Element) -> Result<Vec<OpMode>, ConversionError> {
+    element
+        .elements()
+        .filter(|e| e.name == TAG_OP)
+        .map(|e| {
+            Ok(OpMode {
+                op: int_attribute(e, "n")?,
+                mode: e.attribute("m").map(AttributeValue::as_int).transpose()?,
+            })
+        })
+        .collect()
+}
+
+fn int_attribute(element: &Element, name: &str) -> Result<i32, ConversionError> {
+    element
+        .attribute(name)
+        .ok_or_else(|| ConversionError::MissingAttribute(name.to_string()))?
+        .as_int()
+}
+
+fn name_attribute(element: &Element) -> Result<String, ConversionError> {
+    element
+        .attribute("n")
+        .map(|v| v.to_string())
+        .ok_or_else(|| ConversionError::MissingAttribute("n".to_string()))
+}
+
+// The child `<tag n="key">`, appended if missing.
+fn find_or_push<'a>(parent: &'a mut Element, tag: &str, key: AttributeValue) -> &'a mut Element {
+    let wanted = key.to_string();
+    let index = parent
+        .elements()
+        .position(|e| e.name == tag && e.attribute("n").is_some_and(|n| n.to_string() == wanted));
+    let index = match index {
+        Some(index) => index,
+        None => {
+            parent.push_child(Element::new(tag).with_attribute("n", key));
+            parent.elements().count() - 1
+        }
+    };
+    parent.elements_mut().nth(index).expect("index found above")
+}
+
+fn retype(element: &mut Element) -> Result<(), ConversionError> {
+    for attribute in &mut element.attributes {
+        if let Some((_, _, type_name)) = ATTRIBUTE_TYPES
+            .iter()
+            .find(|(tag, name, _)| *tag == element.name && *name == attribute.name)
+        {
+            attribute.value = attribute.value.convert(type_name)?;
+        }
+    }
+    for child in element.elements_mut() {
+        retype(child)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Async conversion on tokio (the `tokio` feature).
+//!
+//! The output is byte-for-byte what [`XmlToAbxConverter`] writes for the
+//! same input:
+//!
+#![cfg_attr(feature = "tokio", doc = "```rust")]
+#![cfg_attr(not(feature = "tokio"), doc = "```rust,ignore")]
+//! use xml2abx::{XmlToAbxConverter, async_io};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), xml2abx::ConversionError> {
+//! let mut xml = String::from("<?xml version='1.0' encoding='utf-8' standalone='yes' ?>\n");
+//! xml.push_str("<packages>\n    <!-- installed -->\n");
+//! for i in 0..1000 {
+//!     xml.push_str(&format!(
+//!         "    <package name=\"com.example.app{i}\" flags=\"{i}\" enabled=\"true\" \
+//!          path=\"/data/app/with spaces/{i}\"><![CDATA[<raw>]]>text &amp; more</package>\n"
+//!     ));
+//! }
+//! xml.push_str("    <?pi data?>\n</packages>\n");
+//!
+//! let mut blocking = Vec::new();
+//! XmlToAbxConverter::convert_from_string(&xml, &mut blocking)?;
+//!
+//! let mut output = Vec::new();
+//! async_io::convert_from_reader(xml.as_bytes(), &mut output).await?;
+//! assert_eq!(output, blocking);
+//!
+//! // Reading a file works the same way:
+//! // let input = tokio::io::BufReader::new(tokio::fs::File::open("packages-text.xml").await?);
+//! // let output = tokio::fs::File::create("packages.xml").await?;
+//! // async_io::convert_from_reader(input, output).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BinaryXmlSerializer, ConversionError, XmlToAbxConverter};
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
+
+/// Bytes of encoded tokens collected before they are written out.
+const BUFFER_SIZE: usize = 8192;
+
+/// Async counterpart of [`BinaryXmlSerializer`].
+///
+/// Tokens are framed by a [`BinaryXmlSerializer`] into an in-memory buffer,
+/// so the encoding is exactly the blocking one, and the buffer is written to
+/// the inner writer whenever it fills up, on [`Self::flush`] and on
+/// [`Self::end_document`].
+pub struct AsyncBinaryXmlSerializer<W: AsyncWrite + Unpin> {
+    buffer: BinaryXmlSerializer<Vec<u8>>,
+    writer: W,
+}
+
+macro_rules! buffered {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        pub async fn $name(&mut self, $($arg: $ty),*) -> Result<(), ConversionError> {
+            self.buffer.$name($($arg),*)?;
+            self.write_if_full().await
+        }
+    )*};
+}
+
+impl<W: AsyncWrite + Unpin> AsyncBinaryXmlSerializer<W> {
+    pub fn new(writer: W) -> Result<Self, ConversionError> {
+        Self::with_options(writer, true)
+    }
+
+    pub fn with_options(writer: W, preserve_whitespace: bool) -> Result<Self, ConversionError> {
+        Ok(Self {
+            buffer: BinaryXmlSerializer::with_options(
+                Vec::with_capacity(BUFFER_SIZE),
+                preserve_whitespace,
+            )?,
+            writer,
+        })
+    }
+
+    buffered! {
+        start_document();
+        start_tag(name: &str);
+        end_tag(name: &str);
+        attribute(name: &str, value: &str);
+        attribute_interned(name: &str, value: &str);
+        attribute_bytes_hex(name: &str, value: &[u8]);
+        attribute_bytes_base64(name: &str, value: &[u8]);
+        attribute_int(name: &str, value: i32);
+        attribute_int_hex(name: &str, value: i32);
+        attribute_long(name: &str, value: i64);
+        attribute_long_hex(name: &str, value: i64);
+        attribute_float(name: &str, value: f32);
+        attribute_double(name: &str, value: f64);
+        attribute_boolean(name: &str, value: bool);
+        text(text: &str);
+        cdsect(text: &str);
+        comment(text: &str);
+        processing_instruction(target: &str, data: Option<&str>);
+        docdecl(text: &str);
+        ignorable_whitespace(text: &str);
+        entity_ref(text: &str);
+    }
+
+    pub async fn end_document(&mut self) -> Result<(), ConversionError> {
+        self.buffer.end_document()?;
+        self.flush().await
+    }
+
+    /// Writes the buffered tokens and flushes the inner writer.
+    pub async fn flush(&mut self) -> Result<(), ConversionError> {
+        self.write_buffer().await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Returns the inner writer; tokens not yet flushed are dropped.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    async fn write_buffer(&mut self) -> Result<(), ConversionError> {
+        let buffer = self.buffer.get_mut();
+        self.writer.write_all(buffer).await?;
+        buffer.clear();
+        Ok(())
+    }
+
+    async fn write_if_full(&mut self) -> Result<(), ConversionError> {
+        if self.buffer.get_mut().len() >= BUFFER_SIZE {
+            self.write_buffer().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Async equivalent of [`XmlToAbxConverter::convert_from_reader`].
+pub async fn convert_from_reader<R, W>(input: R, writer: W) -> Result<(), ConversionError>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    convert_from_reader_with_options(input, writer, true).await
+}
+
+/// Async equivalent of [`XmlToAbxConverter::convert_from_reader_with_options`].
+pub async fn convert_from_reader_with_options<R, W>(
+    input: R,
+    writer: W,
+    preserve_whitespace: bool,
+) -> Result<(), ConversionError>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = Reader::from_reader(input);
+    reader.config_mut().trim_text(!preserve_whitespace);
+    let mut serializer = AsyncBinaryXmlSerializer::with_options(writer, preserve_whitespace)?;
+    let mut buf = Vec::new();
+    serializer.start_document().await?;
+    loop {
+        match reader.read_event_into_async(&mut buf).await? {
+            Event::Eof => break,
+            event => {
+                XmlToAbxConverter::write_event(&mut serializer.buffer, event, reader.decoder())?
+            }
+        }
+        serializer.write_if_full().await?;
+        buf.clear();
+    }
+    serializer.end_document().await
+}
@@ -0,0 +1,278 @@
+//! Files the platform persists through `AtomicFile` and
+//! `ResilientAtomicFile`.
+//!
+//! `AtomicFile` writes `foo.xml.new` and renames it over `foo.xml`. Releases
+//! before Android 11 instead moved `foo.xml` to `foo.xml.bak` while writing,
+//! and a `.bak` left behind still wins over `foo.xml` on read.
+//!
+//! `ResilientAtomicFile` (`packages.xml` since Android 14) moves the current
+//! file to a temporary backup such as `packages-backup.xml`, writes
+//! `foo.xml` in place and, once the write is synced, copies it to
+//! `foo.xml.reservecopy` and drops the backup. Reads try the backup, then the
+//! file, then the reserve copy, moving on when one fails to parse.
+//!
+//! [`AtomicFile`] picks the file the platform would read and writes the way
+//! the platform does, so the companion files stay consistent. Unlike the
+//! platform it never changes anything on read. Companions are recognised by
+//! name alone, so a write renames or removes any file that happens to be
+//! called `foo.xml.bak` or `foo-backup.xml`; use it only for files the
+//! platform manages.
+//!
+//! ```no_run
+//! use xml2abx::{AbxDocument, AtomicFile};
+//!
+//! let file = AtomicFile::detect("/mnt/data/system/packages.xml");
+//! let (path, mut document) = file.read_with(AbxDocument::from_bytes)?;
+//! println!("read {}", path.display());
+//! document.strip_whitespace();
+//! file.write(|writer| document.write_abx(writer))?;
+//! # Ok::<(), xml2abx::ConversionError>(())
+//! ```
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A file written through `AtomicFile`, or through `ResilientAtomicFile`
+/// when it has a temporary backup and reserve copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtomicFile {
+    base: PathBuf,
+    resilient: Option<Companions>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Companions {
+    temporary_backup: PathBuf,
+    reserve_copy: PathBuf,
+}
+
+impl AtomicFile {
+    /// A plain `AtomicFile`.
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            resilient: None,
+        }
+    }
+
+    /// A `ResilientAtomicFile` with companions named the way `Settings`
+    /// names them for `packages.xml`: `packages-backup.xml` and
+    /// `packages.xml.reservecopy`.
+    pub fn resilient(base: impl Into<PathBuf>) -> Self {
+        let base = base.into();
+        let temporary_backup = match (base.file_stem(), base.extension()) {
+            (Some(stem), Some(ext)) => {
+                let mut name = stem.to_os_string();
+                name.push("-backup.");
+                name.push(ext);
+                base.with_file_name(name)
+            }
+            _ => with_suffix(&base, "-backup"),
+        };
+        let reserve_copy = with_suffix(&base, ".reservecopy");
+        Self::with_companions(base, temporary_backup, reserve_copy)
+    }
+
+    /// A `ResilientAtomicFile` with explicitly named companions.
+    pub fn with_companions(
+        base: impl Into<PathBuf>,
+        temporary_backup: impl Into<PathBuf>,
+        reserve_copy: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            base: base.into(),
+            resilient: Some(Companions {
+                temporary_backup: temporary_backup.into(),
+                reserve_copy: reserve_copy.into(),
+            }),
+        }
+    }
+
+    /// Resilient when a temporary backup or reserve copy with the default
+    /// names exists next to `base`, plain otherwise.
+    pub fn detect(base: impl Into<PathBuf>) -> Self {
+        let resilient = Self::resilient(base);
+        if resilient.companions().any(|path| path.exists()) {
+            resilient
+        } else {
+            Self::new(resilient.base)
+        }
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base
+    }
+
+    pub fn is_resilient(&self) -> bool {
+        self.resilient.is_some()
+    }
+
+    /// `foo.xml.new`, the unfinished write of a plain `AtomicFile`.
+    pub fn new_path(&self) -> PathBuf {
+        with_suffix(&self.base, ".new")
+    }
+
+    /// `foo.xml.bak`, left by releases before Android 11.
+    pub fn legacy_backup_path(&self) -> PathBuf {
+        with_suffix(&self.base, ".bak")
+    }
+
+    pub fn temporary_backup_path(&self) -> Option<&Path> {
+        self.resilient
+            .as_ref()
+            .map(|c| c.temporary_backup.as_path())
+    }
+
+    pub fn reserve_copy_path(&self) -> Option<&Path> {
+        self.resilient.as_ref().map(|c| c.reserve_copy.as_path())
+    }
+
+    /// The existing files the platform would read, in the order it tries
+    /// them. A plain file only ever has one.
+    pub fn read_candidates(&self) -> Vec<PathBuf> {
+        match &self.resilient {
+            None => {
+                let legacy = self.legacy_backup_path();
+                let path = if legacy.exists() {
+                    legacy
+                } else {
+                    self.base.clone()
+                };
+                if path.exists() {
+                    vec![path]
+                } else {
+                    Vec::new()
+                }
+            }
+            Some(c) => [&c.temporary_backup, &self.base, &c.reserve_copy]
+                .into_iter()
+                .filter(|p| p.exists())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Reads the first candidate, returning its path and contents.
+    pub fn read(&self) -> io::Result<(PathBuf, Vec<u8>)> {
+        self.read_with(|data| Ok::<_, io::Error>(data.to_vec()))
+    }
+
+    /// Reads the candidates in turn until `parse` accepts one, as
+    /// `ResilientAtomicFile.failRead` moves on to the next file. The error
+    /// of the last candidate is returned if none parses.
+    pub fn read_with<T, E: From<io::Error>>(
+        &self,
+        mut parse: impl FnMut(&[u8]) -> Result<T, E>,
+    ) -> Result<(PathBuf, T), E> {
+        let mut last_error = None;
+        for path in self.read_candidates() {
+            let result = fs::read(&path)
+                .map_err(E::from)
+                .and_then(|data| parse(&data));
+            match result {
+                Ok(value) => return Ok((path, value)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: no such file", self.base.display()),
+            )
+            .into()
+        }))
+    }
+
+    /// Replaces the contents the way `startWrite`/`finishWrite` do. On
+    /// failure the partial file is removed (`failWrite`) and the previous
+    /// contents stay readable. New files take the mode, and when permitted
+    /// the owner, of the file they replace.
+    pub fn write<E: From<io::Error>>(
+        &self,
+        write: impl FnOnce(&mut BufWriter<&File>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        match &self.resilient {
+            None => {
+                let legacy = self.legacy_backup_path();
+                if legacy.exists() {
+                    fs::rename(&legacy, &self.base)?;
+                }
+                let new_path = self.new_path();
+                let result = write_synced(&new_path, &self.base, write)
+                    .and_then(|()| Ok(fs::rename(&new_path, &self.base)?));
+                if result.is_err() {
+                    let _ = fs::remove_file(&new_path);
+                }
+                result
+            }
+            Some(c) => {
+                if self.base.exists() {
+                    if c.temporary_backup.exists() {
+                        // The backup is the last complete write; the file
+                        // is a partial one.
+                        fs::remove_file(&self.base)?;
+                    } else {
+                        fs::rename(&self.base, &c.temporary_backup)?;
+                    }
+                }
+                if let Err(e) = write_synced(&self.base, &c.temporary_backup, write) {
+                    let _ = fs::remove_file(&self.base);
+                    return Err(e);
+                }
+                write_synced(&c.reserve_copy, &self.base, |writer| {
+                    io::copy(&mut File::open(&self.base)?, writer)?;
+                    Ok::<_, E>(())
+                })?;
+                if c.temporary_backup.exists() {
+                    fs::remove_file(&c.temporary_backup)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn companions(&self) -> impl Iterator<Item = &Path> {
+        self.temporary_backup_path()
+            .into_iter()
+            .chain(self.reserve_copy_path())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Creates `path` with the metadata of `template`, fills it and syncs it.
+fn write_synced<E: From<io::Error>>(
+    path: &Path,
+    template: &Path,
+    write: impl FnOnce(&mut BufWriter<&File>) -> Result<(), E>,
+) -> Result<(), E> {
+    let metadata = fs::metadata(template).ok();
+    let file = File::create(path)?;
+    if let Some(metadata) = metadata {
+        inherit_metadata(&file, &metadata);
+    }
+    let mut writer = BufWriter::new(&file);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Gives `file` the mode and owner in `metadata`, as files that replace
+/// another should keep them. Best effort: only root may hand a file to
+/// another owner.
+pub fn inherit_metadata(file: &File, metadata: &fs::Metadata) {
+    let _ = file.set_permissions(metadata.permissions());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+    }
+}
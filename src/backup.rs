@@ -0,0 +1,472 @@
+//! Android backup archives (`adb backup`, `.ab`).
+//!
+//! An archive is a text header followed by a tar stream, zlib-compressed
+//! when the header says so and AES-256-CBC encrypted when a password was
+//! given:
+//!
+//! ```text
+//! ANDROID BACKUP
+//! 5            format version
+//! 1            compressed
+//! AES-256      or "none"; when encrypted, followed by the user password
+//!              salt, master key checksum salt, PBKDF2 rounds, user key IV
+//!              and encrypted master key blob, one hex line each
+//! ```
+//!
+//! [`AndroidBackup`] decrypts and unpacks the whole archive into memory.
+//! Entries keep their original tar header blocks, pax and GNU extension
+//! headers included, so writing an archive back only changes the entries
+//! whose data was replaced. Writing with a password encrypts with fresh keys
+//! and salts, as `BackupManagerService` does.
+//!
+//! ```no_run
+//! use xml2abx::backup::{AndroidBackup, EntryFormat};
+//!
+//! let data = std::fs::read("backup.ab")?;
+//! let mut backup = AndroidBackup::from_bytes(&data, Some("secret"))?;
+//! for entry in &backup.entries {
+//!     if entry.format() == Some(EntryFormat::Abx) {
+//!         println!("{}", entry.path);
+//!     }
+//! }
+//! let repacked = backup.to_bytes(Some("secret"))?;
+//! # Ok::<(), xml2abx::ConversionError>(())
+//! ```
+
+use crate::ConversionError;
+use crate::document::AbxDocument;
+use crate::value::{decode_hex, encode_hex};
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::{Read, Write};
+
+pub const BACKUP_MAGIC: &str = "ANDROID BACKUP";
+/// Format version written by current releases (`BACKUP_FILE_VERSION`).
+pub const BACKUP_FILE_VERSION: u32 = 5;
+
+const ENCRYPTION_NONE: &str = "none";
+const ENCRYPTION_AES_256: &str = "AES-256";
+const PBKDF2_ROUNDS: u32 = 10000;
+const PBKDF2_SALT_SIZE: usize = 64;
+const KEY_SIZE: usize = 32;
+const IV_SIZE: usize = 16;
+const BLOCK_SIZE: usize = 512;
+
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+/// What an entry holds, judged by its content and name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryFormat {
+    Abx,
+    Xml,
+}
+
+/// One file, directory or other tar entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupEntry {
+    /// Full path, from a pax or GNU long-name header when present.
+    pub path: String,
+    pub data: Vec<u8>,
+    // Every header block of the entry, extension headers and their data
+    // included; the last block is the entry's own header.
+    headers: Vec<u8>,
+}
+
+impl BackupEntry {
+    /// The tar type flag of the entry, e.g. `b'0'` for a file.
+    pub fn type_flag(&self) -> u8 {
+        self.header()[156]
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self.type_flag(), b'0' | b'\0' | b'7')
+    }
+
+    /// `Abx` for files with the ABX magic, `Xml` for other files named
+    /// `*.xml` or starting with an XML declaration.
+    pub fn format(&self) -> Option<EntryFormat> {
+        if !self.is_file() {
+            return None;
+        }
+        if AbxDocument::is_abx(&self.data) {
+            return Some(EntryFormat::Abx);
+        }
+        let text = self.data.trim_ascii_start();
+        if self.path.to_ascii_lowercase().ends_with(".xml") || text.starts_with(b"<?xml") {
+            Some(EntryFormat::Xml)
+        } else {
+            None
+        }
+    }
+
+    fn header(&self) -> &[u8] {
+        &self.headers[self.headers.len() - BLOCK_SIZE..]
+    }
+}
+
+/// A decrypted, decompressed backup archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndroidBackup {
+    pub version: u32,
+    pub compressed: bool,
+    /// Whether the archive read was encrypted. [`Self::write`] encrypts
+    /// whenever it is given a password.
+    pub encrypted: bool,
+    pub entries: Vec<BackupEntry>,
+}
+
+impl AndroidBackup {
+    /// Reads an archive; `password` is required when it is encrypted.
+    pub fn from_bytes(data: &[u8], password: Option<&str>) -> Result<Self, ConversionError> {
+        let mut lines = HeaderLines { data, pos: 0 };
+        if lines.next()? != BACKUP_MAGIC {
+            return Err(backup_error("not an Android backup"));
+        }
+        let version = parse_number(lines.next()?)?;
+        if version == 0 || version > BACKUP_FILE_VERSION {
+            return Err(backup_error(format!("unsupported version {}", version)));
+        }
+        let compressed = parse_number(lines.next()?)? != 0;
+        let encrypted = match lines.next()? {
+            ENCRYPTION_NONE => false,
+            ENCRYPTION_AES_256 => true,
+            other => return Err(backup_error(format!("unknown encryption {:?}", other))),
+        };
+
+        let payload = if encrypted {
+            let password = password
+                .ok_or_else(|| backup_error("the backup is encrypted; a password is required"))?;
+            let user_salt = decode_hex(lines.next()?)?;
+            let checksum_salt = decode_hex(lines.next()?)?;
+            let rounds = parse_number(lines.next()?)?;
+            let user_iv = decode_hex(lines.next()?)?;
+            let master_key_blob = decode_hex(lines.next()?)?;
+
+            let user_key = derive_key(&password_bytes(password, version), &user_salt, rounds);
+            let blob = decrypt(&user_key, &user_iv, &master_key_blob)
+                .map_err(|_| backup_error("wrong password"))?;
+            let mut fields = BlobFields {
+                blob: &blob,
+                pos: 0,
+            };
+            let master_iv = fields.next()?;
+            let master_key = fields.next()?;
+            let checksum = fields.next()?;
+            let expected = derive_key(&checksum_bytes(master_key, version), &checksum_salt, rounds);
+            if checksum != expected {
+                return Err(backup_error("wrong password"));
+            }
+            decrypt(master_key, master_iv, &data[lines.pos..])?
+        } else {
+            data[lines.pos..].to_vec()
+        };
+
+        let tar = if compressed {
+            let mut tar = Vec::new();
+            ZlibDecoder::new(payload.as_slice()).read_to_end(&mut tar)?;
+            tar
+        } else {
+            payload
+        };
+        Ok(Self {
+            version,
+            compressed,
+            encrypted,
+            entries: read_tar(&tar)?,
+        })
+    }
+
+    /// Reads an archive from `input`, which is consumed entirely.
+    pub fn read<R: Read>(mut input: R, password: Option<&str>) -> Result<Self, ConversionError> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        Self::from_bytes(&data, password)
+    }
+
+    /// Writes the archive, encrypted with `password` if one is given.
+    pub fn write<W: Write>(
+        &self,
+        mut output: W,
+        password: Option<&str>,
+    ) -> Result<(), ConversionError> {
+        let mut tar = Vec::new();
+        for entry in &self.entries {
+            write_entry(&mut tar, entry);
+        }
+        tar.resize(tar.len() + 2 * BLOCK_SIZE, 0);
+        let payload = if self.compressed {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(&tar)?;
+            encoder.finish()?
+        } else {
+            tar
+        };
+
+        write!(
+            output,
+            "{}\n{}\n{}\n",
+            BACKUP_MAGIC,
+            self.version,
+            u8::from(self.compressed)
+        )?;
+        match password {
+            None => {
+                writeln!(output, "{}", ENCRYPTION_NONE)?;
+                output.write_all(&payload)?;
+            }
+            Some(password) => {
+                let user_salt = random_bytes(PBKDF2_SALT_SIZE)?;
+                let checksum_salt = random_bytes(PBKDF2_SALT_SIZE)?;
+                let user_iv = random_bytes(IV_SIZE)?;
+                let master_key = random_bytes(KEY_SIZE)?;
+                let master_iv = random_bytes(IV_SIZE)?;
+                let user_key = derive_key(
+                    &password_bytes(password, self.version),
+                    &user_salt,
+                    PBKDF2_ROUNDS,
+                );
+                let checksum = derive_key(
+                    &checksum_bytes(&master_key, self.version),
+                    &checksum_salt,
+                    PBKDF2_ROUNDS,
+                );
+                let mut blob = Vec::new();
+                for field in [&master_iv[..], &master_key, &checksum] {
+                    blob.push(field.len() as u8);
+                    blob.extend_from_slice(field);
+                }
+                write!(
+                    output,
+                    "{}\n{}\n{}\n{}\n{}\n{}\n",
+                    ENCRYPTION_AES_256,
+                    encode_hex(&user_salt),
+                    encode_hex(&checksum_salt),
+                    PBKDF2_ROUNDS,
+                    encode_hex(&user_iv),
+                    encode_hex(&encrypt(&user_key, &user_iv, &blob))
+                )?;
+                output.write_all(&encrypt(&master_key, &master_iv, &payload))?;
+            }
+        }
+        output.flush()?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self, password: Option<&str>) -> Result<Vec<u8>, ConversionError> {
+        let mut out = Vec::new();
+        self.write(&mut out, password)?;
+        Ok(out)
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&BackupEntry> {
+        self.entries.iter().find(|e| e.path == path)
+    }
+
+    pub fn entry_mut(&mut self, path: &str) -> Option<&mut BackupEntry> {
+        self.entries.iter_mut().find(|e| e.path == path)
+    }
+}
+
+fn backup_error(message: impl Into<String>) -> ConversionError {
+    ConversionError::Backup(message.into())
+}
+
+fn parse_number(line: &str) -> Result<u32, ConversionError> {
+    line.trim()
+        .parse()
+        .map_err(|_| backup_error(format!("bad header line {:?}", line)))
+}
+
+// The newline-terminated lines of the text header.
+struct HeaderLines<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> HeaderLines<'a> {
+    fn next(&mut self) -> Result<&'a str, ConversionError> {
+        let rest = &self.data[self.pos..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| backup_error("truncated header"))?;
+        self.pos += end + 1;
+        std::str::from_utf8(&rest[..end]).map_err(|_| backup_error("header is not text"))
+    }
+}
+
+// The length-prefixed fields of the decrypted master key blob.
+struct BlobFields<'a> {
+    blob: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BlobFields<'a> {
+    fn next(&mut self) -> Result<&'a [u8], ConversionError> {
+        let len = *self
+            .blob
+            .get(self.pos)
+            .ok_or_else(|| backup_error("truncated master key blob"))? as usize;
+        let field = self
+            .blob
+            .get(self.pos + 1..self.pos + 1 + len)
+            .ok_or_else(|| backup_error("truncated master key blob"))?;
+        self.pos += 1 + len;
+        Ok(field)
+    }
+}
+
+// Version 1 archives used `PBKDF2WithHmacSHA1And8bit`, which keeps the low
+// byte of each UTF-16 unit; later ones encode the password as UTF-8.
+fn password_bytes(password: &str, version: u32) -> Vec<u8> {
+    if version == 1 {
+        password.encode_utf16().map(|unit| unit as u8).collect()
+    } else {
+        password.as_bytes().to_vec()
+    }
+}
+
+// `makeKeyChecksum` turns each key byte into a Java char with sign
+// extension before deriving, so bytes from 0x80 become U+FF80..U+FFFF.
+fn checksum_bytes(key: &[u8], version: u32) -> Vec<u8> {
+    if version == 1 {
+        return key.to_vec();
+    }
+    let mut out = Vec::new();
+    for &b in key {
+        let c = char::from_u32(if b < 0x80 {
+            b as u32
+        } else {
+            0xff00 | b as u32
+        })
+        .expect("U+FF80..U+FFFF are valid chars");
+        let mut buf = [0; 4];
+        out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+    out
+}
+
+fn derive_key(password: &[u8], salt: &[u8], rounds: u32) -> [u8; KEY_SIZE] {
+    let mut key = [0; KEY_SIZE];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(password, salt, rounds, &mut key);
+    key
+}
+
+fn decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, ConversionError> {
+    Aes256CbcDec::new_from_slices(key, iv)
+        .map_err(|_| backup_error("bad key or IV length"))?
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| backup_error("decryption failed"))
+}
+
+fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    Aes256CbcEnc::new_from_slices(key, iv)
+        .expect("keys and IVs are generated with the right length")
+        .encrypt_padded_vec_mut::<Pkcs7>(data)
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, ConversionError> {
+    let mut bytes = vec![0; len];
+    getrandom::fill(&mut bytes).map_err(|e| backup_error(format!("no randomness: {}", e)))?;
+    Ok(bytes)
+}
+
+fn read_tar(tar: &[u8]) -> Result<Vec<BackupEntry>, ConversionError> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    let mut headers = Vec::new();
+    let mut long_path = None;
+    while let Some(block) = tar.get(pos..pos + BLOCK_SIZE) {
+        if block.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = parse_size(&block[124..136])?;
+        let data_end = pos + BLOCK_SIZE + size;
+        let data = tar
+            .get(pos + BLOCK_SIZE..data_end)
+            .ok_or_else(|| backup_error("truncated tar entry"))?;
+        let next = pos + BLOCK_SIZE + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        match block[156] {
+            // Extension headers describing the entry that follows.
+            b'x' | b'g' | b'L' | b'K' => {
+                if block[156] == b'x' {
+                    long_path = pax_path(data).or(long_path);
+                } else if block[156] == b'L' {
+                    long_path = Some(c_string(data));
+                }
+                headers.extend_from_slice(&tar[pos..next.min(tar.len())]);
+            }
+            _ => {
+                headers.extend_from_slice(block);
+                let path = long_path.take().unwrap_or_else(|| {
+                    let name = c_string(&block[0..100]);
+                    let prefix = if &block[257..262] == b"ustar" {
+                        c_string(&block[345..500])
+                    } else {
+                        String::new()
+                    };
+                    if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{}/{}", prefix, name)
+                    }
+                });
+                entries.push(BackupEntry {
+                    path,
+                    data: data.to_vec(),
+                    headers: std::mem::take(&mut headers),
+                });
+            }
+        }
+        pos = next;
+    }
+    Ok(entries)
+}
+
+// Appends the entry, updating the size and checksum of its header when the
+// data changed length.
+fn write_entry(tar: &mut Vec<u8>, entry: &BackupEntry) {
+    let start = tar.len();
+    tar.extend_from_slice(&entry.headers);
+    let header = &mut tar[start + entry.headers.len() - BLOCK_SIZE..];
+    if parse_size(&header[124..136]).ok() != Some(entry.data.len()) {
+        header[124..136].copy_from_slice(format!("{:011o}\0", entry.data.len()).as_bytes());
+        header[148..156].fill(b' ');
+        let checksum: u32 = header[..BLOCK_SIZE].iter().map(|&b| b as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    }
+    tar.extend_from_slice(&entry.data);
+    tar.resize(tar.len().next_multiple_of(BLOCK_SIZE), 0);
+}
+
+// Octal, or big-endian binary when the high bit of the first byte is set.
+fn parse_size(field: &[u8]) -> Result<usize, ConversionError> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(0usize, |size, &b| (size << 8) | b as usize));
+    }
+    let text = c_string(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(text, 8).map_err(|_| backup_error(format!("bad tar size {:?}", text)))
+}
+
+// `path=` from pax records of the form "<length> <key>=<value>\n".
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data).lines().find_map(|record| {
+        let (_, pair) = record.split_once(' ')?;
+        pair.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
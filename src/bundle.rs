@@ -0,0 +1,394 @@
+//! `PersistableBundle` as serialized by `PersistableBundle.saveToXml`.
+//!
+//! A bundle has no element of its own: its entries are written as children
+//! of whatever element holds it (`<extras>` in `jobs.xml`), in the
+//! `XmlUtils` value layout. Nested bundles become `<pbundle_as_map>`
+//! elements and arrays carry a `num` attribute with one `<item>` per entry:
+//!
+//! ```xml
+//! <extras>
+//!     <string name="account">alice</string>
+//!     <int-array name="ids" num="2">
+//!         <item value="4" />
+//!         <item value="7" />
+//!     </int-array>
+//!     <pbundle_as_map name="retry">
+//!         <long name="backoff" value="30000" />
+//!     </pbundle_as_map>
+//! </extras>
+//! ```
+//!
+//! [`PersistableBundle::save_to_xml`] and [`PersistableBundle::restore_from_xml`]
+//! work on any [`TypedXmlSerializer`]/[`TypedXmlPullParser`], so bundles can
+//! be read from and written into larger documents. The bundle also
+//! implements [`AbxRead`]/[`AbxWrite`], so it can be a `#[abx(child)]` field
+//! of a derived type.
+//!
+//! ```rust
+//! use xml2abx::bundle::{BundleValue, PersistableBundle};
+//! use xml2abx::{AbxRead, AbxWrite};
+//!
+//! let mut extras = PersistableBundle::new();
+//! extras.insert("account", "alice");
+//! extras.insert("ids", BundleValue::IntArray(vec![4, 7]));
+//!
+//! let mut abx = Vec::new();
+//! extras.write_document(&mut abx).unwrap();
+//! let mut reloaded = PersistableBundle::read_document(&abx[..]).unwrap();
+//! assert_eq!(reloaded, extras);
+//! reloaded.insert("account", "bob");
+//! assert_eq!(reloaded.get("account"), Some(&BundleValue::String("bob".into())));
+//! ```
+
+use crate::codec::{AbxRead, AbxWrite};
+use crate::map_xml::{expect_tag, read_text};
+use crate::parser::TypedXmlPullParser;
+use crate::serializer::TypedXmlSerializer;
+use crate::{BinaryXmlSerializer, ConversionError};
+use std::io::Write;
+
+/// Tag of a bundle nested in another bundle.
+pub const TAG_PERSISTABLEMAP: &str = "pbundle_as_map";
+
+/// A value a `PersistableBundle` can hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BundleValue {
+    Null,
+    String(String),
+    Int(i32),
+    Long(i64),
+    Double(f64),
+    Boolean(bool),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+    DoubleArray(Vec<f64>),
+    BooleanArray(Vec<bool>),
+    /// `None` items are written as `<null />`.
+    StringArray(Vec<Option<String>>),
+    Bundle(PersistableBundle),
+}
+
+impl BundleValue {
+    /// Tag the value is written under.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::String(_) => "string",
+            Self::Int(_) => "int",
+            Self::Long(_) => "long",
+            Self::Double(_) => "double",
+            Self::Boolean(_) => "boolean",
+            Self::IntArray(_) => "int-array",
+            Self::LongArray(_) => "long-array",
+            Self::DoubleArray(_) => "double-array",
+            Self::BooleanArray(_) => "boolean-array",
+            Self::StringArray(_) => "string-array",
+            Self::Bundle(_) => TAG_PERSISTABLEMAP,
+        }
+    }
+}
+
+impl From<String> for BundleValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+impl From<&str> for BundleValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+impl From<i32> for BundleValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+impl From<i64> for BundleValue {
+    fn from(value: i64) -> Self {
+        Self::Long(value)
+    }
+}
+impl From<f64> for BundleValue {
+    fn from(value: f64) -> Self {
+        Self::Double(value)
+    }
+}
+impl From<bool> for BundleValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+impl From<PersistableBundle> for BundleValue {
+    fn from(value: PersistableBundle) -> Self {
+        Self::Bundle(value)
+    }
+}
+
+/// Bundle entries, kept in the order they were read or inserted so that a
+/// bundle read from a file is written back unchanged.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PersistableBundle {
+    entries: Vec<(String, BundleValue)>,
+}
+
+impl PersistableBundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BundleValue> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut BundleValue> {
+        self.entries
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Sets `key`, replacing an existing value in place or appending a new
+    /// entry. Returns the previous value.
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<BundleValue>,
+    ) -> Option<BundleValue> {
+        let key = key.into();
+        let value = value.into();
+        match self.get_mut(&key) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<BundleValue> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BundleValue)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(k, _)| k.as_str())
+    }
+
+    /// Writes the entries into the currently open element, like
+    /// `PersistableBundle.saveToXml`.
+    pub fn save_to_xml<S: TypedXmlSerializer + ?Sized>(
+        &self,
+        serializer: &mut S,
+    ) -> Result<(), ConversionError> {
+        for (key, value) in &self.entries {
+            write_value(serializer, value, key)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the entries of the element whose start tag the parser is
+    /// positioned on, like `PersistableBundle.restoreFromXml`. Leaves the
+    /// parser on its end tag.
+    pub fn restore_from_xml<P: TypedXmlPullParser + ?Sized>(
+        parser: &mut P,
+    ) -> Result<Self, ConversionError> {
+        let mut bundle = Self::new();
+        let depth = parser.depth();
+        while parser.next_element_within(depth)? {
+            let name = parser
+                .get_attribute_value("name")
+                .ok_or_else(|| ConversionError::MissingAttribute("name".to_string()))?;
+            let value = read_value(parser)?;
+            bundle.entries.push((name, value));
+        }
+        Ok(bundle)
+    }
+}
+
+impl<K: Into<String>, V: Into<BundleValue>> FromIterator<(K, V)> for PersistableBundle {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut bundle = Self::new();
+        for (key, value) in iter {
+            bundle.insert(key, value);
+        }
+        bundle
+    }
+}
+
+impl AbxWrite for PersistableBundle {
+    const TAG: &'static str = TAG_PERSISTABLEMAP;
+
+    fn write_element<W: Write>(
+        &self,
+        serializer: &mut BinaryXmlSerializer<W>,
+        tag: &str,
+    ) -> Result<(), ConversionError> {
+        serializer.start_tag(tag)?;
+        self.save_to_xml(serializer)?;
+        serializer.end_tag(tag)
+    }
+}
+
+impl AbxRead for PersistableBundle {
+    fn read_element<P: TypedXmlPullParser>(parser: &mut P) -> Result<Self, ConversionError> {
+        Self::restore_from_xml(parser)
+    }
+}
+
+fn write_value<S: TypedXmlSerializer + ?Sized>(
+    serializer: &mut S,
+    value: &BundleValue,
+    name: &str,
+) -> Result<(), ConversionError> {
+    let tag = value.tag();
+    serializer.start_tag(tag)?;
+    serializer.attribute("name", name)?;
+    match value {
+        BundleValue::Null => {}
+        BundleValue::String(text) => serializer.text(text)?,
+        BundleValue::Int(v) => serializer.attribute_int("value", *v)?,
+        BundleValue::Long(v) => serializer.attribute_long("value", *v)?,
+        BundleValue::Double(v) => serializer.attribute_double("value", *v)?,
+        BundleValue::Boolean(v) => serializer.attribute_boolean("value", *v)?,
+        BundleValue::IntArray(items) => {
+            write_items(serializer, items, |s, v| s.attribute_int("value", *v))?
+        }
+        BundleValue::LongArray(items) => {
+            write_items(serializer, items, |s, v| s.attribute_long("value", *v))?
+        }
+        BundleValue::DoubleArray(items) => {
+            write_items(serializer, items, |s, v| s.attribute_double("value", *v))?
+        }
+        BundleValue::BooleanArray(items) => {
+            write_items(serializer, items, |s, v| s.attribute_boolean("value", *v))?
+        }
+        BundleValue::StringArray(items) => {
+            serializer.attribute_int("num", array_len(items.len())?)?;
+            for item in items {
+                match item {
+                    Some(item) => {
+                        serializer.start_tag("item")?;
+                        serializer.attribute("value", item)?;
+                        serializer.end_tag("item")?;
+                    }
+                    None => {
+                        serializer.start_tag("null")?;
+                        serializer.end_tag("null")?;
+                    }
+                }
+            }
+        }
+        BundleValue::Bundle(bundle) => bundle.save_to_xml(serializer)?,
+    }
+    serializer.end_tag(tag)
+}
+
+// Writes the `num` attribute and one `<item>` per entry.
+fn write_items<S: TypedXmlSerializer + ?Sized, T>(
+    serializer: &mut S,
+    items: &[T],
+    mut write: impl FnMut(&mut S, &T) -> Result<(), ConversionError>,
+) -> Result<(), ConversionError> {
+    serializer.attribute_int("num", array_len(items.len())?)?;
+    for item in items {
+        serializer.start_tag("item")?;
+        write(serializer, item)?;
+        serializer.end_tag("item")?;
+    }
+    Ok(())
+}
+
+fn array_len(len: usize) -> Result<i32, ConversionError> {
+    i32::try_from(len).map_err(|_| ConversionError::InvalidValue("int", len.to_string()))
+}
+
+fn read_value<P: TypedXmlPullParser + ?Sized>(
+    parser: &mut P,
+) -> Result<BundleValue, ConversionError> {
+    let tag = parser.name().unwrap_or_default().to_string();
+    let value = match tag.as_str() {
+        "null" => BundleValue::Null,
+        "string" => return Ok(BundleValue::String(read_text(parser)?)),
+        "int" => BundleValue::Int(parser.get_attribute_int("value")?),
+        "long" => BundleValue::Long(parser.get_attribute_long("value")?),
+        "double" => BundleValue::Double(parser.get_attribute_double("value")?),
+        "boolean" => BundleValue::Boolean(parser.get_attribute_boolean("value")?),
+        "int-array" => {
+            return Ok(BundleValue::IntArray(read_items(parser, |p| {
+                p.get_attribute_int("value")
+            })?));
+        }
+        "long-array" => {
+            return Ok(BundleValue::LongArray(read_items(parser, |p| {
+                p.get_attribute_long("value")
+            })?));
+        }
+        "double-array" => {
+            return Ok(BundleValue::DoubleArray(read_items(parser, |p| {
+                p.get_attribute_double("value")
+            })?));
+        }
+        "boolean-array" => {
+            return Ok(BundleValue::BooleanArray(read_items(parser, |p| {
+                p.get_attribute_boolean("value")
+            })?));
+        }
+        "string-array" => {
+            let mut items = Vec::new();
+            let depth = parser.depth();
+            while parser.next_element_within(depth)? {
+                if parser.name() == Some("null") {
+                    items.push(None);
+                } else {
+                    expect_tag(parser, "item")?;
+                    items.push(Some(parser.get_attribute_value("value").ok_or_else(
+                        || ConversionError::MissingAttribute("value".to_string()),
+                    )?));
+                }
+                parser.skip_current_tag()?;
+            }
+            return Ok(BundleValue::StringArray(items));
+        }
+        TAG_PERSISTABLEMAP => {
+            return Ok(BundleValue::Bundle(PersistableBundle::restore_from_xml(
+                parser,
+            )?));
+        }
+        _ => {
+            return Err(ConversionError::Malformed(format!(
+                "unknown bundle value tag <{}>",
+                tag
+            )));
+        }
+    };
+    parser.skip_current_tag()?;
+    Ok(value)
+}
+
+// Reads the `<item>` children of an array element. `num` is not needed to
+// read them and is not checked.
+fn read_items<P: TypedXmlPullParser + ?Sized, T>(
+    parser: &mut P,
+    mut read: impl FnMut(&P) -> Result<T, ConversionError>,
+) -> Result<Vec<T>, ConversionError> {
+    let mut items = Vec::new();
+    let depth = parser.depth();
+    while parser.next_element_within(depth)? {
+        expect_tag(parser, "item")?;
+        items.push(read(parser)?);
+        parser.skip_current_tag()?;
+    }
+    Ok(items)
+}
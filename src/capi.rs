@@ -0,0 +1,554 @@
+//! C API (the `capi` feature).
+//!
+//! Build the library with
+//! `cargo rustc --profile capi --lib --features capi --crate-type cdylib` (or
+//! `staticlib`) and include `include/xml2abx.h`, which cbindgen generates
+//! from this module (`cbindgen --output include/xml2abx.h`).
+//!
+//! Every function returns an [`Xml2AbxError`]; on failure
+//! [`xml2abx_last_error_message`] describes the error. Strings are
+//! NUL-terminated UTF-8, and pointers must be valid for the duration of the
+//! call. Buffers returned through `out`/`out_len` are owned by the caller and
+//! released with [`xml2abx_free_buffer`]. A serializer handle must not be
+//! used from two threads at once.
+//!
+//! Panics are caught and reported as [`Xml2AbxError::Panic`]. This needs
+//! unwinding, which the `release` profile turns off; built with it, a panic
+//! aborts the process instead.
+
+// The pointer requirements are the same for every function and are
+// documented above.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::{BinaryXmlSerializer, ConversionError, XmlToAbxConverter};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Result codes, one per [`ConversionError`] variant.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Xml2AbxError {
+    Ok = 0,
+    XmlParsing = 1,
+    Io = 2,
+    StringTooLong = 3,
+    BinaryDataTooLong = 4,
+    InvalidHex = 5,
+    InvalidBase64 = 6,
+    Utf8 = 7,
+    Attribute = 8,
+    InvalidMagic = 9,
+    UnknownToken = 10,
+    InvalidInternedIndex = 11,
+    Malformed = 12,
+    InvalidSelector = 13,
+    UnknownType = 14,
+    InvalidValue = 15,
+    MissingAttribute = 16,
+    Json = 17,
+    Serde = 18,
+    Backup = 19,
+    /// A required pointer was NULL.
+    InvalidArgument = 100,
+    /// The library panicked; this is a bug.
+    Panic = 101,
+}
+
+impl From<&ConversionError> for Xml2AbxError {
+    fn from(error: &ConversionError) -> Self {
+        match error {
+            ConversionError::XmlParsing(_) => Xml2AbxError::XmlParsing,
+            ConversionError::Io(_) => Xml2AbxError::Io,
+            ConversionError::StringTooLong(..) => Xml2AbxError::StringTooLong,
+            ConversionError::BinaryDataTooLong(..) => Xml2AbxError::BinaryDataTooLong,
+            ConversionError::InvalidHex => Xml2AbxError::InvalidHex,
+            ConversionError::InvalidBase64 => Xml2AbxError::InvalidBase64,
+            ConversionError::Utf8Error(_) => Xml2AbxError::Utf8,
+            ConversionError::AttrError(_) => Xml2AbxError::Attribute,
+            ConversionError::InvalidMagic => Xml2AbxError::InvalidMagic,
+            ConversionError::UnknownToken(_) => Xml2AbxError::UnknownToken,
+            ConversionError::InvalidInternedIndex(_) => Xml2AbxError::InvalidInternedIndex,
+            ConversionError::Malformed(_) => Xml2AbxError::Malformed,
+            ConversionError::InvalidSelector(_) => Xml2AbxError::InvalidSelector,
+            ConversionError::UnknownType(_) => Xml2AbxError::UnknownType,
+            ConversionError::InvalidValue(..) => Xml2AbxError::InvalidValue,
+            ConversionError::MissingAttribute(_) => Xml2AbxError::MissingAttribute,
+            ConversionError::Json(_) => Xml2AbxError::Json,
+            #[cfg(feature = "serde")]
+            ConversionError::Serde(_) => Xml2AbxError::Serde,
+            #[cfg(feature = "backup")]
+            ConversionError::Backup(_) => Xml2AbxError::Backup,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+struct Failure(Xml2AbxError, String);
+
+impl From<ConversionError> for Failure {
+    fn from(error: ConversionError) -> Self {
+        Failure((&error).into(), error.to_string())
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Self {
+        ConversionError::from(error).into()
+    }
+}
+
+fn run(f: impl FnOnce() -> Result<(), Failure>) -> Xml2AbxError {
+    let Failure(code, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return Xml2AbxError::Ok,
+        Ok(Err(failure)) => failure,
+        Err(_) => Failure(Xml2AbxError::Panic, "internal panic".to_string()),
+    };
+    // Messages cannot contain NUL unless the input did; drop it then.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    code
+}
+
+fn null_argument(name: &str) -> Failure {
+    Failure(Xml2AbxError::InvalidArgument, format!("{} is NULL", name))
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(null_argument(name));
+    }
+    let bytes = unsafe { CStr::from_ptr(ptr) }.to_bytes();
+    Ok(std::str::from_utf8(bytes).map_err(ConversionError::from)?)
+}
+
+unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize, name: &str) -> Result<&'a [u8], Failure> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(null_argument(name));
+    }
+    Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+unsafe fn return_buffer(data: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) {
+    let data = data.into_boxed_slice();
+    unsafe {
+        *out_len = data.len();
+        *out = Box::into_raw(data) as *mut u8;
+    }
+}
+
+/// Returns the message of the last error on this thread, or NULL. The string
+/// stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn xml2abx_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Releases a buffer returned by this library.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_free_buffer(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
+    }
+}
+
+/// Converts `xml_len` bytes of XML into a newly allocated ABX buffer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_convert_buffer(
+    xml: *const c_char,
+    xml_len: usize,
+    preserve_whitespace: bool,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> Xml2AbxError {
+    run(|| unsafe {
+        if out.is_null() || out_len.is_null() {
+            return Err(null_argument("out"));
+        }
+        let xml = bytes_arg(xml as *const u8, xml_len, "xml")?;
+        let xml = std::str::from_utf8(xml).map_err(ConversionError::from)?;
+        let mut abx = Vec::new();
+        XmlToAbxConverter::convert_from_string_with_options(xml, &mut abx, preserve_whitespace)?;
+        return_buffer(abx, out, out_len);
+        Ok(())
+    })
+}
+
+/// Converts the XML file at `input_path` into an ABX file at `output_path`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_convert_file(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    preserve_whitespace: bool,
+) -> Xml2AbxError {
+    run(|| unsafe {
+        let input_path = str_arg(input_path, "input_path")?;
+        let output = File::create(str_arg(output_path, "output_path")?)?;
+        let mut writer = BufWriter::new(output);
+        XmlToAbxConverter::convert_from_file_with_options(
+            input_path,
+            &mut writer,
+            preserve_whitespace,
+        )?;
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+enum Sink {
+    Buffer(Vec<u8>),
+    File(BufWriter<File>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Buffer(data) => data.write(buf),
+            Sink::File(file) => file.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Buffer(_) => Ok(()),
+            Sink::File(file) => file.flush(),
+        }
+    }
+}
+
+/// A `BinaryXmlSerializer` writing to memory or to a file.
+pub struct Xml2AbxSerializer {
+    inner: BinaryXmlSerializer<Sink>,
+}
+
+/// Creates a serializer that collects the ABX in memory; retrieve it with
+/// [`xml2abx_serializer_finish`]. Never returns NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn xml2abx_serializer_new_buffer(
+    preserve_whitespace: bool,
+) -> *mut Xml2AbxSerializer {
+    let inner = BinaryXmlSerializer::with_options(Sink::Buffer(Vec::new()), preserve_whitespace)
+        .expect("writing to memory cannot fail");
+    Box::into_raw(Box::new(Xml2AbxSerializer { inner }))
+}
+
+/// Creates a serializer writing to the file at `path`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_new_file(
+    path: *const c_char,
+    preserve_whitespace: bool,
+    out: *mut *mut Xml2AbxSerializer,
+) -> Xml2AbxError {
+    run(|| unsafe {
+        if out.is_null() {
+            return Err(null_argument("out"));
+        }
+        let file = BufWriter::new(File::create(str_arg(path, "path")?)?);
+        let inner = BinaryXmlSerializer::with_options(Sink::File(file), preserve_whitespace)?;
+        *out = Box::into_raw(Box::new(Xml2AbxSerializer { inner }));
+        Ok(())
+    })
+}
+
+/// Flushes and releases the serializer. For an in-memory serializer the ABX
+/// is returned through `out`/`out_len` when they are not NULL. The handle is
+/// released even if flushing fails.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_finish(
+    serializer: *mut Xml2AbxSerializer,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> Xml2AbxError {
+    run(|| unsafe {
+        if serializer.is_null() {
+            return Err(null_argument("serializer"));
+        }
+        let mut serializer = Box::from_raw(serializer);
+        serializer.inner.flush()?;
+        if let Sink::Buffer(data) = serializer.inner.into_inner()
+            && !out.is_null()
+            && !out_len.is_null()
+        {
+            return_buffer(data, out, out_len);
+        }
+        Ok(())
+    })
+}
+
+/// Releases a serializer without flushing it.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_free(serializer: *mut Xml2AbxSerializer) {
+    if !serializer.is_null() {
+        drop(unsafe { Box::from_raw(serializer) });
+    }
+}
+
+unsafe fn with_serializer(
+    serializer: *mut Xml2AbxSerializer,
+    f: impl FnOnce(&mut BinaryXmlSerializer<Sink>) -> Result<(), Failure>,
+) -> Xml2AbxError {
+    run(|| match unsafe { serializer.as_mut() } {
+        Some(serializer) => f(&mut serializer.inner),
+        None => Err(null_argument("serializer")),
+    })
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_start_document(
+    serializer: *mut Xml2AbxSerializer,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.start_document()?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_end_document(
+    serializer: *mut Xml2AbxSerializer,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.end_document()?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_start_tag(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.start_tag(str_arg(name, "name")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_end_tag(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.end_tag(str_arg(name, "name")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: *const c_char,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute(str_arg(name, "name")?, str_arg(value, "value")?)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_interned(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: *const c_char,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_interned(str_arg(name, "name")?, str_arg(value, "value")?)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_text(
+    serializer: *mut Xml2AbxSerializer,
+    text: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.text(str_arg(text, "text")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_cdsect(
+    serializer: *mut Xml2AbxSerializer,
+    text: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.cdsect(str_arg(text, "text")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_comment(
+    serializer: *mut Xml2AbxSerializer,
+    text: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.comment(str_arg(text, "text")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_docdecl(
+    serializer: *mut Xml2AbxSerializer,
+    text: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.docdecl(str_arg(text, "text")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_ignorable_whitespace(
+    serializer: *mut Xml2AbxSerializer,
+    text: *const c_char,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.ignorable_whitespace(str_arg(text, "text")?)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_entity_ref(
+    serializer: *mut Xml2AbxSerializer,
+    text: *const c_char,
+) -> Xml2AbxError {
+    unsafe { with_serializer(serializer, |s| Ok(s.entity_ref(str_arg(text, "text")?)?)) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_int(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: i32,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_int(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_int_hex(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: i32,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_int_hex(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_long(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: i64,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_long(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_long_hex(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: i64,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_long_hex(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_float(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: f32,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_float(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_double(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: f64,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_double(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_boolean(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: bool,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_boolean(str_arg(name, "name")?, value)?)
+        })
+    }
+}
+
+/// `data` may be NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_processing_instruction(
+    serializer: *mut Xml2AbxSerializer,
+    target: *const c_char,
+    data: *const c_char,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            let data = if data.is_null() {
+                None
+            } else {
+                Some(str_arg(data, "data")?)
+            };
+            Ok(s.processing_instruction(str_arg(target, "target")?, data)?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_bytes_hex(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: *const u8,
+    value_len: usize,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_bytes_hex(
+                str_arg(name, "name")?,
+                bytes_arg(value, value_len, "value")?,
+            )?)
+        })
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xml2abx_serializer_attribute_bytes_base64(
+    serializer: *mut Xml2AbxSerializer,
+    name: *const c_char,
+    value: *const u8,
+    value_len: usize,
+) -> Xml2AbxError {
+    unsafe {
+        with_serializer(serializer, |s| {
+            Ok(s.attribute_bytes_base64(
+                str_arg(name, "name")?,
+                bytes_arg(value, value_len, "value")?,
+            )?)
+        })
+    }
+}
@@ -0,0 +1,280 @@
+//! Recovery of ABX documents from raw disk images and memory dumps.
+//!
+//! [`Carver`] scans a byte stream for `PROTOCOL_MAGIC_VERSION_0` and parses
+//! forward from every hit until `END_DOCUMENT`, a structural error or the end
+//! of the input. Hits not followed by `START_DOCUMENT` are ignored. A document
+//! that stops early is returned up to its last complete event, so
+//! [`CarvedDocument::closed`] can turn it into one the reader accepts.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use xml2abx::carve::Carver;
+//!
+//! for document in Carver::new(File::open("userdata.img")?) {
+//!     let document = document?;
+//!     println!(
+//!         "{:#x} {} bytes {}",
+//!         document.offset,
+//!         document.data.len(),
+//!         if document.complete { "complete" } else { "truncated" },
+//!     );
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::reader::{AbxEvent, AbxReader};
+use crate::{BinaryXmlSerializer, ConversionError, FastDataOutput};
+use std::io::{self, Read};
+
+type S = BinaryXmlSerializer<Vec<u8>>;
+
+const MAGIC: [u8; 4] = S::PROTOCOL_MAGIC_VERSION_0;
+const CHUNK_SIZE: usize = 8 << 20;
+
+/// An ABX document found in a byte stream.
+#[derive(Debug)]
+pub struct CarvedDocument {
+    /// Position of the magic header in the stream.
+    pub offset: u64,
+    /// The document from its magic header up to `END_DOCUMENT`, or up to the
+    /// last complete event when truncated.
+    pub data: Vec<u8>,
+    /// Whether the document ends with `END_DOCUMENT` and every tag is closed.
+    pub complete: bool,
+    /// Why parsing stopped early, or `None` when the document is complete or
+    /// the input ran out.
+    pub error: Option<ConversionError>,
+    /// Name of the root element, if the document got that far.
+    pub root: Option<String>,
+    open_tags: Vec<String>,
+}
+
+impl CarvedDocument {
+    /// Names of the tags still open where the document stops, outermost
+    /// first. Empty for complete documents.
+    pub fn open_tags(&self) -> &[String] {
+        &self.open_tags
+    }
+
+    /// The document with its open tags and the document itself closed, so it
+    /// can be parsed. Complete documents are returned unchanged.
+    pub fn closed(&self) -> Vec<u8> {
+        let mut data = self.data.clone();
+        if self.complete {
+            return data;
+        }
+        // Writing to a Vec cannot fail, and tag names that were read back
+        // fit a short length.
+        let mut output = FastDataOutput::new(&mut data);
+        for name in self.open_tags.iter().rev() {
+            output
+                .write_byte(S::END_TAG | S::TYPE_STRING_INTERNED)
+                .and_then(|()| output.write_interned_utf(name))
+                .expect("closing tag fits");
+        }
+        output
+            .write_byte(S::END_DOCUMENT | S::TYPE_NULL)
+            .expect("writing to a Vec");
+        data
+    }
+}
+
+/// Finds ABX documents in a stream, reading it in chunks so images larger
+/// than memory can be scanned. Overlapping hits inside a document that was
+/// returned are skipped.
+pub struct Carver<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    // Stream offset of `buffer[0]`.
+    base: u64,
+    // Where in `buffer` to look for the next magic header.
+    scan: usize,
+    eof: bool,
+}
+
+impl<R: Read> Carver<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+            base: 0,
+            scan: 0,
+            eof: false,
+        }
+    }
+
+    fn next_document(&mut self) -> io::Result<Option<CarvedDocument>> {
+        loop {
+            let Some(found) = find_magic(&self.buffer[self.scan..]) else {
+                if self.eof {
+                    return Ok(None);
+                }
+                // Keep a partial magic header at the end of the buffer.
+                self.scan = self
+                    .scan
+                    .max(self.buffer.len().saturating_sub(MAGIC.len() - 1));
+                self.fill(CHUNK_SIZE)?;
+                continue;
+            };
+            self.scan += found;
+            let candidate = &self.buffer[self.scan..];
+            if candidate.len() <= MAGIC.len() && !self.eof {
+                self.fill(CHUNK_SIZE)?;
+                continue;
+            }
+            let Some(mut parsed) = parse(candidate) else {
+                self.scan += 1;
+                continue;
+            };
+            if parsed.ran_out && !self.eof {
+                // Grow geometrically so a large document is parsed a
+                // logarithmic number of times.
+                self.fill(candidate.len().max(CHUNK_SIZE))?;
+                continue;
+            }
+            // A truncated document that runs into another magic header most
+            // likely swallowed the start of the next document; stop at it.
+            if !parsed.complete
+                && let Some(next) = find_magic(&candidate[MAGIC.len()..parsed.len])
+            {
+                let next = MAGIC.len() + next;
+                parsed = parse(&candidate[..next]).expect("already parsed");
+                parsed.error = Some(ConversionError::Malformed(format!(
+                    "next document starts at {:#x}",
+                    self.base + (self.scan + next) as u64
+                )));
+            }
+            let document = CarvedDocument {
+                offset: self.base + self.scan as u64,
+                data: candidate[..parsed.len].to_vec(),
+                complete: parsed.complete,
+                error: parsed.error,
+                root: parsed.root,
+                open_tags: parsed.open_tags,
+            };
+            self.scan += parsed.len;
+            return Ok(Some(document));
+        }
+    }
+
+    // Drops the scanned part of the buffer and reads at least `additional`
+    // more bytes, or up to the end of the stream.
+    fn fill(&mut self, additional: usize) -> io::Result<()> {
+        self.buffer.drain(..self.scan);
+        self.base += self.scan as u64;
+        self.scan = 0;
+        let target = self.buffer.len() + additional;
+        while self.buffer.len() < target {
+            let read = (&mut self.reader)
+                .take((target - self.buffer.len()) as u64)
+                .read_to_end(&mut self.buffer)?;
+            if read == 0 {
+                self.eof = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Carver<R> {
+    type Item = io::Result<CarvedDocument>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_document().transpose()
+    }
+}
+
+/// Every ABX document in `data`.
+pub fn carve(data: &[u8]) -> Vec<CarvedDocument> {
+    // Reading a slice cannot fail.
+    Carver::new(data).filter_map(Result::ok).collect()
+}
+
+fn find_magic(data: &[u8]) -> Option<usize> {
+    data.windows(MAGIC.len()).position(|window| window == MAGIC)
+}
+
+struct Parsed {
+    len: usize,
+    complete: bool,
+    error: Option<ConversionError>,
+    root: Option<String>,
+    open_tags: Vec<String>,
+    // Whether parsing stopped at the end of `data` rather than on an error
+    // or `END_DOCUMENT`, so more input could change the outcome.
+    ran_out: bool,
+}
+
+// Parses the document starting at the magic header at the start of `data`,
+// or returns `None` when the header is not followed by `START_DOCUMENT`.
+fn parse(data: &[u8]) -> Option<Parsed> {
+    if data.get(MAGIC.len()) != Some(&(S::START_DOCUMENT | S::TYPE_NULL)) {
+        return None;
+    }
+    let mut reader = AbxReader::new(data).ok()?;
+    let mut parsed = Parsed {
+        // End of the last complete event.
+        len: MAGIC.len(),
+        complete: false,
+        error: None,
+        root: None,
+        open_tags: Vec::new(),
+        ran_out: false,
+    };
+    let mut started = false;
+    loop {
+        let event = match reader.next_event() {
+            Ok(Some(event)) => event,
+            // The reader stops after END_DOCUMENT, which returns below.
+            Ok(None) => unreachable!("event after END_DOCUMENT"),
+            // Input ending inside a token or before END_DOCUMENT.
+            Err(ConversionError::Io(_) | ConversionError::Malformed(_))
+                if reader.get_ref().is_empty() =>
+            {
+                parsed.ran_out = true;
+                return Some(parsed);
+            }
+            Err(e) => {
+                parsed.error = Some(e);
+                return Some(parsed);
+            }
+        };
+        let token = data[parsed.len];
+        let open_tags = &mut parsed.open_tags;
+        let structural = match &event {
+            AbxEvent::StartDocument if started => Some("START_DOCUMENT inside the document".into()),
+            AbxEvent::StartTag { name, .. } => {
+                parsed.root.get_or_insert_with(|| name.clone());
+                open_tags.push(name.clone());
+                None
+            }
+            AbxEvent::EndTag(name) => match open_tags.last() {
+                Some(open) if open == name => {
+                    open_tags.pop();
+                    None
+                }
+                Some(open) => Some(format!("</{}> closes <{}>", name, open)),
+                None => Some(format!("</{}> without an open tag", name)),
+            },
+            AbxEvent::EndDocument if token != S::END_DOCUMENT | S::TYPE_NULL => {
+                Some(format!("END_DOCUMENT token {:#04x}", token))
+            }
+            AbxEvent::EndDocument if !open_tags.is_empty() => Some(format!(
+                "END_DOCUMENT with <{}> open",
+                open_tags.last().unwrap()
+            )),
+            _ => None,
+        };
+        if let Some(message) = structural {
+            parsed.error = Some(ConversionError::Malformed(message));
+            return Some(parsed);
+        }
+        started = true;
+        parsed.len = data.len() - reader.get_ref().len() - reader.read_ahead();
+        if event == AbxEvent::EndDocument {
+            parsed.complete = true;
+            return Some(parsed);
+        }
+    }
+}
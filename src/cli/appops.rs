@@ -0,0 +1,131 @@
+use super::{CliResult, read_input, write_atomically};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+use xml2abx::AbxDocument;
+use xml2abx::appops::{self, AppOpsFile, OpMode};
+
+pub fn command() -> Command {
+    let file = || {
+        Arg::new("file")
+            .help("appops.xml, ABX or XML")
+            .required(true)
+    };
+    Command::new("appops")
+        .about("List and set app-op modes in appops.xml")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("Print the modes set per uid and per package")
+                .arg(file())
+                .arg(Arg::new("package").help("Only list this package")),
+        )
+        .subcommand(
+            Command::new("set")
+                .about("Set the mode of an op for a package")
+                .arg(file())
+                .arg(Arg::new("package").required(true))
+                .arg(
+                    Arg::new("op")
+                        .required(true)
+                        .value_parser(clap::value_parser!(i32))
+                        .help("Op code (AppOpsManager.OP_*)"),
+                )
+                .arg(
+                    Arg::new("mode")
+                        .required(true)
+                        .help("allow, ignore, deny, default, foreground or a number"),
+                )
+                .arg(
+                    Arg::new("uid")
+                        .long("uid")
+                        .value_parser(clap::value_parser!(i32))
+                        .help("Package uid, for files grouping packages by uid"),
+                )
+                .arg(
+                    Arg::new("user")
+                        .long("user")
+                        .value_parser(clap::value_parser!(i32))
+                        .default_value("0")
+                        .help("User id, for files grouping packages by user"),
+                )
+                .arg(
+                    Arg::new("keep-version")
+                        .long("keep-version")
+                        .help("Leave the file's version unchanged")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Write the result here instead of over <file>"),
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let (action, sub) = matches.subcommand().unwrap();
+    let path = sub.get_one::<String>("file").unwrap();
+    let data = read_input(path)?;
+    let document = AbxDocument::from_bytes(&data)?;
+    // Write back in the input's format, indented if the input was.
+    let is_abx = AbxDocument::is_abx(&data);
+    let pretty = document.has_formatting_whitespace();
+    let mut file = AppOpsFile::from_document(document)?;
+    let package = sub.get_one::<String>("package");
+
+    if action == "list" {
+        if package.is_none() {
+            for uid in file.uids()? {
+                println!("uid {}", uid.uid);
+                print_ops(&uid.ops);
+            }
+        }
+        for entry in file.packages()? {
+            if package.is_some_and(|p| *p != entry.package) {
+                continue;
+            }
+            match (entry.uid, entry.user) {
+                (Some(uid), _) => println!("pkg {} uid {}", entry.package, uid),
+                (_, Some(user)) => println!("pkg {} user {}", entry.package, user),
+                _ => println!("pkg {}", entry.package),
+            }
+            print_ops(&entry.ops);
+        }
+        return Ok(());
+    }
+
+    let mode = appops::parse_mode(sub.get_one::<String>("mode").unwrap())?;
+    file.set_package_mode(
+        package.unwrap(),
+        *sub.get_one::<i32>("op").unwrap(),
+        mode,
+        sub.get_one::<i32>("uid").copied(),
+        *sub.get_one::<i32>("user").unwrap(),
+    )?;
+    if !sub.get_flag("keep-version") {
+        file.bump_version()?;
+    }
+
+    let output = sub.get_one::<String>("output").unwrap_or(path);
+    write_atomically(Path::new(output), |writer| {
+        if is_abx {
+            file.write_abx(writer)?;
+        } else {
+            file.write_xml(writer, pretty)?;
+        }
+        Ok(())
+    })
+}
+
+fn print_ops(ops: &[OpMode]) {
+    for op in ops {
+        match op.mode {
+            Some(mode) => match appops::mode_name(mode) {
+                Some(name) => println!("  op {} {}", op.op, name),
+                None => println!("  op {} {}", op.op, mode),
+            },
+            None => println!("  op {} default", op.op),
+        }
+    }
+}
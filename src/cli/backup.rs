@@ -0,0 +1,203 @@
+use super::{CliResult, read_input, write_atomically};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::{Component, Path, PathBuf};
+use xml2abx::AbxDocument;
+use xml2abx::backup::{AndroidBackup, BackupEntry, EntryFormat};
+
+pub fn command() -> Command {
+    let archive = || {
+        Arg::new("archive")
+            .help("Android backup (.ab) file (use '-' for stdin)")
+            .required(true)
+    };
+    let password = || {
+        Arg::new("password")
+            .long("password")
+            .short('p')
+            .help("Password of an encrypted backup")
+    };
+    let to = || {
+        Arg::new("to")
+            .long("to")
+            .value_parser(["abx", "xml"])
+            .help("Convert entries to this format (default: keep each entry's format)")
+    };
+    let entries = || {
+        Arg::new("entry")
+            .num_args(0..)
+            .help("Only these entries (default: every XML/ABX entry)")
+    };
+    Command::new("backup")
+        .about("List, extract and convert XML/ABX entries of adb backup archives")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("Print the format, size and path of XML/ABX entries")
+                .arg(archive())
+                .arg(password())
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .short('a')
+                        .help("List every entry")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("extract")
+                .about("Write XML/ABX entries under a directory, optionally converted")
+                .arg(archive())
+                .arg(Arg::new("dir").required(true).help("Output directory"))
+                .arg(entries())
+                .arg(password())
+                .arg(to())
+                .arg(
+                    Arg::new("pretty")
+                        .long("pretty")
+                        .help("Indent XML written from ABX entries")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("repack")
+                .about("Write a copy of the archive with XML/ABX entries converted or replaced")
+                .arg(archive())
+                .arg(Arg::new("output").required(true).help("Output .ab file"))
+                .arg(entries())
+                .arg(password())
+                .arg(to())
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .help("Take entry contents from files under this directory, e.g. one written by extract"),
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let (action, sub) = matches.subcommand().unwrap();
+    let password = sub.get_one::<String>("password").map(String::as_str);
+    let mut backup = AndroidBackup::from_bytes(
+        &read_input(sub.get_one::<String>("archive").unwrap())?,
+        password,
+    )?;
+
+    if action == "list" {
+        let all = sub.get_flag("all");
+        for entry in &backup.entries {
+            let format = match entry.format() {
+                Some(EntryFormat::Abx) => "abx",
+                Some(EntryFormat::Xml) => "xml",
+                None if all => "-",
+                None => continue,
+            };
+            println!("{:<4} {:>9}  {}", format, entry.data.len(), entry.path);
+        }
+        return Ok(());
+    }
+
+    let to = sub.get_one::<String>("to").map(|to| match to.as_str() {
+        "abx" => EntryFormat::Abx,
+        _ => EntryFormat::Xml,
+    });
+    let selected: Vec<&String> = sub
+        .get_many::<String>("entry")
+        .unwrap_or_default()
+        .collect();
+    for path in &selected {
+        if backup.entry(path).is_none() {
+            return Err(format!("no entry {} in the archive", path).into());
+        }
+    }
+    let wanted = |entry: &BackupEntry| {
+        entry.format().is_some() && (selected.is_empty() || selected.contains(&&entry.path))
+    };
+
+    if action == "extract" {
+        let dir = Path::new(sub.get_one::<String>("dir").unwrap());
+        let pretty = sub.get_flag("pretty");
+        for entry in backup.entries.iter().filter(|e| wanted(e)) {
+            let format = to.or(entry.format()).unwrap();
+            let data = convert(&entry.data, format, None, pretty)?;
+            let path = entry_path(dir, &entry.path)?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, data)?;
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    let from = sub.get_one::<String>("from").map(Path::new);
+    let mut replaced = 0;
+    for entry in backup.entries.iter_mut().filter(|e| wanted(e)) {
+        let original_format = entry.format().unwrap();
+        let source = match from.map(|dir| entry_path(dir, &entry.path)).transpose()? {
+            Some(path) if path.exists() => Some(std::fs::read(path)?),
+            _ => None,
+        };
+        let data = match &source {
+            Some(source) => convert(
+                source,
+                to.unwrap_or(original_format),
+                Some(&entry.data),
+                false,
+            )?,
+            None => convert(&entry.data, to.unwrap_or(original_format), None, false)?,
+        };
+        if data != entry.data {
+            entry.data = data;
+            replaced += 1;
+        }
+    }
+    let output = sub.get_one::<String>("output").unwrap();
+    let encrypt_with = if backup.encrypted { password } else { None };
+    write_atomically(Path::new(output), |writer| {
+        backup.write(writer, encrypt_with)?;
+        Ok(())
+    })?;
+    eprintln!("Replaced {} entries", replaced);
+    Ok(())
+}
+
+// Converts `data` to `format`, leaving entries already in it untouched.
+// Text replacing an `original` entry keeps the original's attribute types
+// and, if it had none, its lack of indentation.
+fn convert(
+    data: &[u8],
+    format: EntryFormat,
+    original: Option<&[u8]>,
+    pretty: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let is_abx = AbxDocument::is_abx(data);
+    if is_abx == (format == EntryFormat::Abx) && original.is_none_or(|o| o == data) {
+        return Ok(data.to_vec());
+    }
+    let mut document = AbxDocument::from_bytes(data)?;
+    if let Some(original) = original.filter(|o| !is_abx && *o != data) {
+        let original = AbxDocument::from_bytes(original)?;
+        if !original.has_formatting_whitespace() {
+            document.strip_whitespace();
+        }
+        document.restore_types(&original)?;
+    }
+    let mut out = Vec::new();
+    match format {
+        EntryFormat::Abx => document.write_abx(&mut out)?,
+        EntryFormat::Xml => document.write_xml(&mut out, pretty)?,
+    }
+    Ok(out)
+}
+
+// `dir` joined with an archive path, refusing paths that would leave it.
+fn entry_path(dir: &Path, path: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!("refusing to use unsafe entry path {}", path).into());
+    }
+    Ok(dir.join(relative))
+}
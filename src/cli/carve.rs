@@ -0,0 +1,94 @@
+use super::CliResult;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use xml2abx::carve::Carver;
+
+pub fn command() -> Command {
+    Command::new("carve")
+        .about("Find ABX documents in a raw disk image or memory dump")
+        .arg(
+            Arg::new("image")
+                .required(true)
+                .help("Image or dump to scan (use '-' for stdin)"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .help("Write each document to <offset>.abx under this directory"),
+        )
+        .arg(
+            Arg::new("complete-only")
+                .long("complete-only")
+                .help("Skip truncated documents")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("close")
+                .long("close")
+                .help("Close the open tags of truncated documents written with -o")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let image = matches.get_one::<String>("image").unwrap();
+    let input: Box<dyn Read> = if image == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(image)?))
+    };
+    let dir = matches.get_one::<String>("output").map(Path::new);
+    if let Some(dir) = dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let complete_only = matches.get_flag("complete-only");
+    let close = matches.get_flag("close");
+
+    let (mut complete, mut truncated) = (0, 0);
+    for document in Carver::new(input) {
+        let document = document?;
+        if !document.complete && complete_only {
+            continue;
+        }
+        let root = document.root.as_deref().unwrap_or("-");
+        if document.complete {
+            complete += 1;
+            println!(
+                "{:#014x} {:>9}  complete   {}",
+                document.offset,
+                document.data.len(),
+                root
+            );
+        } else {
+            truncated += 1;
+            let reason = match (&document.error, document.open_tags().last()) {
+                (Some(error), _) => error.to_string(),
+                (None, Some(open)) => format!("input ends inside <{}>", open),
+                (None, None) => "input ends".to_string(),
+            };
+            println!(
+                "{:#014x} {:>9}  truncated  {} ({})",
+                document.offset,
+                document.data.len(),
+                root,
+                reason
+            );
+        }
+        if let Some(dir) = dir {
+            let data = if close {
+                document.closed()
+            } else {
+                document.data
+            };
+            std::fs::write(dir.join(format!("{:012x}.abx", document.offset)), data)?;
+        }
+    }
+    eprintln!(
+        "Found {} complete and {} truncated documents",
+        complete, truncated
+    );
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use super::{CliResult, load_document};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use xml2abx::diff::{self, DiffOptions, KeyAttribute};
+
+pub fn command() -> Command {
+    Command::new("diff")
+        .about("Structurally compare two ABX or XML documents")
+        .arg(Arg::new("old").help("Original document").required(true))
+        .arg(Arg::new("new").help("Changed document").required(true))
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .short('k')
+                .value_name("[ELEMENT=]ATTR")
+                .help("Match elements by this attribute, e.g. 'package=name'")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('f')
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::new("strict-types")
+                .long("strict-types")
+                .help("Treat interned and plain strings as different types")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("exit-code")
+                .long("exit-code")
+                .help("Exit with status 1 when the documents differ")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let old = load_document(matches.get_one::<String>("old").unwrap())?;
+    let new = load_document(matches.get_one::<String>("new").unwrap())?;
+    let options = DiffOptions {
+        keys: matches
+            .get_many::<String>("key")
+            .unwrap_or_default()
+            .map(|spec| KeyAttribute::parse(spec))
+            .collect(),
+        strict_types: matches.get_flag("strict-types"),
+    };
+
+    let changes = diff::diff(&old, &new, &options);
+    if matches.get_one::<String>("format").unwrap() == "json" {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff::to_json(&changes))?
+        );
+    } else {
+        print!("{}", diff::render_text(&changes));
+    }
+
+    if matches.get_flag("exit-code") && !changes.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use super::{CliResult, read_input, write_atomically};
+use clap::{Arg, ArgMatches, Command};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use xml2abx::{AbxDocument, ConversionError};
+
+pub fn command() -> Command {
+    Command::new("edit")
+        .about("Edit an ABX file as XML in $EDITOR, keeping attribute types")
+        .arg(
+            Arg::new("file")
+                .help("ABX or XML file to edit")
+                .required(true),
+        )
+}
+
+fn editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string())
+}
+
+fn launch_editor(path: &Path) -> CliResult {
+    let editor = editor();
+    // Run through the shell like git does, so EDITOR may carry arguments.
+    let status = if cfg!(windows) {
+        process::Command::new("cmd")
+            .arg("/C")
+            .arg(&editor)
+            .arg(path)
+            .status()?
+    } else {
+        process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", editor))
+            .arg(&editor)
+            .arg(path)
+            .status()?
+    };
+    if !status.success() {
+        return Err(format!("editor '{}' exited with {}", editor, status).into());
+    }
+    Ok(())
+}
+
+// Creates `<stem>.xml` holding `contents` in a fresh directory only the
+// current user can enter. Both are created exclusively, so nothing planted in
+// a shared temp directory is followed or reused.
+fn create_temp_file(file: &Path, contents: &str) -> io::Result<PathBuf> {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let mut attempt = 0u32;
+    let dir = loop {
+        let dir = std::env::temp_dir().join(format!(
+            "xml2abx-edit-{}-{:08x}",
+            process::id(),
+            nanos.wrapping_add(attempt)
+        ));
+        match builder.create(&dir) {
+            Ok(()) => break dir,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    let path = dir.join(format!("{}.xml", stem));
+    let mut tmp = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    tmp.write_all(contents.as_bytes())?;
+    Ok(path)
+}
+
+// Removes the directory made by `create_temp_file`, along with anything the
+// editor left next to the file, such as swap or backup files.
+fn remove_temp_file(path: &Path) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let file = matches.get_one::<String>("file").unwrap();
+    let path = Path::new(file);
+    let data = read_input(file)?;
+    let is_abx = AbxDocument::is_abx(&data);
+    let original = AbxDocument::from_bytes(&data)?;
+
+    // Files written by the platform carry no indentation, which would leave
+    // the whole document on one line; edit an indented view instead and drop
+    // the added whitespace again afterwards.
+    let pretty = !original.has_formatting_whitespace();
+    let xml = original.to_xml_string(pretty);
+    let tmp = create_temp_file(path, &xml)?;
+
+    if let Err(e) = launch_editor(&tmp) {
+        remove_temp_file(&tmp);
+        return Err(e);
+    }
+    let edited = fs::read_to_string(&tmp)?;
+    if edited == xml {
+        remove_temp_file(&tmp);
+        eprintln!("No changes.");
+        return Ok(());
+    }
+
+    let parsed = AbxDocument::from_xml_str(&edited).and_then(|mut document| {
+        if document.root().is_none() {
+            return Err(ConversionError::Malformed("no root element".into()));
+        }
+        if pretty {
+            document.strip_whitespace();
+        }
+        document.restore_types(&original)?;
+        Ok(document)
+    });
+    let document = match parsed {
+        Ok(document) => document,
+        Err(e) => {
+            return Err(format!(
+                "{}; {} was not modified, your edits are kept in {}",
+                e,
+                path.display(),
+                tmp.display()
+            )
+            .into());
+        }
+    };
+
+    write_atomically(path, |writer| {
+        if is_abx {
+            document.write_abx(writer)?;
+        } else {
+            document.write_xml(writer, false)?;
+        }
+        Ok(())
+    })?;
+    remove_temp_file(&tmp);
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use super::{CliResult, create_output, read_input};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io::Write;
+use xml2abx::json;
+
+pub fn command() -> Command {
+    Command::new("from-json")
+        .about("Convert the JSON representation written by to-json back to ABX")
+        .arg(
+            Arg::new("input")
+                .help("Input JSON file (use '-' for stdin)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("output")
+                .help("Output file (use '-' for stdout)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("xml")
+                .long("xml")
+                .help("Write text XML instead of ABX")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let data = read_input(matches.get_one::<String>("input").unwrap())?;
+    let document = json::from_json_str(std::str::from_utf8(&data)?)?;
+    let mut output = create_output(matches.get_one::<String>("output").unwrap())?;
+    if matches.get_flag("xml") {
+        document.write_xml(&mut output, false)?;
+    } else {
+        document.write_abx(&mut output)?;
+    }
+    output.flush()?;
+    Ok(())
+}
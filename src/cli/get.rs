@@ -0,0 +1,55 @@
+use super::{CliResult, load_document};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use xml2abx::Selector;
+use xml2abx::select::{QueryMatch, Target};
+
+pub fn command() -> Command {
+    Command::new("get")
+        .about("Print attribute values or elements addressed by a selector")
+        .arg(
+            Arg::new("file")
+                .help("ABX or XML file (use '-' for stdin)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("selector")
+                .help("e.g. /packages/package[@name='com.foo']/@flags")
+                .required(true),
+        )
+        .arg(
+            Arg::new("type")
+                .long("type")
+                .short('t')
+                .help("Prefix each attribute value with its ABX type")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let document = load_document(matches.get_one::<String>("file").unwrap())?;
+    let selector: Selector = matches.get_one::<String>("selector").unwrap().parse()?;
+    let with_type = matches.get_flag("type");
+
+    // `@*` prints each attribute as name=value.
+    let all_attributes = selector.target == Target::AllAttributes;
+    let matches = document.query(&selector);
+    for m in &matches {
+        match m {
+            QueryMatch::Attribute { attribute, .. } => {
+                if with_type {
+                    print!("{}\t", attribute.value.type_name());
+                }
+                if all_attributes {
+                    print!("{}=", attribute.name);
+                }
+                println!("{}", attribute.value);
+            }
+            QueryMatch::Element { element, .. } => println!("{}", element.to_xml_string(true)),
+            QueryMatch::Text { text, .. } => println!("{}", text),
+        }
+    }
+    if matches.is_empty() {
+        return Err("selector matched nothing".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use super::{CliResult, read_input, write_atomically};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+use xml2abx::AbxDocument;
+use xml2abx::diff::KeyAttribute;
+use xml2abx::merge;
+
+pub fn command() -> Command {
+    Command::new("merge-driver")
+        .about("Three-way structural merge of ABX or XML files (for git merge drivers)")
+        .arg(Arg::new("base").help("Common ancestor (%O)").required(true))
+        .arg(
+            Arg::new("ours")
+                .help("Our version (%A); receives the result")
+                .required(true),
+        )
+        .arg(Arg::new("theirs").help("Their version (%B)").required(true))
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .help("Write the result here instead of over <ours>"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .short('k')
+                .value_name("[ELEMENT=]ATTR")
+                .help("Match elements by this attribute, e.g. 'package=name'")
+                .action(ArgAction::Append),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let ours_path = matches.get_one::<String>("ours").unwrap();
+    let ours_data = read_input(ours_path)?;
+    let base = AbxDocument::from_bytes(&read_input(matches.get_one::<String>("base").unwrap())?)?;
+    let ours = AbxDocument::from_bytes(&ours_data)?;
+    let theirs =
+        AbxDocument::from_bytes(&read_input(matches.get_one::<String>("theirs").unwrap())?)?;
+    let keys: Vec<KeyAttribute> = matches
+        .get_many::<String>("key")
+        .unwrap_or_default()
+        .map(|spec| KeyAttribute::parse(spec))
+        .collect();
+
+    let result = merge::merge(&base, &ours, &theirs, &keys);
+
+    // Keep the format of our side, so XML stays XML and ABX stays ABX.
+    let output_path = matches.get_one::<String>("output").unwrap_or(ours_path);
+    write_atomically(Path::new(output_path), |writer| {
+        if AbxDocument::is_abx(&ours_data) {
+            result.document.write_abx(writer)?;
+        } else {
+            result.document.write_xml(writer, false)?;
+        }
+        Ok(())
+    })?;
+
+    if !result.conflicts.is_empty() {
+        for conflict in &result.conflicts {
+            eprintln!("CONFLICT: {}", conflict);
+        }
+        std::process::exit(1);
+    }
+    Ok(())
+}
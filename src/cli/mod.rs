@@ -0,0 +1,134 @@
+pub mod appops;
+#[cfg(feature = "backup")]
+pub mod backup;
+pub mod carve;
+pub mod diff;
+pub mod edit;
+pub mod from_json;
+pub mod get;
+pub mod merge_driver;
+pub mod packages;
+pub mod permissions;
+pub mod query;
+pub mod set;
+pub mod settings;
+pub mod textconv;
+pub mod to_json;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use xml2abx::atomic_file::inherit_metadata;
+use xml2abx::{AbxDocument, AtomicFile, ConversionError};
+
+pub type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+static ATOMIC_FILE: AtomicBool = AtomicBool::new(false);
+
+/// Makes the functions below read and write files the way the platform's
+/// `AtomicFile` and `ResilientAtomicFile` do (`--atomic-file`). Off by
+/// default: those pick companions by name alone, so an unrelated
+/// `notes-backup.xml` or `notes.xml.bak` would be read in place of
+/// `notes.xml` and renamed or deleted on write.
+pub fn use_atomic_file(enabled: bool) {
+    ATOMIC_FILE.store(enabled, Ordering::Relaxed);
+}
+
+fn atomic_file(path: impl AsRef<Path>) -> Option<AtomicFile> {
+    ATOMIC_FILE
+        .load(Ordering::Relaxed)
+        .then(|| AtomicFile::detect(path.as_ref()))
+}
+
+/// Reads a file, or stdin when `path` is `-`. With `--atomic-file`, a file
+/// kept by `AtomicFile` is read from whichever companion the platform would
+/// pick.
+pub fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else if let Some(file) = atomic_file(path) {
+        let (source, data) = file.read()?;
+        note_source(path, &source);
+        Ok(data)
+    } else {
+        std::fs::read(path)
+    }
+}
+
+/// Opens a file for writing, or stdout when `path` is `-`.
+pub fn create_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" {
+        Ok(Box::new(BufWriter::new(io::stdout().lock())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Loads an ABX or XML document, detected by its header. With
+/// `--atomic-file`, falls back to the next companion file when one does not
+/// parse or has no root element, like `ResilientAtomicFile`.
+pub fn load_document(path: &str) -> Result<AbxDocument, Box<dyn std::error::Error>> {
+    let Some(file) = atomic_file(path).filter(|_| path != "-") else {
+        return Ok(AbxDocument::from_bytes(&read_input(path)?)?);
+    };
+    let (source, document) = file.read_with(|data| {
+        let document = AbxDocument::from_bytes(data)?;
+        match document.root() {
+            Some(_) => Ok(document),
+            None => Err(ConversionError::Malformed(format!(
+                "{} has no root element",
+                path
+            ))),
+        }
+    })?;
+    note_source(path, &source);
+    Ok(document)
+}
+
+/// Replaces `path` by writing a new sibling temporary file and renaming it
+/// over the original, so readers never observe a partially written file.
+/// The new file keeps the mode and owner of the one it replaces.
+/// With `--atomic-file`, writes the way `AtomicFile` (or
+/// `ResilientAtomicFile`, when its companion files are present) does
+/// instead.
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<&File>) -> CliResult,
+) -> CliResult {
+    if let Some(file) = atomic_file(path) {
+        return file.write(write);
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".tmp-{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    // Never truncate a file that happens to have the temporary name.
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        inherit_metadata(&file, &metadata);
+    }
+    let result = (|| {
+        let mut writer = BufWriter::new(&file);
+        write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn note_source(path: &str, source: &Path) {
+    if source != Path::new(path) {
+        eprintln!("Reading {} in place of {}", source.display(), path);
+    }
+}
@@ -0,0 +1,57 @@
+use super::{CliResult, load_document};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use xml2abx::packages;
+
+pub fn command() -> Command {
+    Command::new("packages")
+        .about("Report installed packages from packages.xml")
+        .arg(
+            Arg::new("file")
+                .help("packages.xml, ABX or XML (use '-' for stdin)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('f')
+                .value_parser(["table", "json"])
+                .default_value("table")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::new("package")
+                .long("package")
+                .short('p')
+                .help("Only report this package (repeatable)")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .help("List permissions and signing references under each package")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let document = load_document(matches.get_one::<String>("file").unwrap())?;
+    let mut report = packages::read_packages(&document)?;
+    if let Some(names) = matches.get_many::<String>("package") {
+        let names: Vec<&String> = names.collect();
+        report.packages.retain(|p| names.contains(&&p.name));
+    }
+
+    if matches.get_one::<String>("format").unwrap() == "json" {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&packages::to_json(&report))?
+        );
+    } else {
+        print!(
+            "{}",
+            packages::render_text(&report, matches.get_flag("verbose"))
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use super::{CliResult, read_input, write_atomically};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+use xml2abx::AbxDocument;
+use xml2abx::runtime_permissions::{Owner, RuntimePermissionsFile};
+
+pub fn command() -> Command {
+    let file = || {
+        Arg::new("file")
+            .help("runtime-permissions.xml, ABX or XML")
+            .required(true)
+    };
+    let shared_user = || {
+        Arg::new("shared-user")
+            .long("shared-user")
+            .help("<name> is a shared user rather than a package")
+            .action(ArgAction::SetTrue)
+    };
+    let change = |name: &'static str, about: &'static str| {
+        Command::new(name)
+            .about(about)
+            .arg(file())
+            .arg(Arg::new("name").required(true).help("Package name"))
+            .arg(
+                Arg::new("permission")
+                    .required(true)
+                    .num_args(1..)
+                    .help("Permission names"),
+            )
+            .arg(shared_user())
+            .arg(
+                Arg::new("flags")
+                    .long("flags")
+                    .value_parser(parse_flags)
+                    .help("Replace the permission flags (hex, e.g. 300)"),
+            )
+            .arg(
+                Arg::new("keep-version")
+                    .long("keep-version")
+                    .help("Leave the file's version unchanged")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .help("Write the result here instead of over <file>"),
+            )
+    };
+    Command::new("permissions")
+        .about("List, grant and revoke runtime permissions in runtime-permissions.xml")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("Print each package's permissions as name granted flags")
+                .arg(file())
+                .arg(Arg::new("name").help("Only list this package"))
+                .arg(shared_user()),
+        )
+        .subcommand(change("grant", "Grant permissions, adding them if missing"))
+        .subcommand(change(
+            "revoke",
+            "Revoke permissions, adding them if missing",
+        ))
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let (action, sub) = matches.subcommand().unwrap();
+    let path = sub.get_one::<String>("file").unwrap();
+    let data = read_input(path)?;
+    let document = AbxDocument::from_bytes(&data)?;
+    // Write back in the input's format, indented if the input was.
+    let is_abx = AbxDocument::is_abx(&data);
+    let pretty = document.has_formatting_whitespace();
+    let mut file = RuntimePermissionsFile::from_document(document)?;
+    let owner = if sub.get_flag("shared-user") {
+        Owner::SharedUser
+    } else {
+        Owner::Package
+    };
+    let name = sub.get_one::<String>("name");
+
+    if action == "list" {
+        for entry in file.entries()? {
+            if name.is_some_and(|n| *n != entry.name || entry.owner != owner) {
+                continue;
+            }
+            let label = match entry.owner {
+                Owner::Package => "package",
+                Owner::SharedUser => "shared-user",
+            };
+            println!("{} {}", label, entry.name);
+            for permission in &entry.permissions {
+                println!(
+                    "  {} {} {:x}",
+                    permission.name,
+                    if permission.granted {
+                        "granted"
+                    } else {
+                        "denied"
+                    },
+                    permission.flags
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let name = name.unwrap();
+    let flags = sub.get_one::<i32>("flags").copied();
+    for permission in sub.get_many::<String>("permission").unwrap() {
+        file.set_granted(owner, name, permission, action == "grant", flags)?;
+    }
+    if !sub.get_flag("keep-version") {
+        file.bump_version()?;
+    }
+
+    let output = sub.get_one::<String>("output").unwrap_or(path);
+    write_atomically(Path::new(output), |writer| {
+        if is_abx {
+            file.write_abx(writer)?;
+        } else {
+            file.write_xml(writer, pretty)?;
+        }
+        Ok(())
+    })
+}
+
+fn parse_flags(text: &str) -> Result<i32, String> {
+    let digits = text.trim_start_matches("0x");
+    u32::from_str_radix(digits, 16)
+        .map(|flags| flags as i32)
+        .map_err(|_| format!("not a hex number: {}", text))
+}
@@ -0,0 +1,149 @@
+use super::{CliResult, load_document};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde_json::{Map, Value, json};
+use xml2abx::Selector;
+use xml2abx::select::QueryMatch;
+use xml2abx::serializer::{escape_attribute, escape_text};
+
+pub fn command() -> Command {
+    Command::new("query")
+        .about("Evaluate a selector against ABX or XML files and print the matches")
+        .arg(
+            Arg::new("expression")
+                .help("e.g. //package[@isOrphaned='true']/@name")
+                .required(true),
+        )
+        .arg(
+            Arg::new("files")
+                .help("ABX or XML files (use '-' for stdin)")
+                .required(true)
+                .num_args(1..),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('f')
+                .value_parser(["xml", "value", "json"])
+                .default_value("value")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::new("with-filename")
+                .long("with-filename")
+                .short('H')
+                .help("Prefix matches with the file name (default with several files)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("files-with-matches")
+                .long("files-with-matches")
+                .short('l')
+                .help("Only print the names of files with matches")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn match_json(file: &str, m: &QueryMatch) -> Value {
+    match m {
+        QueryMatch::Element { path, element } => {
+            let attributes: Map<String, Value> = element
+                .attributes
+                .iter()
+                .map(|a| {
+                    let value =
+                        json!({ "type": a.value.type_name(), "value": a.value.to_string() });
+                    (a.name.clone(), value)
+                })
+                .collect();
+            json!({
+                "file": file,
+                "path": path,
+                "kind": "element",
+                "name": element.name,
+                "attributes": attributes,
+                "text": element.text(),
+            })
+        }
+        QueryMatch::Attribute { path, attribute } => json!({
+            "file": file,
+            "path": path,
+            "kind": "attribute",
+            "name": attribute.name,
+            "type": attribute.value.type_name(),
+            "value": attribute.value.to_string(),
+        }),
+        QueryMatch::Text { path, text } => json!({
+            "file": file,
+            "path": path,
+            "kind": "text",
+            "value": text,
+        }),
+    }
+}
+
+fn match_text(m: &QueryMatch, xml: bool) -> String {
+    match m {
+        QueryMatch::Element { element, .. } if xml => element.to_xml_string(true),
+        QueryMatch::Element { path, .. } => path.clone(),
+        QueryMatch::Attribute { attribute, .. } if xml => format!(
+            "{}=\"{}\"",
+            attribute.name,
+            escape_attribute(&attribute.value.to_string())
+        ),
+        QueryMatch::Attribute { attribute, .. } => attribute.value.to_string(),
+        QueryMatch::Text { text, .. } if xml => escape_text(text),
+        QueryMatch::Text { text, .. } => text.clone(),
+    }
+}
+
+/// Exits like grep: 0 if anything matched, 1 if nothing did, 2 on errors.
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let selector: Selector = matches.get_one::<String>("expression").unwrap().parse()?;
+    let files: Vec<&String> = matches.get_many::<String>("files").unwrap().collect();
+    let format = matches.get_one::<String>("format").unwrap().as_str();
+    let with_filename = matches.get_flag("with-filename") || files.len() > 1;
+    let files_only = matches.get_flag("files-with-matches");
+
+    let mut any_match = false;
+    let mut had_error = false;
+    let mut json_matches = Vec::new();
+    for file in files {
+        let document = match load_document(file) {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                had_error = true;
+                continue;
+            }
+        };
+        let results = document.query(&selector);
+        if results.is_empty() {
+            continue;
+        }
+        any_match = true;
+        if files_only {
+            println!("{}", file);
+            continue;
+        }
+        for m in &results {
+            if format == "json" {
+                json_matches.push(match_json(file, m));
+            } else if with_filename {
+                println!("{}:{}", file, match_text(m, format == "xml"));
+            } else {
+                println!("{}", match_text(m, format == "xml"));
+            }
+        }
+    }
+    if format == "json" && !files_only {
+        println!("{}", serde_json::to_string_pretty(&json_matches)?);
+    }
+
+    if had_error {
+        std::process::exit(2);
+    }
+    if !any_match {
+        std::process::exit(1);
+    }
+    Ok(())
+}
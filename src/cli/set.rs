@@ -0,0 +1,75 @@
+use super::{CliResult, read_input, write_atomically};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+use xml2abx::select::Target;
+use xml2abx::{AbxDocument, AttributeValue, Selector};
+
+pub fn command() -> Command {
+    Command::new("set")
+        .about("Set an attribute addressed by a selector, keeping the rest of the file intact")
+        .arg(Arg::new("file").help("ABX or XML file").required(true))
+        .arg(
+            Arg::new("selector")
+                .help("e.g. /packages/package[@name='com.foo']/@flags")
+                .required(true),
+        )
+        .arg(Arg::new("value").help("New value").required(true))
+        .arg(
+            Arg::new("type")
+                .long("type")
+                .short('t')
+                .value_parser(AttributeValue::TYPE_NAMES)
+                .help("ABX type to store (default: keep the existing type)"),
+        )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .help("Allow the selector to match more than one element")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .help("Write the result here instead of over <file>"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let path = matches.get_one::<String>("file").unwrap();
+    let data = read_input(path)?;
+    let mut document = AbxDocument::from_bytes(&data)?;
+    let selector: Selector = matches.get_one::<String>("selector").unwrap().parse()?;
+    match selector.target {
+        Target::Attribute(_) => {}
+        Target::AllAttributes => return Err("cannot set @*; name the attribute to set".into()),
+        _ => return Err("selector must address an attribute (end in /@name)".into()),
+    }
+
+    let count = document.select(&selector).len();
+    if count == 0 {
+        return Err("selector matched nothing".into());
+    }
+    if count > 1 && !matches.get_flag("all") {
+        return Err(format!(
+            "selector matched {} elements; pass --all to set them all",
+            count
+        )
+        .into());
+    }
+    document.set_attribute_value(
+        &selector,
+        matches.get_one::<String>("value").unwrap(),
+        matches.get_one::<String>("type").map(String::as_str),
+    )?;
+
+    let output = matches.get_one::<String>("output").unwrap_or(path);
+    write_atomically(Path::new(output), |writer| {
+        if AbxDocument::is_abx(&data) {
+            document.write_abx(writer)?;
+        } else {
+            document.write_xml(writer, false)?;
+        }
+        Ok(())
+    })
+}
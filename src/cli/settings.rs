@@ -0,0 +1,161 @@
+use super::{CliResult, load_document, write_atomically};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+use xml2abx::settings::{Setting, SettingsFile};
+
+/// Package recorded for settings put from the command line, as `adb shell
+/// settings put` does.
+const SHELL_PACKAGE: &str = "com.android.shell";
+
+pub fn command() -> Command {
+    let file = || {
+        Arg::new("file")
+            .help("settings_*.xml file, ABX or XML")
+            .required(true)
+    };
+    let output = || {
+        Arg::new("output")
+            .long("output")
+            .short('o')
+            .help("Write the result here instead of over <file>")
+    };
+    Command::new("settings")
+        .about("List, get, put and delete entries of settings-provider files")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("Print every setting as name=value")
+                .arg(file()),
+        )
+        .subcommand(
+            Command::new("get")
+                .about("Print the value of a setting")
+                .arg(file())
+                .arg(Arg::new("name").required(true)),
+        )
+        .subcommand(
+            Command::new("put")
+                .about("Insert or update a setting, giving it the next free id")
+                .arg(file())
+                .arg(Arg::new("name").required(true))
+                .arg(Arg::new("value").required(true))
+                .arg(
+                    Arg::new("package")
+                        .long("package")
+                        .short('p')
+                        .default_value(SHELL_PACKAGE)
+                        .help("Package recorded as the setting's writer"),
+                )
+                .arg(
+                    Arg::new("default")
+                        .long("default")
+                        .help("Default value (kept from the existing setting if omitted)"),
+                )
+                .arg(
+                    Arg::new("default-sys-set")
+                        .long("default-sys-set")
+                        .help("Mark the default as set by the system")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .long("tag")
+                        .help("Tag stored with the default"),
+                )
+                .arg(
+                    Arg::new("preserve-in-restore")
+                        .long("preserve-in-restore")
+                        .help("Keep the value when a backup is restored")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(output()),
+        )
+        .subcommand(
+            Command::new("delete")
+                .about("Remove a setting")
+                .arg(file())
+                .arg(Arg::new("name").required(true))
+                .arg(output()),
+        )
+        .subcommand(
+            Command::new("version")
+                .about("Print the file's version, or set it")
+                .arg(file())
+                .arg(
+                    Arg::new("version")
+                        .value_parser(clap::value_parser!(i32))
+                        .help("New version (the settings provider upgrades from it on boot)"),
+                )
+                .arg(output()),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let (action, sub) = matches.subcommand().unwrap();
+    let path = sub.get_one::<String>("file").unwrap();
+    let mut file = SettingsFile::from_document(load_document(path)?)?;
+    let name = || sub.get_one::<String>("name").unwrap();
+
+    match action {
+        "list" => {
+            for setting in file.settings()? {
+                println!("{}={}", setting.name, display(setting.value.as_deref()));
+            }
+            return Ok(());
+        }
+        "get" => {
+            let setting = file
+                .get(name())?
+                .ok_or_else(|| format!("no setting named {}", name()))?;
+            println!("{}", display(setting.value.as_deref()));
+            return Ok(());
+        }
+        "put" => {
+            let mut setting = file
+                .get(name())?
+                .unwrap_or_else(|| Setting::new(name(), None, SHELL_PACKAGE));
+            setting.value = sub.get_one::<String>("value").cloned();
+            setting.package = sub.get_one::<String>("package").unwrap().clone();
+            if let Some(default) = sub.get_one::<String>("default") {
+                setting.default_value = Some(default.clone());
+            }
+            if sub.get_flag("default-sys-set") {
+                setting.default_sys_set = true;
+            }
+            if let Some(tag) = sub.get_one::<String>("tag") {
+                setting.tag = Some(tag.clone());
+            }
+            if sub.get_flag("preserve-in-restore") {
+                setting.preserve_in_restore = true;
+            }
+            file.put(setting)?;
+        }
+        "delete" => {
+            if file.delete(name())?.is_none() {
+                return Err(format!("no setting named {}", name()).into());
+            }
+        }
+        "version" => match sub.get_one::<i32>("version") {
+            Some(&version) => file.set_version(version),
+            None => {
+                match file.version()? {
+                    Some(version) => println!("{}", version),
+                    None => return Err("file has no version attribute".into()),
+                }
+                return Ok(());
+            }
+        },
+        _ => unreachable!(),
+    }
+
+    let output = sub.get_one::<String>("output").unwrap_or(path);
+    write_atomically(Path::new(output), |writer| {
+        file.write_abx(writer)?;
+        Ok(())
+    })
+}
+
+// `settings get` prints a missing value as "null".
+fn display(value: Option<&str>) -> &str {
+    value.unwrap_or("null")
+}
@@ -0,0 +1,27 @@
+use super::{CliResult, load_document};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io::{self, Write};
+
+pub fn command() -> Command {
+    Command::new("textconv")
+        .about("Print a stable XML rendering of an ABX or XML file (for git diff)")
+        .arg(
+            Arg::new("input")
+                .help("Input file (use '-' for stdin)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("annotate-types")
+                .long("annotate-types")
+                .help("Add name:abx-type attributes where the type cannot be inferred")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let document = load_document(matches.get_one::<String>("input").unwrap())?;
+    let mut stdout = io::stdout().lock();
+    document.write_xml_with_options(&mut stdout, true, matches.get_flag("annotate-types"))?;
+    stdout.flush()?;
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use super::{CliResult, create_output, load_document};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io::Write;
+use xml2abx::json;
+
+pub fn command() -> Command {
+    Command::new("to-json")
+        .about("Convert an ABX or XML file to the lossless JSON representation")
+        .arg(
+            Arg::new("input")
+                .help("Input file (use '-' for stdin)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("output")
+                .help("Output JSON file (default: stdout)")
+                .default_value("-"),
+        )
+        .arg(
+            Arg::new("compact")
+                .long("compact")
+                .help("Write the JSON on a single line")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> CliResult {
+    let document = load_document(matches.get_one::<String>("input").unwrap())?;
+    let mut output = create_output(matches.get_one::<String>("output").unwrap())?;
+    writeln!(
+        output,
+        "{}",
+        json::to_json_string(&document, !matches.get_flag("compact"))
+    )?;
+    output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! Traits for types that map to a single ABX element.
+//!
+//! They are usually derived with `#[derive(AbxWrite, AbxRead)]` (the
+//! `derive` feature), but can be implemented by hand for anything that needs
+//! custom handling.
+//!
+#![cfg_attr(feature = "derive", doc = "```rust")]
+#![cfg_attr(not(feature = "derive"), doc = "```rust,ignore")]
+//! use xml2abx::{AbxDocument, AbxRead, AbxWrite, AttributeValue};
+//!
+//! #[derive(AbxWrite, AbxRead, Debug, PartialEq)]
+//! #[abx(tag = "package")]
+//! struct Package {
+//!     #[abx(attr, interned)]
+//!     name: String,
+//!     #[abx(attr, int_hex)]
+//!     flags: u32,
+//!     #[abx(attr, long_hex, rename = "ft")]
+//!     first_install_time: u64,
+//!     #[abx(attr, bytes_base64)]
+//!     signature: Option<Vec<u8>>,
+//!     #[abx(child, rename = "perm")]
+//!     permissions: Vec<Permission>,
+//!     #[abx(child)]
+//!     installer: Option<Installer>,
+//!     #[abx(skip)]
+//!     cached_size: u64,
+//! }
+//!
+//! #[derive(AbxWrite, AbxRead, Debug, PartialEq)]
+//! #[abx(tag = "perm")]
+//! struct Permission {
+//!     #[abx(attr)]
+//!     name: String,
+//!     #[abx(attr)]
+//!     granted: bool,
+//! }
+//!
+//! #[derive(AbxWrite, AbxRead, Debug, PartialEq)]
+//! #[abx(tag = "installer")]
+//! struct Installer {
+//!     #[abx(text)]
+//!     package: String,
+//! }
+//!
+//! let package = Package {
+//!     name: "com.foo".into(),
+//!     flags: 0x1c,
+//!     first_install_time: 0x18c5a3f1e20,
+//!     signature: Some(b"hello".to_vec()),
+//!     permissions: vec![Permission {
+//!         name: "android.permission.INTERNET".into(),
+//!         granted: true,
+//!     }],
+//!     installer: Some(Installer {
+//!         package: "com.android.vending".into(),
+//!     }),
+//!     cached_size: 42,
+//! };
+//! let mut abx = Vec::new();
+//! package.write_document(&mut abx)?;
+//!
+//! let document = AbxDocument::from_abx(&abx[..])?;
+//! let root = document.root().unwrap();
+//! assert_eq!(root.attribute("name"), Some(&AttributeValue::Interned("com.foo".into())));
+//! assert_eq!(root.attribute("flags"), Some(&AttributeValue::IntHex(0x1c)));
+//! assert_eq!(root.attribute("ft"), Some(&AttributeValue::LongHex(0x18c5a3f1e20)));
+//! assert_eq!(root.attribute("signature"), Some(&AttributeValue::BytesBase64(b"hello".to_vec())));
+//! assert_eq!(root.attribute("cached_size"), None);
+//! assert_eq!(root.child("installer").unwrap().text(), "com.android.vending");
+//!
+//! let read = Package::read_document(&abx[..])?;
+//! assert_eq!(read, Package { cached_size: 0, ..package });
+//!
+//! let bare = Package {
+//!     signature: None,
+//!     permissions: Vec::new(),
+//!     installer: None,
+//!     ..read
+//! };
+//! let mut abx = Vec::new();
+//! bare.write_document(&mut abx)?;
+//! assert_eq!(Package::read_document(&abx[..])?, bare);
+//! # Ok::<(), xml2abx::ConversionError>(())
+//! ```
+//!
+//! Field attributes:
+//! - `attr` (the default) stores the field as an attribute. The encoding
+//!   follows the Rust type (see [`AttributeField`]) unless one of `string`,
+//!   `interned`, `bytes_hex`, `bytes_base64`, `int`, `int_hex`, `long`,
+//!   `long_hex`, `float`, `double` or `boolean` is given. `Option` fields are
+//!   omitted when `None`.
+//! - `child` stores the field as a child element; the type must implement
+//!   [`AbxWrite`]/[`AbxRead`]. `Vec` fields repeat the element and `Option`
+//!   fields may omit it.
+//! - `text` stores the field as the element's text.
+//! - `skip` leaves the field out; it is filled with `Default::default()` when
+//!   reading.
+//! - `rename = "..."` sets the attribute or child tag name, which otherwise is
+//!   the field name.
+
+use crate::parser::{AbxPullParser, TypedXmlPullParser};
+use crate::reader::AbxEvent;
+use crate::value::AttributeValue;
+use crate::{BinaryXmlSerializer, ConversionError};
+use std::io::{Read, Write};
+
+pub trait AbxWrite {
+    /// Tag used for the root element by [`Self::write_document`].
+    const TAG: &'static str;
+
+    /// Writes `self` as an element named `tag`.
+    fn write_element<W: Write>(
+        &self,
+        serializer: &mut BinaryXmlSerializer<W>,
+        tag: &str,
+    ) -> Result<(), ConversionError>;
+
+    /// Writes a complete ABX document with `self` as the root element.
+    fn write_document<W: Write>(&self, writer: W) -> Result<(), ConversionError> {
+        let mut serializer = BinaryXmlSerializer::new(writer)?;
+        serializer.start_document()?;
+        self.write_element(&mut serializer, Self::TAG)?;
+        serializer.end_document()
+    }
+}
+
+pub trait AbxRead: Sized {
+    /// Reads the element whose start tag the parser is positioned on,
+    /// leaving the parser on its end tag.
+    fn read_element<P: TypedXmlPullParser>(parser: &mut P) -> Result<Self, ConversionError>;
+
+    /// Reads an ABX document whose root element is `Self`.
+    fn read_document<R: Read>(reader: R) -> Result<Self, ConversionError> {
+        let mut parser = AbxPullParser::new(reader)?;
+        loop {
+            match parser.next()? {
+                Some(AbxEvent::StartTag { .. }) => return Self::read_element(&mut parser),
+                None | Some(AbxEvent::EndDocument) => {
+                    return Err(ConversionError::Malformed(
+                        "document has no root element".to_string(),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// A value stored in a single attribute, with its default encoding.
+pub trait AttributeField: Sized {
+    fn to_value(&self) -> AttributeValue;
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError>;
+}
+
+impl AttributeField for String {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::String(self.clone())
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        Ok(value.to_string())
+    }
+}
+
+impl AttributeField for bool {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::Bool(*self)
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        value.as_bool()
+    }
+}
+
+macro_rules! int_field {
+    ($($ty:ty),*) => {$(
+        impl AttributeField for $ty {
+            fn to_value(&self) -> AttributeValue {
+                AttributeValue::Int(*self as i32)
+            }
+            fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+                let v = value.as_int()?;
+                <$ty>::try_from(v)
+                    .map_err(|_| ConversionError::InvalidValue(stringify!($ty), v.to_string()))
+            }
+        }
+    )*};
+}
+
+int_field!(i8, i16, i32, u8, u16);
+
+impl AttributeField for i64 {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::Long(*self)
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        value.as_long()
+    }
+}
+
+impl AttributeField for u32 {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::Long(*self as i64)
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        match value {
+            AttributeValue::Int(v) | AttributeValue::IntHex(v) => Ok(*v as u32),
+            _ => {
+                let v = value.as_long()?;
+                u32::try_from(v).map_err(|_| ConversionError::InvalidValue("u32", v.to_string()))
+            }
+        }
+    }
+}
+
+/// Stored as the bits of a Java `long`, the way the platform keeps unsigned
+/// values.
+impl AttributeField for u64 {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::Long(*self as i64)
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        Ok(value.as_long()? as u64)
+    }
+}
+
+impl AttributeField for f32 {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::Float(*self)
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        value.as_float()
+    }
+}
+
+impl AttributeField for f64 {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::Double(*self)
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        value.as_double()
+    }
+}
+
+impl AttributeField for Vec<u8> {
+    fn to_value(&self) -> AttributeValue {
+        AttributeValue::BytesBase64(self.clone())
+    }
+    fn from_value(value: &AttributeValue) -> Result<Self, ConversionError> {
+        value.as_bytes_base64()
+    }
+}
+
+/// Walks the content of the element the parser is positioned on. `on_child`
+/// is called at each direct child start tag and returns whether it consumed
+/// the child (up to its end tag); unconsumed children are skipped. Returns
+/// the element's direct text.
+pub fn read_children<P, F>(parser: &mut P, mut on_child: F) -> Result<String, ConversionError>
+where
+    P: TypedXmlPullParser,
+    F: FnMut(&mut P, &str) -> Result<bool, ConversionError>,
+{
+    let depth = parser.depth();
+    let mut text = String::new();
+    loop {
+        let (child, is_end) = match parser.next()? {
+            None | Some(AbxEvent::EndDocument) => {
+                return Err(ConversionError::Malformed(
+                    "unexpected end of document".to_string(),
+                ));
+            }
+            Some(AbxEvent::EndTag(_)) => (None, true),
+            Some(AbxEvent::Text(t)) => {
+                text.push_str(t);
+                (None, false)
+            }
+            Some(AbxEvent::StartTag { name, .. }) => (Some(name.clone()), false),
+            Some(_) => (None, false),
+        };
+        if is_end && parser.depth() == depth {
+            return Ok(text);
+        }
+        let Some(name) = child else {
+            continue;
+        };
+        if !on_child(parser, &name)? {
+            parser.skip_current_tag()?;
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! Serde deserialization from ABX or text XML.
+//!
+//! Uses the field conventions described in [`crate::ser`]: `@name` fields
+//! read attributes, `$text` reads the element text and other fields read
+//! child elements (all of them for sequences, otherwise the first). Typed
+//! ABX attributes are returned directly; text values are parsed the same way
+//! [`crate::TypedXmlPullParser`] does, so both formats deserialize alike.
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element};
+use crate::value::AttributeValue;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use std::io::Read;
+
+/// Deserializes a value from an ABX or text XML document.
+pub fn from_reader<R: Read, T: DeserializeOwned>(mut reader: R) -> Result<T, ConversionError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let document = AbxDocument::from_bytes(&data)?;
+    let root = document
+        .root()
+        .ok_or_else(|| ConversionError::Malformed("document has no root element".to_string()))?;
+    T::deserialize(ElementDeserializer(root))
+}
+
+impl de::Error for ConversionError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        ConversionError::Serde(msg.to_string())
+    }
+}
+
+/// Deserializes a single attribute value or element text.
+struct ValueDeserializer(AttributeValue);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = ConversionError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.0 {
+            AttributeValue::String(v) | AttributeValue::Interned(v) => visitor.visit_string(v),
+            AttributeValue::BytesHex(v) | AttributeValue::BytesBase64(v) => {
+                visitor.visit_byte_buf(v)
+            }
+            AttributeValue::Int(v) | AttributeValue::IntHex(v) => visitor.visit_i32(v),
+            AttributeValue::Long(v) | AttributeValue::LongHex(v) => visitor.visit_i64(v),
+            AttributeValue::Float(v) => visitor.visit_f32(v),
+            AttributeValue::Double(v) => visitor.visit_f64(v),
+            AttributeValue::Bool(v) => visitor.visit_bool(v),
+        }
+    }
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_bool(self.0.as_bool()?)
+    }
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i32(self.0.as_int()?)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i32(self.0.as_int()?)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i32(self.0.as_int()?)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i64(self.0.as_long()?)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i32(self.0.as_int()?)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i32(self.0.as_int()?)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i64(self.0.as_long()?)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_i64(self.0.as_long()?)
+    }
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_f32(self.0.as_float()?)
+    }
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_f64(self.0.as_double()?)
+    }
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_string(self.0.to_string())
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_string(self.0.to_string())
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_string(self.0.to_string())
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_byte_buf(self.0.as_bytes_base64()?)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_byte_buf(self.0.as_bytes_base64()?)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_some(self)
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_enum(self.0.to_string().into_deserializer())
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_string(self.0.to_string())
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        seq tuple tuple_struct map struct
+    }
+}
+
+/// Deserializes an element: structs and maps from its attributes, children
+/// and text, anything else from its text.
+struct ElementDeserializer<'a>(&'a Element);
+
+impl<'a> ElementDeserializer<'a> {
+    fn text(&self) -> ValueDeserializer {
+        ValueDeserializer(AttributeValue::String(self.0.text()))
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ElementDeserializer<'a> {
+    type Error = ConversionError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        if self.0.attributes.is_empty() && self.0.elements().next().is_none() {
+            self.text().deserialize_any(visitor)
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_map(ElementAccess::new(self.0, false))
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_map(ElementAccess::new(self.0, fields.contains(&"$text")))
+    }
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_seq(ElementsAccess(vec![self.0].into_iter()))
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_some(self)
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_enum(name, variants, visitor)
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+
+    // Scalars come from the element text.
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_bool(visitor)
+    }
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_i8(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_i16(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_i32(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_i64(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_u8(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_u16(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_u32(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_u64(visitor)
+    }
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_f32(visitor)
+    }
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_f64(visitor)
+    }
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_char(visitor)
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_str(visitor)
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_string(visitor)
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_bytes(visitor)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_byte_buf(visitor)
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.text().deserialize_identifier(visitor)
+    }
+}
+
+/// Deserializes all same-named children of a field: sequences take every
+/// element, anything else takes the first.
+struct ChildrenDeserializer<'a>(Vec<&'a Element>);
+
+// Hands the remaining `deserialize_*` calls to the first child element.
+macro_rules! forward_to_first {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+                ElementDeserializer(self.0[0]).$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ChildrenDeserializer<'a> {
+    type Error = ConversionError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        ElementDeserializer(self.0[0]).deserialize_any(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_seq(ElementsAccess(self.0.into_iter()))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_some(self)
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        ElementDeserializer(self.0[0]).deserialize_struct(name, fields, visitor)
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        ElementDeserializer(self.0[0]).deserialize_enum(name, variants, visitor)
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_first! {
+        deserialize_map deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_identifier
+    }
+}
+
+enum Entry<'a> {
+    Attribute(&'a AttributeValue),
+    Text(String),
+    Children(Vec<&'a Element>),
+}
+
+struct ElementAccess<'a> {
+    entries: std::vec::IntoIter<(String, Entry<'a>)>,
+    value: Option<Entry<'a>>,
+}
+
+impl<'a> ElementAccess<'a> {
+    fn new(element: &'a Element, with_text: bool) -> Self {
+        let mut entries: Vec<(String, Entry<'a>)> = element
+            .attributes
+            .iter()
+            .map(|a| (format!("@{}", a.name), Entry::Attribute(&a.value)))
+            .collect();
+        let mut children: Vec<(String, Entry<'a>)> = Vec::new();
+        for child in element.elements() {
+            match children.iter_mut().find(|(name, _)| *name == child.name) {
+                Some((_, Entry::Children(list))) => list.push(child),
+                _ => children.push((child.name.clone(), Entry::Children(vec![child]))),
+            }
+        }
+        entries.extend(children);
+        let text = element.text();
+        if with_text || !text.trim().is_empty() {
+            entries.push(("$text".to_string(), Entry::Text(text)));
+        }
+        Self {
+            entries: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'a> de::MapAccess<'de> for ElementAccess<'a> {
+    type Error = ConversionError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, ConversionError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, ConversionError> {
+        match self.value.take() {
+            Some(Entry::Attribute(value)) => seed.deserialize(ValueDeserializer(value.clone())),
+            Some(Entry::Text(text)) => {
+                seed.deserialize(ValueDeserializer(AttributeValue::String(text)))
+            }
+            Some(Entry::Children(children)) => seed.deserialize(ChildrenDeserializer(children)),
+            None => Err(ConversionError::Serde(
+                "value requested before key".to_string(),
+            )),
+        }
+    }
+}
+
+struct ElementsAccess<'a>(std::vec::IntoIter<&'a Element>);
+
+impl<'de, 'a> de::SeqAccess<'de> for ElementsAccess<'a> {
+    type Error = ConversionError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, ConversionError> {
+        match self.0.next() {
+            Some(element) => seed.deserialize(ElementDeserializer(element)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
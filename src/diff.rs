@@ -0,0 +1,394 @@
+//! Structural comparison of two documents.
+//!
+//! Elements are matched among their siblings by name and, when a
+//! [`KeyAttribute`] applies, by the value of that attribute (for example
+//! `name` on `<package>`). Unkeyed siblings with the same name are matched
+//! by position. Text is compared by what it means, with references
+//! resolved:
+//!
+//! ```rust
+//! use xml2abx::AbxDocument;
+//! use xml2abx::diff::{ChangeKind, DiffOptions, diff};
+//!
+//! let old = AbxDocument::from_xml_str("<r><s>a &amp; b</s></r>")?;
+//! let new = AbxDocument::from_xml_str("<r><s>a &lt; b</s></r>")?;
+//! let changes = diff(&old, &new, &DiffOptions::default());
+//! assert_eq!(changes.len(), 1);
+//! assert_eq!(changes[0].path, "/r/s");
+//! assert_eq!(
+//!     changes[0].kind,
+//!     ChangeKind::TextChanged { old: "a & b".into(), new: "a < b".into() }
+//! );
+//!
+//! let same = AbxDocument::from_xml_str("<r><s><![CDATA[a & b]]></s></r>")?;
+//! assert!(diff(&old, &same, &DiffOptions::default()).is_empty());
+//! # Ok::<(), xml2abx::ConversionError>(())
+//! ```
+
+use crate::document::{AbxDocument, Element};
+use crate::value::AttributeValue;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyAttribute {
+    /// Element name the key applies to, or `None` for every element.
+    pub element: Option<String>,
+    pub attribute: String,
+}
+
+impl KeyAttribute {
+    /// Parses `element=attribute` or a bare `attribute`.
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once('=') {
+            Some((element, attribute)) => Self {
+                element: Some(element.to_string()),
+                attribute: attribute.to_string(),
+            },
+            None => Self {
+                element: None,
+                attribute: spec.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    pub keys: Vec<KeyAttribute>,
+    /// Report `string` and `interned` as different types.
+    pub strict_types: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    ElementAdded,
+    ElementRemoved,
+    AttributeAdded {
+        name: String,
+        value: AttributeValue,
+    },
+    AttributeRemoved {
+        name: String,
+        value: AttributeValue,
+    },
+    AttributeChanged {
+        name: String,
+        old: AttributeValue,
+        new: AttributeValue,
+        type_changed: bool,
+    },
+    TextChanged {
+        old: String,
+        new: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Path of the affected element, e.g. `/packages/package[@name='com.foo']`.
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+pub fn diff(old: &AbxDocument, new: &AbxDocument, options: &DiffOptions) -> Vec<Change> {
+    let mut changes = Vec::new();
+    match (old.root(), new.root()) {
+        (Some(a), Some(b)) if a.name == b.name => {
+            diff_elements(&format!("/{}", a.name), a, b, options, &mut changes)
+        }
+        (a, b) => {
+            if let Some(a) = a {
+                changes.push(Change {
+                    path: format!("/{}", a.name),
+                    kind: ChangeKind::ElementRemoved,
+                });
+            }
+            if let Some(b) = b {
+                changes.push(Change {
+                    path: format!("/{}", b.name),
+                    kind: ChangeKind::ElementAdded,
+                });
+            }
+        }
+    }
+    changes
+}
+
+fn type_family(value: &AttributeValue, options: &DiffOptions) -> &'static str {
+    match value {
+        AttributeValue::Interned(_) if !options.strict_types => "string",
+        _ => value.type_name(),
+    }
+}
+
+fn diff_elements(
+    path: &str,
+    old: &Element,
+    new: &Element,
+    options: &DiffOptions,
+    changes: &mut Vec<Change>,
+) {
+    for attr in &old.attributes {
+        match new.attribute(&attr.name) {
+            None => changes.push(Change {
+                path: path.to_string(),
+                kind: ChangeKind::AttributeRemoved {
+                    name: attr.name.clone(),
+                    value: attr.value.clone(),
+                },
+            }),
+            Some(value) => {
+                let type_changed = type_family(&attr.value, options) != type_family(value, options);
+                // Compare the rendered form so that NaN equals NaN.
+                if type_changed || attr.value.to_string() != value.to_string() {
+                    changes.push(Change {
+                        path: path.to_string(),
+                        kind: ChangeKind::AttributeChanged {
+                            name: attr.name.clone(),
+                            old: attr.value.clone(),
+                            new: value.clone(),
+                            type_changed,
+                        },
+                    });
+                }
+            }
+        }
+    }
+    for attr in &new.attributes {
+        if old.attribute(&attr.name).is_none() {
+            changes.push(Change {
+                path: path.to_string(),
+                kind: ChangeKind::AttributeAdded {
+                    name: attr.name.clone(),
+                    value: attr.value.clone(),
+                },
+            });
+        }
+    }
+
+    let old_text = old.text();
+    let new_text = new.text();
+    if old_text.trim() != new_text.trim() {
+        changes.push(Change {
+            path: path.to_string(),
+            kind: ChangeKind::TextChanged {
+                old: old_text,
+                new: new_text,
+            },
+        });
+    }
+
+    let old_children = keyed_children(old, &options.keys);
+    let new_children = keyed_children(new, &options.keys);
+    let new_index: HashMap<&SiblingKey, usize> = new_children
+        .iter()
+        .enumerate()
+        .map(|(i, sibling)| (&sibling.key, i))
+        .collect();
+    let mut matched = vec![false; new_children.len()];
+    for sibling in &old_children {
+        let child_path = format!("{}/{}", path, sibling.segment);
+        match new_index.get(&sibling.key) {
+            Some(&i) => {
+                matched[i] = true;
+                let other = new_children[i].element;
+                diff_elements(&child_path, sibling.element, other, options, changes);
+            }
+            None => changes.push(Change {
+                path: child_path,
+                kind: ChangeKind::ElementRemoved,
+            }),
+        }
+    }
+    for (sibling, matched) in new_children.iter().zip(matched) {
+        if !matched {
+            changes.push(Change {
+                path: format!("{}/{}", path, sibling.segment),
+                kind: ChangeKind::ElementAdded,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum SiblingKey {
+    /// `occurrence` tells apart siblings sharing a key value, counting from 1
+    /// in document order.
+    Keyed {
+        name: String,
+        attribute: String,
+        value: String,
+        occurrence: usize,
+    },
+    Positional {
+        name: String,
+        index: usize,
+    },
+}
+
+pub(crate) struct Sibling<'e> {
+    pub key: SiblingKey,
+    pub segment: String,
+    pub element: &'e Element,
+}
+
+fn key_attribute<'a>(element: &Element, keys: &'a [KeyAttribute]) -> Option<&'a str> {
+    keys.iter()
+        .filter(|k| k.element.as_deref().is_none_or(|e| e == element.name))
+        .find(|k| element.attribute(&k.attribute).is_some())
+        .map(|k| k.attribute.as_str())
+}
+
+pub(crate) fn keyed_children<'e>(parent: &'e Element, keys: &[KeyAttribute]) -> Vec<Sibling<'e>> {
+    // Elements without a key are grouped by name, keyed ones by name and
+    // key value; a group of one needs no position in its path segment.
+    let group = |child: &Element| {
+        let keyed = key_attribute(child, keys)
+            .map(|attribute| (attribute, child.attribute(attribute).unwrap().to_string()));
+        (child.name.clone(), keyed)
+    };
+    let mut totals: HashMap<_, usize> = HashMap::new();
+    for child in parent.elements() {
+        *totals.entry(group(child)).or_default() += 1;
+    }
+    let mut seen: HashMap<_, usize> = HashMap::new();
+    parent
+        .elements()
+        .map(|child| {
+            let group = group(child);
+            let total = totals[&group];
+            let index = seen.entry(group.clone()).or_default();
+            *index += 1;
+            let (name, keyed) = group;
+            let (mut segment, key) = match keyed {
+                Some((attribute, value)) => (
+                    format!("{}[@{}='{}']", name, attribute, value),
+                    SiblingKey::Keyed {
+                        name,
+                        attribute: attribute.to_string(),
+                        value,
+                        occurrence: *index,
+                    },
+                ),
+                None => (
+                    name.clone(),
+                    SiblingKey::Positional {
+                        name,
+                        index: *index,
+                    },
+                ),
+            };
+            if total > 1 {
+                segment.push_str(&format!("[{}]", index));
+            }
+            Sibling {
+                key,
+                segment,
+                element: child,
+            }
+        })
+        .collect()
+}
+
+fn describe(value: &AttributeValue) -> String {
+    format!("{:?} ({})", value.to_string(), value.type_name())
+}
+
+/// Renders changes one per line: `+` added, `-` removed, `~` changed.
+pub fn render_text(changes: &[Change]) -> String {
+    let mut out = String::new();
+    for change in changes {
+        let path = &change.path;
+        let _ = match &change.kind {
+            ChangeKind::ElementAdded => writeln!(out, "+ {}", path),
+            ChangeKind::ElementRemoved => writeln!(out, "- {}", path),
+            ChangeKind::AttributeAdded { name, value } => {
+                writeln!(out, "+ {}/@{} = {}", path, name, describe(value))
+            }
+            ChangeKind::AttributeRemoved { name, value } => {
+                writeln!(out, "- {}/@{} = {}", path, name, describe(value))
+            }
+            ChangeKind::AttributeChanged {
+                name,
+                old,
+                new,
+                type_changed,
+            } => {
+                let marker = if *type_changed { " [type changed]" } else { "" };
+                writeln!(
+                    out,
+                    "~ {}/@{}: {} -> {}{}",
+                    path,
+                    name,
+                    describe(old),
+                    describe(new),
+                    marker
+                )
+            }
+            ChangeKind::TextChanged { old, new } => {
+                writeln!(
+                    out,
+                    "~ {}/text(): {:?} -> {:?}",
+                    path,
+                    old.trim(),
+                    new.trim()
+                )
+            }
+        };
+    }
+    out
+}
+
+fn value_json(value: &AttributeValue) -> Value {
+    json!({ "type": value.type_name(), "value": value.to_string() })
+}
+
+pub fn to_json(changes: &[Change]) -> Value {
+    Value::Array(
+        changes
+            .iter()
+            .map(|change| {
+                let path = &change.path;
+                match &change.kind {
+                    ChangeKind::ElementAdded => json!({ "op": "element-added", "path": path }),
+                    ChangeKind::ElementRemoved => {
+                        json!({ "op": "element-removed", "path": path })
+                    }
+                    ChangeKind::AttributeAdded { name, value } => json!({
+                        "op": "attribute-added",
+                        "path": path,
+                        "attribute": name,
+                        "new": value_json(value),
+                    }),
+                    ChangeKind::AttributeRemoved { name, value } => json!({
+                        "op": "attribute-removed",
+                        "path": path,
+                        "attribute": name,
+                        "old": value_json(value),
+                    }),
+                    ChangeKind::AttributeChanged {
+                        name,
+                        old,
+                        new,
+                        type_changed,
+                    } => json!({
+                        "op": "attribute-changed",
+                        "path": path,
+                        "attribute": name,
+                        "old": value_json(old),
+                        "new": value_json(new),
+                        "type_changed": type_changed,
+                    }),
+                    ChangeKind::TextChanged { old, new } => json!({
+                        "op": "text-changed",
+                        "path": path,
+                        "old": old,
+                        "new": new,
+                    }),
+                }
+            })
+            .collect(),
+    )
+}
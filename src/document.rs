@@ -0,0 +1,464 @@
+//! In-memory tree representation of an ABX or XML document.
+//!
+//! Attributes keep their ABX type as an [`AttributeValue`], so a document
+//! loaded from ABX and written back produces the same encoding.
+//!
+//! ```rust
+//! use xml2abx::{AbxDocument, AttributeValue, Element};
+//!
+//! let mut doc = AbxDocument::from_xml_str(r#"<packages><package name="com.foo"/></packages>"#).unwrap();
+//! let root = doc.root_mut().unwrap();
+//! root.child_mut("package")
+//!     .unwrap()
+//!     .set_attribute("flags", AttributeValue::IntHex(0x1c));
+//! root.push_child(Element::new("package").with_attribute("name", AttributeValue::Interned("com.bar".into())));
+//!
+//! let mut abx = Vec::new();
+//! doc.write_abx(&mut abx).unwrap();
+//! let reloaded = AbxDocument::from_abx(&abx[..]).unwrap();
+//! assert_eq!(reloaded, doc);
+//! ```
+
+use crate::diff::{KeyAttribute, SiblingKey, keyed_children};
+use crate::parser::resolve_entity;
+use crate::reader::{AbxEvent, AbxReader};
+use crate::serializer::{TextXmlSerializer, TypedXmlSerializer};
+use crate::value::{Attribute, AttributeValue};
+use crate::{BinaryXmlSerializer, ConversionError, XmlToAbxConverter};
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+    CData(String),
+    EntityRef(String),
+    IgnorableWhitespace(String),
+    ProcessingInstruction(String),
+    Comment(String),
+    DocDecl(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AbxDocument {
+    pub children: Vec<Node>,
+}
+
+impl From<Element> for Node {
+    fn from(element: Element) -> Self {
+        Node::Element(element)
+    }
+}
+
+impl Element {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+    pub fn with_attribute(mut self, name: impl Into<String>, value: AttributeValue) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+    pub fn with_child(mut self, child: impl Into<Node>) -> Self {
+        self.children.push(child.into());
+        self
+    }
+    pub fn attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| &a.value)
+    }
+    pub fn attribute_mut(&mut self, name: &str) -> Option<&mut AttributeValue> {
+        self.attributes
+            .iter_mut()
+            .find(|a| a.name == name)
+            .map(|a| &mut a.value)
+    }
+    /// Replaces the value of an existing attribute in place, or appends a new one.
+    pub fn set_attribute(&mut self, name: impl Into<String>, value: AttributeValue) {
+        let name = name.into();
+        match self.attribute_mut(&name) {
+            Some(existing) => *existing = value,
+            None => self.attributes.push(Attribute::new(name, value)),
+        }
+    }
+    pub fn remove_attribute(&mut self, name: &str) -> Option<AttributeValue> {
+        let index = self.attributes.iter().position(|a| a.name == name)?;
+        Some(self.attributes.remove(index).value)
+    }
+    pub fn push_child(&mut self, child: impl Into<Node>) {
+        self.children.push(child.into());
+    }
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+    /// First child element with the given name.
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.elements_mut().find(|e| e.name == name)
+    }
+    /// Removes every child element for which `keep` returns false.
+    pub fn retain_elements(&mut self, mut keep: impl FnMut(&Element) -> bool) {
+        self.children.retain(|node| match node {
+            Node::Element(e) => keep(e),
+            _ => true,
+        });
+    }
+    /// Renders the element and its content as an XML fragment.
+    pub fn to_xml_string(&self, pretty: bool) -> String {
+        let mut out = Vec::new();
+        // Writing to a Vec cannot fail.
+        let _ = if pretty {
+            write_element(
+                &mut TextXmlSerializer::with_indent(&mut out, "  "),
+                self,
+                true,
+            )
+        } else {
+            write_element(&mut TextXmlSerializer::new(&mut out), self, false)
+        };
+        let xml = String::from_utf8(out).unwrap_or_default();
+        xml.trim_end_matches('\n').to_string()
+    }
+    /// Concatenated text and CDATA content of the direct children, with
+    /// predefined and character references resolved.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Text(t) | Node::CData(t) => text.push_str(t),
+                Node::EntityRef(name) => text.push_str(&resolve_entity(name)),
+                _ => {}
+            }
+        }
+        text
+    }
+}
+
+impl AbxDocument {
+    pub fn new(root: Element) -> Self {
+        Self {
+            children: vec![Node::Element(root)],
+        }
+    }
+
+    pub fn is_abx(data: &[u8]) -> bool {
+        data.starts_with(&BinaryXmlSerializer::<Vec<u8>>::PROTOCOL_MAGIC_VERSION_0)
+    }
+
+    /// Loads either format, detected by the ABX magic header.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ConversionError> {
+        if Self::is_abx(data) {
+            Self::from_abx(data)
+        } else {
+            Self::from_xml_str(std::str::from_utf8(data)?)
+        }
+    }
+
+    pub fn from_xml_str(xml: &str) -> Result<Self, ConversionError> {
+        let mut abx = Vec::new();
+        XmlToAbxConverter::convert_from_string(xml, &mut abx)?;
+        Self::from_abx(&abx[..])
+    }
+
+    pub fn from_xml_reader<R: BufRead>(input: R) -> Result<Self, ConversionError> {
+        let mut abx = Vec::new();
+        XmlToAbxConverter::convert_from_reader(input, &mut abx)?;
+        Self::from_abx(&abx[..])
+    }
+
+    pub fn from_abx<R: Read>(input: R) -> Result<Self, ConversionError> {
+        let mut reader = AbxReader::new(input)?;
+        let mut document = AbxDocument::default();
+        let mut stack: Vec<Element> = Vec::new();
+        while let Some(event) = reader.next_event()? {
+            let node = match event {
+                AbxEvent::StartDocument | AbxEvent::EndDocument => continue,
+                AbxEvent::StartTag { name, attributes } => {
+                    stack.push(Element {
+                        name,
+                        attributes,
+                        children: Vec::new(),
+                    });
+                    continue;
+                }
+                AbxEvent::EndTag(name) => {
+                    let element = stack.pop().ok_or_else(|| {
+                        ConversionError::Malformed(format!("unexpected end tag </{}>", name))
+                    })?;
+                    if element.name != name {
+                        return Err(ConversionError::Malformed(format!(
+                            "end tag </{}> does not match <{}>",
+                            name, element.name
+                        )));
+                    }
+                    Node::Element(element)
+                }
+                AbxEvent::Text(t) => Node::Text(t),
+                AbxEvent::CdSect(t) => Node::CData(t),
+                AbxEvent::EntityRef(t) => Node::EntityRef(t),
+                AbxEvent::IgnorableWhitespace(t) => Node::IgnorableWhitespace(t),
+                AbxEvent::ProcessingInstruction(t) => Node::ProcessingInstruction(t),
+                AbxEvent::Comment(t) => Node::Comment(t),
+                AbxEvent::DocDecl(t) => Node::DocDecl(t),
+            };
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => document.children.push(node),
+            }
+        }
+        if let Some(open) = stack.last() {
+            return Err(ConversionError::Malformed(format!(
+                "unclosed element <{}>",
+                open.name
+            )));
+        }
+        Ok(document)
+    }
+
+    pub fn root(&self) -> Option<&Element> {
+        self.children.iter().find_map(|node| match node {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn root_mut(&mut self) -> Option<&mut Element> {
+        self.children.iter_mut().find_map(|node| match node {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    /// Whether any whitespace-only text appears between nodes.
+    pub fn has_formatting_whitespace(&self) -> bool {
+        fn visit(nodes: &[Node]) -> bool {
+            nodes.iter().any(|node| match node {
+                Node::Element(e) => visit(&e.children),
+                node => is_blank(node),
+            })
+        }
+        visit(&self.children)
+    }
+
+    /// Removes whitespace-only text nodes throughout the document.
+    pub fn strip_whitespace(&mut self) {
+        fn visit(nodes: &mut Vec<Node>) {
+            nodes.retain(|node| !is_blank(node));
+            for node in nodes {
+                if let Node::Element(e) = node {
+                    visit(&mut e.children);
+                }
+            }
+        }
+        visit(&mut self.children)
+    }
+
+    /// Gives every attribute that also exists on the matching element of
+    /// `original` the type it had there, re-parsing its current text.
+    /// Elements are matched among their siblings by name and by their
+    /// `name`, `id` or `n` attribute; siblings without one are matched by
+    /// position only when both documents have the same number of them.
+    /// Attributes of unmatched elements, and new attributes, keep their type.
+    pub fn restore_types(&mut self, original: &AbxDocument) -> Result<(), ConversionError> {
+        let keys: Vec<KeyAttribute> = RESTORE_KEYS
+            .iter()
+            .map(|k| KeyAttribute::parse(k))
+            .collect();
+        match (self.root_mut(), original.root()) {
+            (Some(root), Some(template)) if root.name == template.name => {
+                let path = format!("/{}", root.name);
+                restore_element(root, template, &path, &keys)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_abx_bytes(&self) -> Result<Vec<u8>, ConversionError> {
+        let mut out = Vec::new();
+        self.write_abx(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn write_abx<W: Write>(&self, writer: W) -> Result<(), ConversionError> {
+        let mut serializer = BinaryXmlSerializer::new(writer)?;
+        self.serialize(&mut serializer)
+    }
+
+    /// Writes text XML. With `pretty`, whitespace between elements is
+    /// dropped and elements are re-indented, which gives a stable rendering
+    /// regardless of how the source was formatted.
+    pub fn write_xml<W: Write>(&self, writer: W, pretty: bool) -> Result<(), ConversionError> {
+        self.write_xml_with_options(writer, pretty, false)
+    }
+
+    /// Like [`Self::write_xml`]; with `annotate_types`, attributes whose type
+    /// cannot be inferred from their text get a `name:abx-type` annotation
+    /// (see [`TextXmlSerializer`]).
+    pub fn write_xml_with_options<W: Write>(
+        &self,
+        writer: W,
+        pretty: bool,
+        annotate_types: bool,
+    ) -> Result<(), ConversionError> {
+        if pretty {
+            let mut serializer =
+                TextXmlSerializer::with_indent(writer, "  ").with_type_annotations(annotate_types);
+            write_document(&mut serializer, &self.children, true)
+        } else {
+            let mut serializer =
+                TextXmlSerializer::new(writer).with_type_annotations(annotate_types);
+            self.serialize(&mut serializer)
+        }
+    }
+
+    /// Replays the document, including the start and end document events,
+    /// into any [`TypedXmlSerializer`]. Attributes keep their types.
+    pub fn serialize<S: TypedXmlSerializer + ?Sized>(
+        &self,
+        serializer: &mut S,
+    ) -> Result<(), ConversionError> {
+        write_document(serializer, &self.children, false)
+    }
+
+    pub fn to_xml_string(&self, pretty: bool) -> String {
+        let mut out = Vec::new();
+        // Writing to a Vec cannot fail.
+        let _ = self.write_xml(&mut out, pretty);
+        String::from_utf8(out).unwrap_or_default()
+    }
+}
+
+// Visits every element with its positional path, e.g. `/packages[1]/package[3]`.
+// Attributes that identify an element among its siblings in the platform's
+// files: package and setting names, setting ids and app-op codes.
+const RESTORE_KEYS: [&str; 3] = ["name", "id", "n"];
+
+fn restore_element(
+    element: &mut Element,
+    template: &Element,
+    path: &str,
+    keys: &[KeyAttribute],
+) -> Result<(), ConversionError> {
+    for attr in &mut element.attributes {
+        if let Some(original) = template.attribute(&attr.name)
+            && original.type_name() != attr.value.type_name()
+        {
+            attr.value = original.with_text(&attr.value.to_string()).map_err(|e| {
+                ConversionError::Malformed(format!("{}/@{}: {}", path, attr.name, e))
+            })?;
+        }
+    }
+
+    let template_siblings = keyed_children(template, keys);
+    let templates: HashMap<&SiblingKey, &Element> = template_siblings
+        .iter()
+        .map(|s| (&s.key, s.element))
+        .collect();
+    let siblings: Vec<(SiblingKey, String)> = keyed_children(element, keys)
+        .into_iter()
+        .map(|s| (s.key, s.segment))
+        .collect();
+    // Unkeyed siblings are only matched by position when none were added or
+    // removed, so an inserted element does not take its neighbour's types.
+    let template_counts = positional_counts(template_siblings.iter().map(|s| &s.key));
+    let counts = positional_counts(siblings.iter().map(|(key, _)| key));
+    for (child, (key, segment)) in element.elements_mut().zip(&siblings) {
+        if let SiblingKey::Positional { name, .. } = key
+            && counts.get(name.as_str()) != template_counts.get(name.as_str())
+        {
+            continue;
+        }
+        if let Some(template) = templates.get(key) {
+            restore_element(child, template, &format!("{}/{}", path, segment), keys)?;
+        }
+    }
+    Ok(())
+}
+
+fn positional_counts<'a>(keys: impl Iterator<Item = &'a SiblingKey>) -> HashMap<&'a str, usize> {
+    let mut counts = HashMap::new();
+    for key in keys {
+        if let SiblingKey::Positional { name, .. } = key {
+            *counts.entry(name.as_str()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+fn write_document<S: TypedXmlSerializer + ?Sized>(
+    serializer: &mut S,
+    nodes: &[Node],
+    skip_blank: bool,
+) -> Result<(), ConversionError> {
+    serializer.start_document()?;
+    for node in nodes {
+        write_node(serializer, node, skip_blank)?;
+    }
+    serializer.end_document()
+}
+
+fn write_node<S: TypedXmlSerializer + ?Sized>(
+    serializer: &mut S,
+    node: &Node,
+    skip_blank: bool,
+) -> Result<(), ConversionError> {
+    if skip_blank && is_blank(node) {
+        return Ok(());
+    }
+    match node {
+        Node::Element(element) => write_element(serializer, element, skip_blank),
+        Node::Text(t) => serializer.text(t),
+        Node::CData(t) => serializer.cdsect(t),
+        Node::EntityRef(t) => serializer.entity_ref(t),
+        Node::IgnorableWhitespace(t) => serializer.ignorable_whitespace(t),
+        Node::ProcessingInstruction(t) => serializer.processing_instruction(t, None),
+        Node::Comment(t) => serializer.comment(t),
+        Node::DocDecl(t) => serializer.docdecl(t),
+    }
+}
+
+fn write_element<S: TypedXmlSerializer + ?Sized>(
+    serializer: &mut S,
+    element: &Element,
+    skip_blank: bool,
+) -> Result<(), ConversionError> {
+    serializer.start_tag(&element.name)?;
+    for attr in &element.attributes {
+        attr.value.write(serializer, &attr.name)?;
+    }
+    for child in &element.children {
+        write_node(serializer, child, skip_blank)?;
+    }
+    serializer.end_tag(&element.name)
+}
+
+fn is_blank(node: &Node) -> bool {
+    match node {
+        Node::IgnorableWhitespace(_) => true,
+        Node::Text(t) => t.trim().is_empty(),
+        _ => false,
+    }
+}
@@ -0,0 +1,182 @@
+//! Lossless JSON representation of ABX documents.
+//!
+//! A document is an object with a format `version` and its top-level
+//! `children`. Every node is an object with a `type`:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "children": [
+//!     { "type": "comment", "value": " packages " },
+//!     {
+//!       "type": "element",
+//!       "name": "package",
+//!       "attributes": [
+//!         { "name": "name", "type": "interned", "value": "com.foo" },
+//!         { "name": "ft", "type": "long-hex", "value": "18c5a3f1e20" }
+//!       ],
+//!       "children": [{ "type": "cdata", "value": "<raw>" }]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Node types are `element`, `text`, `cdata`, `entity-ref`,
+//! `ignorable-whitespace`, `processing-instruction`, `comment` and `docdecl`.
+//! Attribute types are the ABX type names (see
+//! [`AttributeValue::type_name`]) and values are always strings, formatted as
+//! in text XML, so 64-bit values survive JavaScript consumers. `attributes`
+//! and `children` may be omitted when empty.
+//!
+//! Converting ABX to JSON and back writes the same bytes as the original
+//! file, provided it was produced by the platform serializer.
+//!
+//! ```rust
+//! use xml2abx::{AbxDocument, json};
+//!
+//! let doc = AbxDocument::from_xml_str(r#"<settings version="3"><!-- x --></settings>"#).unwrap();
+//! let abx = doc.to_abx_bytes().unwrap();
+//! let value = json::to_json(&AbxDocument::from_abx(&abx[..]).unwrap());
+//! assert_eq!(json::from_json(&value).unwrap().to_abx_bytes().unwrap(), abx);
+//! ```
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element, Node};
+use crate::value::{Attribute, AttributeValue};
+use serde_json::{Map, Value, json};
+
+/// Version written to, and accepted in, the `version` field.
+pub const FORMAT_VERSION: u64 = 1;
+
+pub fn to_json(document: &AbxDocument) -> Value {
+    json!({
+        "version": FORMAT_VERSION,
+        "children": nodes_to_json(&document.children),
+    })
+}
+
+pub fn from_json(value: &Value) -> Result<AbxDocument, ConversionError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| malformed("document must be an object"))?;
+    if let Some(version) = object.get("version")
+        && version.as_u64() != Some(FORMAT_VERSION)
+    {
+        return Err(malformed(format!("unsupported version {}", version)));
+    }
+    Ok(AbxDocument {
+        children: nodes_from_json(object.get("children"))?,
+    })
+}
+
+pub fn to_json_string(document: &AbxDocument, pretty: bool) -> String {
+    let value = to_json(document);
+    if pretty {
+        // Serializing a `Value` cannot fail.
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn from_json_str(json: &str) -> Result<AbxDocument, ConversionError> {
+    from_json(&serde_json::from_str(json)?)
+}
+
+fn nodes_to_json(nodes: &[Node]) -> Value {
+    Value::Array(nodes.iter().map(node_to_json).collect())
+}
+
+fn node_to_json(node: &Node) -> Value {
+    let (kind, value) = match node {
+        Node::Element(element) => {
+            return json!({
+                "type": "element",
+                "name": element.name,
+                "attributes": element
+                    .attributes
+                    .iter()
+                    .map(|attr| json!({
+                        "name": attr.name,
+                        "type": attr.value.type_name(),
+                        "value": attr.value.to_string(),
+                    }))
+                    .collect::<Vec<_>>(),
+                "children": nodes_to_json(&element.children),
+            });
+        }
+        Node::Text(t) => ("text", t),
+        Node::CData(t) => ("cdata", t),
+        Node::EntityRef(t) => ("entity-ref", t),
+        Node::IgnorableWhitespace(t) => ("ignorable-whitespace", t),
+        Node::ProcessingInstruction(t) => ("processing-instruction", t),
+        Node::Comment(t) => ("comment", t),
+        Node::DocDecl(t) => ("docdecl", t),
+    };
+    json!({ "type": kind, "value": value })
+}
+
+fn nodes_from_json(value: Option<&Value>) -> Result<Vec<Node>, ConversionError> {
+    match value {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(nodes)) => nodes.iter().map(node_from_json).collect(),
+        Some(_) => Err(malformed("children must be an array")),
+    }
+}
+
+fn node_from_json(value: &Value) -> Result<Node, ConversionError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| malformed("node must be an object"))?;
+    let kind = string_field(object, "type")?;
+    if kind == "element" {
+        return Ok(Node::Element(Element {
+            name: string_field(object, "name")?.to_string(),
+            attributes: attributes_from_json(object.get("attributes"))?,
+            children: nodes_from_json(object.get("children"))?,
+        }));
+    }
+    let text = string_field(object, "value")?.to_string();
+    Ok(match kind {
+        "text" => Node::Text(text),
+        "cdata" => Node::CData(text),
+        "entity-ref" => Node::EntityRef(text),
+        "ignorable-whitespace" => Node::IgnorableWhitespace(text),
+        "processing-instruction" => Node::ProcessingInstruction(text),
+        "comment" => Node::Comment(text),
+        "docdecl" => Node::DocDecl(text),
+        _ => return Err(malformed(format!("unknown node type {:?}", kind))),
+    })
+}
+
+fn attributes_from_json(value: Option<&Value>) -> Result<Vec<Attribute>, ConversionError> {
+    let attributes = match value {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::Array(attributes)) => attributes,
+        Some(_) => return Err(malformed("attributes must be an array")),
+    };
+    attributes
+        .iter()
+        .map(|attr| {
+            let object = attr
+                .as_object()
+                .ok_or_else(|| malformed("attribute must be an object"))?;
+            let value = AttributeValue::parse(
+                string_field(object, "type")?,
+                string_field(object, "value")?,
+            )?;
+            Ok(Attribute::new(string_field(object, "name")?, value))
+        })
+        .collect()
+}
+
+fn string_field<'a>(object: &'a Map<String, Value>, key: &str) -> Result<&'a str, ConversionError> {
+    object
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| malformed(format!("missing string field {:?}", key)))
+}
+
+fn malformed(message: impl Into<String>) -> ConversionError {
+    ConversionError::Malformed(format!("JSON: {}", message.into()))
+}
@@ -2,6 +2,10 @@
 //!
 //! A library for converting XML to Android Binary XML format.
 //!
+//! Besides the streaming [`XmlToAbxConverter`], ABX can be decoded with
+//! [`AbxReader`] and loaded into an [`AbxDocument`], an editable tree whose
+//! attributes keep their ABX types.
+//!
 //! ## Example
 //!
 //! ```rust
@@ -19,10 +23,60 @@
 
 use byteorder::{BigEndian, WriteBytesExt};
 use quick_xml::Reader;
-use quick_xml::events::Event;
+use quick_xml::encoding::Decoder;
+use quick_xml::events::{BytesStart, Event};
 use std::collections::HashMap;
 use std::io::{self, BufRead, Write};
 use thiserror::Error;
+
+pub mod appops;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod atomic_file;
+#[cfg(feature = "backup")]
+pub mod backup;
+pub mod bundle;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod carve;
+pub mod codec;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod diff;
+pub mod document;
+pub mod json;
+pub mod map_xml;
+pub mod merge;
+pub mod packages;
+pub mod parser;
+pub mod reader;
+pub mod runtime_permissions;
+pub mod select;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod serializer;
+pub mod settings;
+pub mod stream;
+pub mod value;
+
+pub use atomic_file::AtomicFile;
+pub use bundle::{BundleValue, PersistableBundle};
+pub use codec::{AbxRead, AbxWrite, AttributeField};
+#[cfg(feature = "serde")]
+pub use de::from_reader;
+pub use document::{AbxDocument, Element, Node};
+pub use map_xml::MapValue;
+pub use parser::{AbxPullParser, TypedXmlPullParser, XmlPullParser};
+pub use reader::{AbxEvent, AbxReader, FastDataInput};
+pub use select::Selector;
+#[cfg(feature = "serde")]
+pub use ser::to_writer;
+pub use serializer::{TextXmlSerializer, TypedXmlSerializer};
+pub use stream::{AbxToXmlReader, XmlToAbxWriter};
+pub use value::{Attribute, AttributeValue};
+#[cfg(feature = "derive")]
+pub use xml2abx_derive::{AbxRead, AbxWrite};
+
 #[derive(Error, Debug)]
 pub enum ConversionError {
     #[error("XML parsing failed: {0}")]
@@ -41,7 +95,69 @@ pub enum ConversionError {
     Utf8Error(#[from] std::str::Utf8Error),
     #[error("Attribute error: {0}")]
     AttrError(#[from] quick_xml::events::attributes::AttrError),
+    #[error("Not an ABX document (bad magic header)")]
+    InvalidMagic,
+    #[error("Unknown ABX token: {0:#04x}")]
+    UnknownToken(u8),
+    #[error("Invalid interned string index: {0}")]
+    InvalidInternedIndex(u16),
+    #[error("Malformed document: {0}")]
+    Malformed(String),
+    #[error("Invalid selector: {0}")]
+    InvalidSelector(String),
+    #[error("Unknown attribute type: {0}")]
+    UnknownType(String),
+    #[error("Invalid {0} value: {1:?}")]
+    InvalidValue(&'static str, String),
+    #[error("Missing attribute: {0}")]
+    MissingAttribute(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "serde")]
+    #[error("Serde error: {0}")]
+    Serde(String),
+    #[cfg(feature = "backup")]
+    #[error("Backup error: {0}")]
+    Backup(String),
+}
+impl ConversionError {
+    /// Name of the variant, stable across releases, for bindings that
+    /// surface errors to other languages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::XmlParsing(_) => "XmlParsing",
+            Self::Io(_) => "Io",
+            Self::StringTooLong(..) => "StringTooLong",
+            Self::BinaryDataTooLong(..) => "BinaryDataTooLong",
+            Self::InvalidHex => "InvalidHex",
+            Self::InvalidBase64 => "InvalidBase64",
+            Self::Utf8Error(_) => "Utf8Error",
+            Self::AttrError(_) => "AttrError",
+            Self::InvalidMagic => "InvalidMagic",
+            Self::UnknownToken(_) => "UnknownToken",
+            Self::InvalidInternedIndex(_) => "InvalidInternedIndex",
+            Self::Malformed(_) => "Malformed",
+            Self::InvalidSelector(_) => "InvalidSelector",
+            Self::UnknownType(_) => "UnknownType",
+            Self::InvalidValue(..) => "InvalidValue",
+            Self::MissingAttribute(_) => "MissingAttribute",
+            Self::Json(_) => "Json",
+            #[cfg(feature = "serde")]
+            Self::Serde(_) => "Serde",
+            #[cfg(feature = "backup")]
+            Self::Backup(_) => "Backup",
+        }
+    }
+}
+impl From<ConversionError> for io::Error {
+    fn from(error: ConversionError) -> Self {
+        match error {
+            ConversionError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
 }
+
 pub fn show_warning(feature: &str, details: Option<&str>) {
     eprintln!("WARNING: {} is not supported and might be lost.", feature);
     if let Some(details) = details {
@@ -118,6 +234,12 @@ impl<W: Write> FastDataOutput<W> {
         self.writer.flush()?;
         Ok(())
     }
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
 }
 pub struct BinaryXmlSerializer<W: Write> {
     output: FastDataOutput<W>,
@@ -318,11 +440,17 @@ impl<W: Write> BinaryXmlSerializer<W> {
     pub fn entity_ref(&mut self, text: &str) -> Result<(), ConversionError> {
         self.write_token(Self::ENTITY_REF, Some(text))
     }
+    pub fn flush(&mut self) -> Result<(), ConversionError> {
+        self.output.flush()
+    }
+    pub fn get_mut(&mut self) -> &mut W {
+        self.output.get_mut()
+    }
+    pub fn into_inner(self) -> W {
+        self.output.into_inner()
+    }
 }
 mod type_detection {
-    pub fn is_boolean(s: &str) -> bool {
-        s == "true" || s == "false"
-    }
     pub fn is_whitespace_only(s: &str) -> bool {
         s.chars().all(|c| c.is_whitespace())
     }
@@ -375,151 +503,157 @@ impl XmlToAbxConverter {
     ) -> Result<(), ConversionError> {
         let mut serializer = BinaryXmlSerializer::with_options(writer, preserve_whitespace)?;
         let mut buf = Vec::new();
-        let mut tag_stack = Vec::new();
         serializer.start_document()?;
         loop {
             match reader.read_event_into(&mut buf)? {
-                Event::Start(e) => {
-                    let name_bytes = e.name();
-                    let name = std::str::from_utf8(name_bytes.as_ref())?;
-                    if name.contains(':') {
-                        show_warning(
-                            "Namespaces and prefixes",
-                            Some(&format!("Found prefixed element: {}", name)),
-                        );
-                    }
-                    serializer.start_tag(name)?;
-                    tag_stack.push(name.to_string());
-                    for attr in e.attributes() {
-                        let attr = attr?;
-                        let attr_name = std::str::from_utf8(attr.key.as_ref())?;
-                        let attr_value = std::str::from_utf8(&attr.value)?;
-                        if attr_name.starts_with("xmlns") || attr_name.contains(':') {
-                            show_warning(
-                                "Namespaces and prefixes",
-                                Some(&format!(
-                                    "Found namespace declaration or prefixed attribute: {}",
-                                    attr_name
-                                )),
-                            );
-                        }
-                        Self::write_attribute(&mut serializer, attr_name, attr_value)?;
-                    }
-                }
-                Event::End(e) => {
-                    let name_bytes = e.name();
-                    let name = std::str::from_utf8(name_bytes.as_ref())?;
-                    serializer.end_tag(name)?;
-                    tag_stack.pop();
+                Event::Eof => break,
+                event => Self::write_event(&mut serializer, event, reader.decoder())?,
+            }
+            buf.clear();
+        }
+        serializer.end_document()?;
+        Ok(())
+    }
+    /// Writes one parsed XML event; `Eof` is ignored.
+    pub(crate) fn write_event<W: Write>(
+        serializer: &mut BinaryXmlSerializer<W>,
+        event: Event,
+        decoder: Decoder,
+    ) -> Result<(), ConversionError> {
+        match event {
+            Event::Start(e) => {
+                let name_bytes = e.name();
+                let name = std::str::from_utf8(name_bytes.as_ref())?;
+                if name.contains(':') {
+                    show_warning(
+                        "Namespaces and prefixes",
+                        Some(&format!("Found prefixed element: {}", name)),
+                    );
                 }
-                Event::Empty(e) => {
-                    let name_bytes = e.name();
-                    let name = std::str::from_utf8(name_bytes.as_ref())?;
-                    if name.contains(':') {
-                        show_warning(
-                            "Namespaces and prefixes",
-                            Some(&format!("Found prefixed element: {}", name)),
-                        );
-                    }
-                    serializer.start_tag(name)?;
-                    for attr in e.attributes() {
-                        let attr = attr?;
-                        let attr_name = std::str::from_utf8(attr.key.as_ref())?;
-                        let attr_value = std::str::from_utf8(&attr.value)?;
-                        if attr_name.starts_with("xmlns") || attr_name.contains(':') {
-                            show_warning(
-                                "Namespaces and prefixes",
-                                Some(&format!(
-                                    "Found namespace declaration or prefixed attribute: {}",
-                                    attr_name
-                                )),
-                            );
-                        }
-                        Self::write_attribute(&mut serializer, attr_name, attr_value)?;
-                    }
-                    serializer.end_tag(name)?;
+                serializer.start_tag(name)?;
+                Self::write_attributes(serializer, &e, decoder)?;
+            }
+            Event::End(e) => {
+                let name_bytes = e.name();
+                let name = std::str::from_utf8(name_bytes.as_ref())?;
+                serializer.end_tag(name)?;
+            }
+            Event::Empty(e) => {
+                let name_bytes = e.name();
+                let name = std::str::from_utf8(name_bytes.as_ref())?;
+                if name.contains(':') {
+                    show_warning(
+                        "Namespaces and prefixes",
+                        Some(&format!("Found prefixed element: {}", name)),
+                    );
                 }
-                Event::Text(e) => {
-                    let text = std::str::from_utf8(&e)?;
-                    if type_detection::is_whitespace_only(text) {
-                        if serializer.preserve_whitespace {
-                            serializer.ignorable_whitespace(text)?;
-                        }
-                    } else {
-                        serializer.text(text)?;
+                serializer.start_tag(name)?;
+                Self::write_attributes(serializer, &e, decoder)?;
+                serializer.end_tag(name)?;
+            }
+            Event::Text(e) => {
+                let text = std::str::from_utf8(&e)?;
+                if type_detection::is_whitespace_only(text) {
+                    if serializer.preserve_whitespace {
+                        serializer.ignorable_whitespace(text)?;
                     }
+                } else {
+                    serializer.text(text)?;
                 }
-                Event::CData(e) => {
-                    let text = std::str::from_utf8(&e)?;
-                    serializer.cdsect(text)?;
-                }
-                Event::Comment(e) => {
-                    let text = std::str::from_utf8(&e)?;
-                    serializer.comment(text)?;
-                }
-                Event::PI(e) => {
-                    let target = std::str::from_utf8(e.target())?;
-                    let raw = e.content();
-                    let data = if raw.is_empty() {
-                        None
-                    } else {
-                        Some(std::str::from_utf8(raw)?)
-                    };
-                    if target == "xml" {
-                        if let Some(content) = data {
-                            if content.contains("encoding")
-                                && !content.to_lowercase().contains("utf-8")
-                            {
-                                show_warning(
-                                    "Non‑UTF‑8 encoding",
-                                    Some(&format!("Found in declaration: {}", content)),
-                                );
-                            }
-                        }
-                    }
-                    serializer.processing_instruction(target, data)?;
+            }
+            Event::CData(e) => {
+                let text = std::str::from_utf8(&e)?;
+                serializer.cdsect(text)?;
+            }
+            Event::Comment(e) => {
+                let text = std::str::from_utf8(&e)?;
+                serializer.comment(text)?;
+            }
+            Event::PI(e) => {
+                let target = std::str::from_utf8(e.target())?;
+                let raw = e.content();
+                let data = if raw.is_empty() {
+                    None
+                } else {
+                    Some(std::str::from_utf8(raw)?)
+                };
+                if target == "xml"
+                    && let Some(content) = data
+                    && content.contains("encoding")
+                    && !content.to_lowercase().contains("utf-8")
+                {
+                    show_warning(
+                        "Non‑UTF‑8 encoding",
+                        Some(&format!("Found in declaration: {}", content)),
+                    );
                 }
-                Event::Decl(decl) => {
-                    if let Some(enc_result) = decl.encoding() {
-                        let enc_bytes = enc_result?;
-                        let enc = std::str::from_utf8(enc_bytes.as_ref())?;
-                        if !enc.to_lowercase().contains("utf-8") {
-                            show_warning(
-                                "Non-UTF-8 encoding",
-                                Some(&format!("Found encoding: {}", enc)),
-                            );
-                        }
+                serializer.processing_instruction(target, data)?;
+            }
+            Event::Decl(decl) => {
+                if let Some(enc_result) = decl.encoding() {
+                    let enc_bytes = enc_result?;
+                    let enc = std::str::from_utf8(enc_bytes.as_ref())?;
+                    if !enc.to_lowercase().contains("utf-8") {
+                        show_warning(
+                            "Non-UTF-8 encoding",
+                            Some(&format!("Found encoding: {}", enc)),
+                        );
                     }
                 }
-                Event::DocType(e) => {
-                    let text = std::str::from_utf8(&e)?;
-                    serializer.docdecl(text)?;
-                }
-                Event::GeneralRef(e) => {
-                    let text = std::str::from_utf8(&e)?;
-                    serializer.entity_ref(text)?;
-                }
-                Event::Eof => break,
             }
-            buf.clear();
+            Event::DocType(e) => {
+                let text = std::str::from_utf8(&e)?;
+                serializer.docdecl(text)?;
+            }
+            Event::GeneralRef(e) => {
+                let text = std::str::from_utf8(&e)?;
+                serializer.entity_ref(text)?;
+            }
+            Event::Eof => {}
         }
-        serializer.end_document()?;
         Ok(())
     }
-    fn write_attribute<W: Write>(
+    /// Writes the attributes of a start tag. Values are typed by
+    /// [`AttributeValue::infer`] unless annotated XML gives the type in a
+    /// `name:abx-type` attribute; annotations themselves are not written.
+    fn write_attributes<W: Write>(
         serializer: &mut BinaryXmlSerializer<W>,
-        name: &str,
-        value: &str,
+        start: &BytesStart,
+        decoder: Decoder,
     ) -> Result<(), ConversionError> {
-        use type_detection::*;
-        if is_boolean(value) {
-            serializer.attribute_boolean(name, value == "true")?;
-        } else {
-            if value.len() < 50 && !value.contains(' ') {
-                serializer.attribute_interned(name, value)?;
-            } else {
-                serializer.attribute(name, value)?;
+        let mut attributes = Vec::new();
+        let mut types = HashMap::new();
+        for attr in start.attributes() {
+            let attr = attr?;
+            let attr_name = std::str::from_utf8(attr.key.as_ref())?;
+            let attr_value = attr.decode_and_unescape_value(decoder)?;
+            if let Some(target) = attr_name.strip_suffix(AttributeValue::TYPE_ANNOTATION_SUFFIX) {
+                types.insert(target.to_string(), attr_value.into_owned());
+                continue;
             }
+            if attr_name.starts_with("xmlns") || attr_name.contains(':') {
+                show_warning(
+                    "Namespaces and prefixes",
+                    Some(&format!(
+                        "Found namespace declaration or prefixed attribute: {}",
+                        attr_name
+                    )),
+                );
+            }
+            attributes.push((attr_name.to_string(), attr_value.into_owned()));
+        }
+        for (name, value) in &attributes {
+            let value = match types.remove(name) {
+                Some(type_name) => AttributeValue::parse(&type_name, value)?,
+                None => AttributeValue::infer(value),
+            };
+            value.write(serializer, name)?;
+        }
+        if let Some(name) = types.keys().next() {
+            return Err(ConversionError::Malformed(format!(
+                "type annotation for missing attribute {}",
+                name
+            )));
         }
         Ok(())
     }
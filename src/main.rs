@@ -1,11 +1,14 @@
-use clap::{Arg, Command};
-use std::fs::File;
-use std::io::Read;
-use std::io::{self, BufWriter};
+mod cli;
+
+use clap::{Arg, ArgMatches, Command};
+use std::io;
+use std::path::Path;
 use xml2abx::XmlToAbxConverter;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let matches = Command::new("xml2abx")
+    let command = Command::new("xml2abx")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("input")
                 .help("Input XML file (use '-' for stdin)")
@@ -27,12 +30,68 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .arg(
             Arg::new("collapse-whitespace")
                 .long("collapse-whitespace")
-                .short('i')
                 .help("Collapse whitespace")
                 .action(clap::ArgAction::SetTrue),
         )
-        .get_matches();
+        .arg(
+            Arg::new("atomic-file")
+                .long("atomic-file")
+                .help(
+                    "Read and write files the way Android's AtomicFile does, \
+                     using and updating companions such as foo.xml.bak",
+                )
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .subcommand(cli::diff::command())
+        .subcommand(cli::textconv::command())
+        .subcommand(cli::merge_driver::command())
+        .subcommand(cli::edit::command())
+        .subcommand(cli::get::command())
+        .subcommand(cli::set::command())
+        .subcommand(cli::query::command())
+        .subcommand(cli::to_json::command())
+        .subcommand(cli::from_json::command())
+        .subcommand(cli::settings::command())
+        .subcommand(cli::packages::command())
+        .subcommand(cli::permissions::command())
+        .subcommand(cli::appops::command())
+        .subcommand(cli::carve::command());
+    #[cfg(feature = "backup")]
+    let command = command.subcommand(cli::backup::command());
+    let matches = command.get_matches();
+    cli::use_atomic_file(matches.get_flag("atomic-file"));
+
+    let result = match matches.subcommand() {
+        Some(("diff", sub)) => cli::diff::run(sub),
+        Some(("textconv", sub)) => cli::textconv::run(sub),
+        Some(("merge-driver", sub)) => cli::merge_driver::run(sub),
+        Some(("edit", sub)) => cli::edit::run(sub),
+        Some(("get", sub)) => cli::get::run(sub),
+        Some(("set", sub)) => cli::set::run(sub),
+        Some(("query", sub)) => cli::query::run(sub),
+        Some(("to-json", sub)) => cli::to_json::run(sub),
+        Some(("from-json", sub)) => cli::from_json::run(sub),
+        Some(("settings", sub)) => cli::settings::run(sub),
+        Some(("packages", sub)) => cli::packages::run(sub),
+        Some(("permissions", sub)) => cli::permissions::run(sub),
+        Some(("appops", sub)) => cli::appops::run(sub),
+        Some(("carve", sub)) => cli::carve::run(sub),
+        #[cfg(feature = "backup")]
+        Some(("backup", sub)) => cli::backup::run(sub),
+        _ => convert(&matches),
+    };
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
+fn convert(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
     let input_path = matches.get_one::<String>("input").unwrap();
     let output_path = matches.get_one::<String>("output");
     let in_place = matches.get_flag("in-place");
@@ -54,62 +113,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         std::process::exit(1);
     };
 
-    let result = if input_path == "-" {
-        let mut xml_content = String::new();
-        io::stdin().read_to_string(&mut xml_content)?;
+    // Read the input completely first, which also makes in-place
+    // conversion safe.
+    let xml_content = String::from_utf8(cli::read_input(input_path)?)?;
 
-        if let Some(ref output_path) = final_output_path {
-            if output_path == "-" {
-                XmlToAbxConverter::convert_from_string_with_options(
-                    &xml_content,
-                    io::stdout(),
-                    preserve_whitespace,
-                )
-            } else {
-                let file = File::create(output_path)?;
-                let writer = BufWriter::new(file);
-                XmlToAbxConverter::convert_from_string_with_options(
-                    &xml_content,
-                    writer,
-                    preserve_whitespace,
-                )
-            }
-        } else {
-            eprintln!("Error: Output path is required");
-            std::process::exit(1);
-        }
-    } else {
-        // for in-place editing, we need to read the file completely first
-
-        let xml_content = std::fs::read_to_string(input_path)?;
-
-        if let Some(ref output_path) = final_output_path {
-            if output_path == "-" {
-                XmlToAbxConverter::convert_from_string_with_options(
-                    &xml_content,
-                    io::stdout(),
-                    preserve_whitespace,
-                )
-            } else {
-                let file = File::create(output_path)?;
-                let writer = BufWriter::new(file);
-                XmlToAbxConverter::convert_from_string_with_options(
-                    &xml_content,
-                    writer,
-                    preserve_whitespace,
-                )
-            }
-        } else {
+    match final_output_path.as_deref() {
+        Some("-") => Ok(XmlToAbxConverter::convert_from_string_with_options(
+            &xml_content,
+            io::stdout(),
+            preserve_whitespace,
+        )?),
+        Some(output_path) => cli::write_atomically(Path::new(output_path), |writer| {
+            XmlToAbxConverter::convert_from_string_with_options(
+                &xml_content,
+                writer,
+                preserve_whitespace,
+            )?;
+            Ok(())
+        }),
+        None => {
             eprintln!("Error: Output path is required");
             std::process::exit(1);
         }
-    };
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
-        }
     }
 }
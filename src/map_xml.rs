@@ -0,0 +1,320 @@
+//! The `XmlUtils.writeMapXml` layout used by SharedPreferences and many
+//! platform files.
+//!
+//! A map is a `<map>` element with one child per entry, named by its `name`
+//! attribute:
+//!
+//! ```xml
+//! <map>
+//!     <string name="user">alice</string>
+//!     <int name="launches" value="3" />
+//!     <long name="last_sync" value="1700000000000" />
+//!     <boolean name="onboarded" value="true" />
+//!     <set name="tags">
+//!         <string>beta</string>
+//!     </set>
+//! </map>
+//! ```
+//!
+//! Values are written with the typed serializer calls `XmlUtils` uses
+//! (`attribute_int` for `<int>`, `attribute_long` for `<long>` and so on), so
+//! the ABX form matches files written by the platform.
+//!
+//! ```rust
+//! use std::collections::BTreeMap;
+//! use xml2abx::map_xml::{self, MapValue};
+//!
+//! let mut prefs = BTreeMap::new();
+//! prefs.insert("launches".to_string(), MapValue::Int(3));
+//! prefs.insert("user".to_string(), MapValue::from("alice"));
+//!
+//! let mut abx = Vec::new();
+//! map_xml::write_map_abx(&mut abx, &prefs).unwrap();
+//! assert_eq!(map_xml::read_map_abx(&abx[..]).unwrap(), prefs);
+//! ```
+
+use crate::parser::{AbxPullParser, TypedXmlPullParser, XmlPullParser};
+use crate::reader::AbxEvent;
+use crate::serializer::{TextXmlSerializer, TypedXmlSerializer};
+use crate::{BinaryXmlSerializer, ConversionError};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read, Write};
+
+/// A map entry value, one variant per `XmlUtils` value tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapValue {
+    /// `<null name="..." />`
+    Null,
+    /// `<string name="...">text</string>`
+    String(String),
+    /// `<int name="..." value="..." />`
+    Int(i32),
+    /// `<long name="..." value="..." />`
+    Long(i64),
+    /// `<float name="..." value="..." />`
+    Float(f32),
+    /// `<double name="..." value="..." />`
+    Double(f64),
+    /// `<boolean name="..." value="..." />`
+    Boolean(bool),
+    /// `<set name="...">` of `<string>` items, in file order.
+    Set(Vec<String>),
+}
+
+impl MapValue {
+    /// Tag the value is written under.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::String(_) => "string",
+            Self::Int(_) => "int",
+            Self::Long(_) => "long",
+            Self::Float(_) => "float",
+            Self::Double(_) => "double",
+            Self::Boolean(_) => "boolean",
+            Self::Set(_) => "set",
+        }
+    }
+}
+
+impl From<String> for MapValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+impl From<&str> for MapValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+impl From<i32> for MapValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+impl From<i64> for MapValue {
+    fn from(value: i64) -> Self {
+        Self::Long(value)
+    }
+}
+impl From<f32> for MapValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+impl From<f64> for MapValue {
+    fn from(value: f64) -> Self {
+        Self::Double(value)
+    }
+}
+impl From<bool> for MapValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+impl From<Vec<String>> for MapValue {
+    fn from(value: Vec<String>) -> Self {
+        Self::Set(value)
+    }
+}
+
+/// Writes `map` as a `<map>` element, like `XmlUtils.writeMapXml(map, name,
+/// out)`. `name` becomes the element's `name` attribute, for maps nested in
+/// a larger document.
+pub fn write_map<S: TypedXmlSerializer + ?Sized>(
+    serializer: &mut S,
+    map: &BTreeMap<String, MapValue>,
+    name: Option<&str>,
+) -> Result<(), ConversionError> {
+    serializer.start_tag("map")?;
+    if let Some(name) = name {
+        serializer.attribute("name", name)?;
+    }
+    for (key, value) in map {
+        write_value(serializer, value, Some(key))?;
+    }
+    serializer.end_tag("map")
+}
+
+/// Writes a single value element, like `XmlUtils.writeValueXml`.
+pub fn write_value<S: TypedXmlSerializer + ?Sized>(
+    serializer: &mut S,
+    value: &MapValue,
+    name: Option<&str>,
+) -> Result<(), ConversionError> {
+    let tag = value.tag();
+    serializer.start_tag(tag)?;
+    if let Some(name) = name {
+        serializer.attribute("name", name)?;
+    }
+    match value {
+        MapValue::Null => {}
+        MapValue::String(text) => serializer.text(text)?,
+        MapValue::Int(v) => serializer.attribute_int("value", *v)?,
+        MapValue::Long(v) => serializer.attribute_long("value", *v)?,
+        MapValue::Float(v) => serializer.attribute_float("value", *v)?,
+        MapValue::Double(v) => serializer.attribute_double("value", *v)?,
+        MapValue::Boolean(v) => serializer.attribute_boolean("value", *v)?,
+        MapValue::Set(items) => {
+            for item in items {
+                serializer.start_tag("string")?;
+                serializer.text(item)?;
+                serializer.end_tag("string")?;
+            }
+        }
+    }
+    serializer.end_tag(tag)
+}
+
+/// Writes a complete ABX document whose root is the `<map>` element.
+pub fn write_map_abx<W: Write>(
+    writer: W,
+    map: &BTreeMap<String, MapValue>,
+) -> Result<(), ConversionError> {
+    let mut serializer = BinaryXmlSerializer::new(writer)?;
+    write_map_document(&mut serializer, map)
+}
+
+/// Writes a complete text XML document whose root is the `<map>` element,
+/// indented the way SharedPreferences files are.
+pub fn write_map_xml<W: Write>(
+    writer: W,
+    map: &BTreeMap<String, MapValue>,
+) -> Result<(), ConversionError> {
+    let mut serializer = TextXmlSerializer::with_indent(writer, "    ");
+    write_map_document(&mut serializer, map)
+}
+
+fn write_map_document<S: TypedXmlSerializer>(
+    serializer: &mut S,
+    map: &BTreeMap<String, MapValue>,
+) -> Result<(), ConversionError> {
+    serializer.start_document()?;
+    write_map(serializer, map, None)?;
+    serializer.end_document()
+}
+
+/// Reads the `<map>` element whose start tag the parser is positioned on,
+/// leaving the parser on its end tag. Entries without a `name` are rejected.
+pub fn read_map<P: TypedXmlPullParser + ?Sized>(
+    parser: &mut P,
+) -> Result<BTreeMap<String, MapValue>, ConversionError> {
+    expect_tag(parser, "map")?;
+    let mut map = BTreeMap::new();
+    let depth = parser.depth();
+    while parser.next_element_within(depth)? {
+        let (name, value) = read_value(parser)?;
+        let name = name.ok_or_else(|| ConversionError::MissingAttribute("name".to_string()))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+/// Reads the value element whose start tag the parser is positioned on,
+/// returning its `name` attribute and value, like
+/// `XmlUtils.readThisValueXml`. Leaves the parser on the end tag.
+pub fn read_value<P: TypedXmlPullParser + ?Sized>(
+    parser: &mut P,
+) -> Result<(Option<String>, MapValue), ConversionError> {
+    let tag = parser
+        .name()
+        .ok_or_else(|| ConversionError::Malformed("expected a value element".to_string()))?
+        .to_string();
+    let name = parser.get_attribute_value("name");
+    let value = match tag.as_str() {
+        "null" => MapValue::Null,
+        "string" => MapValue::String(read_text(parser)?),
+        "int" => MapValue::Int(parser.get_attribute_int("value")?),
+        "long" => MapValue::Long(parser.get_attribute_long("value")?),
+        "float" => MapValue::Float(parser.get_attribute_float("value")?),
+        "double" => MapValue::Double(parser.get_attribute_double("value")?),
+        "boolean" => MapValue::Boolean(parser.get_attribute_boolean("value")?),
+        "set" => {
+            let mut items = Vec::new();
+            let depth = parser.depth();
+            while parser.next_element_within(depth)? {
+                expect_tag(parser, "string")?;
+                items.push(read_text(parser)?);
+            }
+            return Ok((name, MapValue::Set(items)));
+        }
+        _ => {
+            return Err(ConversionError::Malformed(format!(
+                "unknown value tag <{}>",
+                tag
+            )));
+        }
+    };
+    if tag != "string" {
+        parser.skip_current_tag()?;
+    }
+    Ok((name, value))
+}
+
+/// Reads an ABX document whose root is a `<map>` element.
+pub fn read_map_abx<R: Read>(reader: R) -> Result<BTreeMap<String, MapValue>, ConversionError> {
+    read_map_document(&mut AbxPullParser::new(reader)?)
+}
+
+/// Reads a text XML document whose root is a `<map>` element.
+pub fn read_map_xml<R: BufRead>(reader: R) -> Result<BTreeMap<String, MapValue>, ConversionError> {
+    read_map_document(&mut XmlPullParser::new(reader))
+}
+
+fn read_map_document<P: TypedXmlPullParser>(
+    parser: &mut P,
+) -> Result<BTreeMap<String, MapValue>, ConversionError> {
+    loop {
+        match parser.next()? {
+            Some(AbxEvent::StartTag { .. }) => return read_map(parser),
+            None | Some(AbxEvent::EndDocument) => {
+                return Err(ConversionError::Malformed(
+                    "document has no root element".to_string(),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+pub(crate) fn expect_tag<P: TypedXmlPullParser + ?Sized>(
+    parser: &P,
+    expected: &str,
+) -> Result<(), ConversionError> {
+    match parser.name() {
+        Some(name) if name == expected => Ok(()),
+        Some(name) => Err(ConversionError::Malformed(format!(
+            "expected <{}>, found <{}>",
+            expected, name
+        ))),
+        None => Err(ConversionError::Malformed(format!(
+            "expected <{}>",
+            expected
+        ))),
+    }
+}
+
+// Collects the text of the current element up to its end tag.
+pub(crate) fn read_text<P: TypedXmlPullParser + ?Sized>(
+    parser: &mut P,
+) -> Result<String, ConversionError> {
+    let mut text = String::new();
+    loop {
+        match parser.next()? {
+            Some(AbxEvent::Text(t)) => text.push_str(t),
+            Some(AbxEvent::EndTag(_)) => return Ok(text),
+            Some(AbxEvent::StartTag { name, .. }) => {
+                return Err(ConversionError::Malformed(format!(
+                    "unexpected <{}> in text value",
+                    name
+                )));
+            }
+            None | Some(AbxEvent::EndDocument) => {
+                return Err(ConversionError::Malformed(
+                    "unexpected end of document".to_string(),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+}
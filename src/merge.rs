@@ -0,0 +1,298 @@
+//! Three-way structural merge of documents.
+//!
+//! Elements are matched the same way as in [`crate::diff`]. A change made on
+//! only one side is taken; a change made differently on both sides is a
+//! conflict, which keeps "ours" (or the modified side for modify/delete
+//! conflicts) and is recorded both in [`MergeResult::conflicts`] and as a
+//! comment inside the affected element.
+
+use crate::diff::{KeyAttribute, SiblingKey, keyed_children};
+use crate::document::{AbxDocument, Element, Node};
+use crate::value::{Attribute, AttributeValue};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    Attribute {
+        path: String,
+        name: String,
+        base: Option<AttributeValue>,
+        ours: Option<AttributeValue>,
+        theirs: Option<AttributeValue>,
+    },
+    Text {
+        path: String,
+        base: String,
+        ours: String,
+        theirs: String,
+    },
+    Element {
+        path: String,
+        reason: &'static str,
+    },
+}
+
+fn describe(value: &Option<AttributeValue>) -> String {
+    match value {
+        Some(value) => format!("{:?} ({})", value.to_string(), value.type_name()),
+        None => "<absent>".to_string(),
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Attribute {
+                path,
+                name,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "{}/@{}: base {}, ours {}, theirs {}",
+                path,
+                name,
+                describe(base),
+                describe(ours),
+                describe(theirs)
+            ),
+            Conflict::Text {
+                path,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "{}/text(): base {:?}, ours {:?}, theirs {:?}",
+                path,
+                base.trim(),
+                ours.trim(),
+                theirs.trim()
+            ),
+            Conflict::Element { path, reason } => write!(f, "{}: {}", path, reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    pub document: AbxDocument,
+    pub conflicts: Vec<Conflict>,
+}
+
+pub fn merge(
+    base: &AbxDocument,
+    ours: &AbxDocument,
+    theirs: &AbxDocument,
+    keys: &[KeyAttribute],
+) -> MergeResult {
+    if ours == theirs || theirs == base {
+        return MergeResult {
+            document: ours.clone(),
+            conflicts: Vec::new(),
+        };
+    }
+    if ours == base {
+        return MergeResult {
+            document: theirs.clone(),
+            conflicts: Vec::new(),
+        };
+    }
+    let mut document = ours.clone();
+    let mut conflicts = Vec::new();
+    match (ours.root(), theirs.root()) {
+        (Some(o), Some(t)) if o.name == t.name => {
+            let b = base.root().filter(|b| b.name == o.name);
+            let merged = merge_element(&format!("/{}", o.name), b, o, t, keys, &mut conflicts);
+            if let Some(root) = document.root_mut() {
+                *root = merged;
+            }
+        }
+        (o, _) => conflicts.push(Conflict::Element {
+            path: format!("/{}", o.map(|o| o.name.as_str()).unwrap_or_default()),
+            reason: "root element changed on both sides",
+        }),
+    }
+    MergeResult {
+        document,
+        conflicts,
+    }
+}
+
+fn pick<'a, T: PartialEq + ?Sized>(base: &'a T, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+fn merge_element(
+    path: &str,
+    base: Option<&Element>,
+    ours: &Element,
+    theirs: &Element,
+    keys: &[KeyAttribute],
+    conflicts: &mut Vec<Conflict>,
+) -> Element {
+    let mut local = Vec::new();
+
+    let mut names: Vec<&str> = ours.attributes.iter().map(|a| a.name.as_str()).collect();
+    for attr in &theirs.attributes {
+        if ours.attribute(&attr.name).is_none() {
+            names.push(&attr.name);
+        }
+    }
+    let mut attributes = Vec::new();
+    for name in names {
+        let b = base.and_then(|b| b.attribute(name));
+        let o = ours.attribute(name);
+        let t = theirs.attribute(name);
+        let value = match pick(&b, &o, &t) {
+            Some(value) => *value,
+            None => {
+                local.push(Conflict::Attribute {
+                    path: path.to_string(),
+                    name: name.to_string(),
+                    base: b.cloned(),
+                    ours: o.cloned(),
+                    theirs: t.cloned(),
+                });
+                o
+            }
+        };
+        if let Some(value) = value {
+            attributes.push(Attribute::new(name, value.clone()));
+        }
+    }
+
+    let base_siblings = base.map(|b| keyed_children(b, keys)).unwrap_or_default();
+    let base_map: HashMap<&SiblingKey, &Element> =
+        base_siblings.iter().map(|s| (&s.key, s.element)).collect();
+    let their_siblings = keyed_children(theirs, keys);
+    let their_map: HashMap<&SiblingKey, &Element> =
+        their_siblings.iter().map(|s| (&s.key, s.element)).collect();
+    let our_siblings = keyed_children(ours, keys);
+
+    let mut children: Vec<(Option<SiblingKey>, Node)> = Vec::new();
+    let mut our_elements = our_siblings.iter();
+    for node in &ours.children {
+        if !matches!(node, Node::Element(_)) {
+            children.push((None, node.clone()));
+            continue;
+        }
+        let Some(sibling) = our_elements.next() else {
+            continue;
+        };
+        let child_path = format!("{}/{}", path, sibling.segment);
+        let b = base_map.get(&sibling.key).copied();
+        let merged = match (b, their_map.get(&sibling.key)) {
+            (_, Some(t)) => Some(merge_element(
+                &child_path,
+                b,
+                sibling.element,
+                t,
+                keys,
+                conflicts,
+            )),
+            (Some(b), None) if b == sibling.element => None,
+            (Some(_), None) => {
+                local.push(Conflict::Element {
+                    path: child_path,
+                    reason: "modified in ours, deleted in theirs",
+                });
+                Some(sibling.element.clone())
+            }
+            (None, None) => Some(sibling.element.clone()),
+        };
+        if let Some(merged) = merged {
+            children.push((Some(sibling.key.clone()), Node::Element(merged)));
+        }
+    }
+
+    let our_keys: Vec<&SiblingKey> = our_siblings.iter().map(|s| &s.key).collect();
+    for (i, sibling) in their_siblings.iter().enumerate() {
+        if our_keys.contains(&&sibling.key) {
+            continue;
+        }
+        if let Some(b) = base_map.get(&sibling.key) {
+            if *b == sibling.element {
+                continue;
+            }
+            local.push(Conflict::Element {
+                path: format!("{}/{}", path, sibling.segment),
+                reason: "deleted in ours, modified in theirs",
+            });
+        }
+        // Insert after the nearest preceding sibling that survived the merge.
+        let position = their_siblings[..i]
+            .iter()
+            .rev()
+            .find_map(|prev| {
+                children
+                    .iter()
+                    .position(|(key, _)| key.as_ref() == Some(&prev.key))
+                    .map(|p| p + 1)
+            })
+            .or_else(|| children.iter().position(|(key, _)| key.is_some()))
+            .unwrap_or(children.len());
+        children.insert(
+            position,
+            (
+                Some(sibling.key.clone()),
+                Node::Element(sibling.element.clone()),
+            ),
+        );
+    }
+
+    let base_text = base.map(|b| b.text()).unwrap_or_default();
+    let our_text = ours.text();
+    let their_text = theirs.text();
+    if our_text != their_text && their_text != base_text {
+        if our_text == base_text {
+            let position = children
+                .iter()
+                .position(|(_, node)| {
+                    matches!(node, Node::Text(_) | Node::CData(_) | Node::EntityRef(_))
+                })
+                .unwrap_or(children.len());
+            children.retain(|(_, node)| {
+                !matches!(node, Node::Text(_) | Node::CData(_) | Node::EntityRef(_))
+            });
+            let position = position.min(children.len());
+            let text_nodes = theirs
+                .children
+                .iter()
+                .filter(|node| matches!(node, Node::Text(_) | Node::CData(_) | Node::EntityRef(_)))
+                .map(|node| (None, node.clone()));
+            children.splice(position..position, text_nodes);
+        } else {
+            local.push(Conflict::Text {
+                path: path.to_string(),
+                base: base_text,
+                ours: our_text,
+                theirs: their_text,
+            });
+        }
+    }
+
+    let mut nodes: Vec<Node> = local
+        .iter()
+        // Values may contain "--", which a comment cannot.
+        .map(|c| {
+            let text = c.to_string().replace("--", "- -");
+            Node::Comment(format!(" xml2abx merge conflict: {} ", text))
+        })
+        .collect();
+    nodes.extend(children.into_iter().map(|(_, node)| node));
+    conflicts.extend(local);
+    Element {
+        name: ours.name.clone(),
+        attributes,
+        children: nodes,
+    }
+}
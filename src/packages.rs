@@ -0,0 +1,386 @@
+//! Reading the package manager's `packages.xml`.
+//!
+//! [`read_packages`] collects, per `<package>`, what `Settings.writePackageLPr`
+//! stores: code path, version code, app id or shared user id, installer,
+//! the `ft`/`it`/`ut` timestamps (milliseconds since the epoch, written as
+//! long-hex), install permissions from `<perms>`, certificate references
+//! from `<sigs>` and the signing key set. Permissions declared in the
+//! top-level `<permissions>` and `<permission-trees>` sections are attached to
+//! the package that owns them, and shared user ids are resolved to the
+//! `<shared-user>` names.
+//!
+//! ```rust
+//! use xml2abx::{AbxDocument, packages};
+//!
+//! let doc = AbxDocument::from_xml_str(r#"<packages>
+//!     <package name="com.foo" codePath="/data/app/com.foo-1" ft="18c5a3f1e20" ut="18c5a3f1e20" version="7" sharedUserId="1000" />
+//!     <shared-user name="android.uid.system" userId="1000" />
+//! </packages>"#).unwrap();
+//! let report = packages::read_packages(&doc).unwrap();
+//! assert_eq!(report.packages[0].shared_user.as_deref(), Some("android.uid.system"));
+//! assert_eq!(packages::format_time(report.packages[0].last_update_time.unwrap()), "2023-12-11 18:58:55");
+//! ```
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element};
+use crate::value::AttributeValue;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PackageInfo {
+    pub name: String,
+    pub code_path: Option<String>,
+    pub version_code: Option<i64>,
+    /// `userId`, present when the package has its own app id.
+    pub app_id: Option<i32>,
+    /// `sharedUserId`, present when the package runs under a shared user.
+    pub shared_user_id: Option<i32>,
+    /// Name of the `<shared-user>` with `shared_user_id`.
+    pub shared_user: Option<String>,
+    pub installer: Option<String>,
+    /// `ft`: last modification of the code path.
+    pub timestamp: Option<i64>,
+    /// `it`: first install, only written by older releases.
+    pub first_install_time: Option<i64>,
+    /// `ut`: last update.
+    pub last_update_time: Option<i64>,
+    /// Permissions defined by the package.
+    pub declared_permissions: Vec<String>,
+    pub install_permissions: Vec<PermissionGrant>,
+    pub signatures: Option<Signatures>,
+    /// `identifier` of `<proper-signing-keyset>`.
+    pub signing_keyset: Option<i64>,
+}
+
+impl PackageInfo {
+    /// Uid for user 0: the app id, or the shared user id.
+    pub fn uid(&self) -> Option<i32> {
+        self.app_id.or(self.shared_user_id)
+    }
+
+    /// `it` when present, else `ft`, which is the best available first
+    /// install time on releases that keep it per user.
+    pub fn install_time(&self) -> Option<i64> {
+        self.first_install_time.or(self.timestamp)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermissionGrant {
+    pub name: String,
+    pub granted: bool,
+}
+
+/// The `<sigs>` element: certificates are stored once per file and later
+/// occurrences only refer to them by `index`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Signatures {
+    pub scheme_version: Option<i32>,
+    pub cert_indexes: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SharedUser {
+    pub name: String,
+    pub user_id: i32,
+    pub install_permissions: Vec<PermissionGrant>,
+    pub signatures: Option<Signatures>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PackagesReport {
+    pub packages: Vec<PackageInfo>,
+    pub shared_users: Vec<SharedUser>,
+}
+
+pub fn read_packages(document: &AbxDocument) -> Result<PackagesReport, ConversionError> {
+    let root = match document.root() {
+        Some(root) if root.name == "packages" => root,
+        Some(root) => {
+            return Err(ConversionError::Malformed(format!(
+                "expected <packages> root, found <{}>",
+                root.name
+            )));
+        }
+        None => {
+            return Err(ConversionError::Malformed(
+                "document has no root element".to_string(),
+            ));
+        }
+    };
+
+    let mut declared: HashMap<String, Vec<String>> = HashMap::new();
+    for section in root
+        .elements()
+        .filter(|e| e.name == "permissions" || e.name == "permission-trees")
+    {
+        for item in section.elements().filter(|e| e.name == "item") {
+            if let (Some(name), Some(package)) = (text(item, "name"), text(item, "package")) {
+                declared.entry(package).or_default().push(name);
+            }
+        }
+    }
+
+    let shared_users = root
+        .elements()
+        .filter(|e| e.name == "shared-user")
+        .map(|e| {
+            Ok(SharedUser {
+                name: required(e, "name")?,
+                user_id: e
+                    .attribute("userId")
+                    .ok_or_else(|| ConversionError::MissingAttribute("userId".to_string()))?
+                    .as_int()?,
+                install_permissions: permissions(e)?,
+                signatures: signatures(e)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ConversionError>>()?;
+
+    let packages = root
+        .elements()
+        .filter(|e| e.name == "package")
+        .map(|e| {
+            let name = required(e, "name")?;
+            let shared_user_id = int(e, "sharedUserId")?;
+            Ok(PackageInfo {
+                code_path: text(e, "codePath"),
+                version_code: long(e, "version")?,
+                app_id: int(e, "userId")?,
+                shared_user_id,
+                shared_user: shared_users
+                    .iter()
+                    .find(|u| Some(u.user_id) == shared_user_id)
+                    .map(|u| u.name.clone()),
+                installer: text(e, "installer"),
+                timestamp: time(e, "ft")?,
+                first_install_time: time(e, "it")?,
+                last_update_time: time(e, "ut")?,
+                declared_permissions: declared.remove(&name).unwrap_or_default(),
+                install_permissions: permissions(e)?,
+                signatures: signatures(e)?,
+                signing_keyset: match e.child("proper-signing-keyset") {
+                    Some(keyset) => long(keyset, "identifier")?,
+                    None => None,
+                },
+                name,
+            })
+        })
+        .collect::<Result<Vec<_>, ConversionError>>()?;
+
+    Ok(PackagesReport {
+        packages,
+        shared_users,
+    })
+}
+
+/// Formats milliseconds since the epoch as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_time(millis: i64) -> String {
+    let secs = millis.div_euclid(1000);
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Howard Hinnant's civil_from_days.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+pub fn to_json(report: &PackagesReport) -> Value {
+    let time_json = |t: Option<i64>| t.map(|t| json!({ "millis": t, "utc": format_time(t) }));
+    json!({
+        "packages": report.packages.iter().map(|p| json!({
+            "name": p.name,
+            "codePath": p.code_path,
+            "versionCode": p.version_code,
+            "uid": p.uid(),
+            "appId": p.app_id,
+            "sharedUserId": p.shared_user_id,
+            "sharedUser": p.shared_user,
+            "installer": p.installer,
+            "timestamp": time_json(p.timestamp),
+            "firstInstallTime": time_json(p.first_install_time),
+            "lastUpdateTime": time_json(p.last_update_time),
+            "declaredPermissions": p.declared_permissions,
+            "installPermissions": grants_json(&p.install_permissions),
+            "signatures": p.signatures.as_ref().map(signatures_json),
+            "signingKeySet": p.signing_keyset,
+        })).collect::<Vec<_>>(),
+        "sharedUsers": report.shared_users.iter().map(|u| json!({
+            "name": u.name,
+            "userId": u.user_id,
+            "installPermissions": grants_json(&u.install_permissions),
+            "signatures": u.signatures.as_ref().map(signatures_json),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// One row per package. With `verbose`, each row is followed by the
+/// package's permissions and signing references.
+pub fn render_text(report: &PackagesReport, verbose: bool) -> String {
+    let header = [
+        "PACKAGE",
+        "UID",
+        "VERSION",
+        "SHARED USER",
+        "INSTALLED",
+        "UPDATED",
+        "CODE PATH",
+    ];
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    let rows: Vec<[String; 7]> = report
+        .packages
+        .iter()
+        .map(|p| {
+            [
+                p.name.clone(),
+                or_dash(p.uid().map(|v| v.to_string())),
+                or_dash(p.version_code.map(|v| v.to_string())),
+                or_dash(p.shared_user.clone()),
+                or_dash(p.install_time().map(format_time)),
+                or_dash(p.last_update_time.map(format_time)),
+                or_dash(p.code_path.clone()),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let write_row = |out: &mut String, cells: &[&str]| {
+        let last = cells.len() - 1;
+        for (i, cell) in cells.iter().enumerate() {
+            if i == last {
+                let _ = writeln!(out, "{}", cell);
+            } else {
+                let _ = write!(out, "{:width$}  ", cell, width = widths[i]);
+            }
+        }
+    };
+    write_row(&mut out, &header);
+    for (package, row) in report.packages.iter().zip(&rows) {
+        write_row(&mut out, &row.each_ref().map(String::as_str));
+        if verbose {
+            render_details(&mut out, package);
+        }
+    }
+    out
+}
+
+fn render_details(out: &mut String, package: &PackageInfo) {
+    if let Some(installer) = &package.installer {
+        let _ = writeln!(out, "    installer: {}", installer);
+    }
+    for name in &package.declared_permissions {
+        let _ = writeln!(out, "    declares: {}", name);
+    }
+    for grant in &package.install_permissions {
+        let state = if grant.granted { "granted" } else { "denied" };
+        let _ = writeln!(out, "    permission: {} ({})", grant.name, state);
+    }
+    if let Some(sigs) = &package.signatures {
+        let indexes: Vec<String> = sigs.cert_indexes.iter().map(i32::to_string).collect();
+        let _ = write!(out, "    certificates: {}", indexes.join(", "));
+        if let Some(scheme) = sigs.scheme_version {
+            let _ = write!(out, " (scheme v{})", scheme);
+        }
+        let _ = writeln!(out);
+    }
+    if let Some(keyset) = package.signing_keyset {
+        let _ = writeln!(out, "    signing key set: {}", keyset);
+    }
+}
+
+fn grants_json(grants: &[PermissionGrant]) -> Value {
+    Value::Array(
+        grants
+            .iter()
+            .map(|g| json!({ "name": g.name, "granted": g.granted }))
+            .collect(),
+    )
+}
+
+fn signatures_json(sigs: &Signatures) -> Value {
+    json!({ "schemeVersion": sigs.scheme_version, "certIndexes": sigs.cert_indexes })
+}
+
+fn text(element: &Element, name: &str) -> Option<String> {
+    element.attribute(name).map(|v| v.to_string())
+}
+
+fn required(element: &Element, name: &str) -> Result<String, ConversionError> {
+    text(element, name).ok_or_else(|| ConversionError::MissingAttribute(name.to_string()))
+}
+
+fn int(element: &Element, name: &str) -> Result<Option<i32>, ConversionError> {
+    element
+        .attribute(name)
+        .map(AttributeValue::as_int)
+        .transpose()
+}
+
+fn long(element: &Element, name: &str) -> Result<Option<i64>, ConversionError> {
+    element
+        .attribute(name)
+        .map(AttributeValue::as_long)
+        .transpose()
+}
+
+fn time(element: &Element, name: &str) -> Result<Option<i64>, ConversionError> {
+    element
+        .attribute(name)
+        .map(AttributeValue::as_long_hex)
+        .transpose()
+}
+
+// `<perms><item name granted /></perms>`; `granted` defaults to true.
+fn permissions(element: &Element) -> Result<Vec<PermissionGrant>, ConversionError> {
+    let Some(perms) = element.child("perms") else {
+        return Ok(Vec::new());
+    };
+    perms
+        .elements()
+        .filter(|e| e.name == "item")
+        .map(|item| {
+            Ok(PermissionGrant {
+                name: required(item, "name")?,
+                granted: item
+                    .attribute("granted")
+                    .map_or(Ok(true), AttributeValue::as_bool)?,
+            })
+        })
+        .collect()
+}
+
+fn signatures(element: &Element) -> Result<Option<Signatures>, ConversionError> {
+    let Some(sigs) = element.child("sigs") else {
+        return Ok(None);
+    };
+    Ok(Some(Signatures {
+        scheme_version: int(sigs, "schemeVersion")?,
+        cert_indexes: sigs
+            .elements()
+            .filter(|e| e.name == "cert")
+            .filter_map(|cert| int(cert, "index").transpose())
+            .collect::<Result<_, _>>()?,
+    }))
+}
@@ -0,0 +1,398 @@
+//! The `TypedXmlPullParser` interface shared by the binary and text readers.
+//!
+//! Mirrors Android's `TypedXmlPullParser`: [`AbxPullParser`] reads ABX and
+//! [`XmlPullParser`] reads text XML, and both answer the same typed attribute
+//! calls. Values stored with a compatible ABX type are returned directly;
+//! anything else (including every attribute of a text document) is parsed
+//! from its text form, see [`AttributeValue::as_int`] and friends.
+
+use crate::ConversionError;
+use crate::reader::{AbxEvent, AbxReader};
+use crate::value::{Attribute, AttributeValue};
+use quick_xml::Reader;
+use quick_xml::encoding::Decoder;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{BufRead, Read};
+
+pub trait TypedXmlPullParser {
+    /// Advances to the next start tag, end tag or text event, like
+    /// `XmlPullParser.next()`. Comments, processing instructions and doctype
+    /// declarations are skipped, and adjacent text, CDATA and entity
+    /// references are merged into one [`AbxEvent::Text`]. Returns `None` once
+    /// the input is exhausted.
+    fn next(&mut self) -> Result<Option<&AbxEvent>, ConversionError>;
+    /// Advances to the next event without skipping or merging anything.
+    fn next_token(&mut self) -> Result<Option<&AbxEvent>, ConversionError>;
+    /// The event the parser is positioned on.
+    fn event(&self) -> Option<&AbxEvent>;
+    /// Element depth, counted the way `XmlPullParser.getDepth()` does: a
+    /// start tag and its matching end tag report the same depth.
+    fn depth(&self) -> usize;
+
+    /// Tag name of the current start or end tag.
+    fn name(&self) -> Option<&str> {
+        match self.event() {
+            Some(AbxEvent::StartTag { name, .. }) | Some(AbxEvent::EndTag(name)) => Some(name),
+            _ => None,
+        }
+    }
+    fn text(&self) -> Option<&str> {
+        match self.event() {
+            Some(AbxEvent::Text(t))
+            | Some(AbxEvent::CdSect(t))
+            | Some(AbxEvent::IgnorableWhitespace(t))
+            | Some(AbxEvent::Comment(t))
+            | Some(AbxEvent::ProcessingInstruction(t))
+            | Some(AbxEvent::DocDecl(t))
+            | Some(AbxEvent::EntityRef(t)) => Some(t),
+            _ => None,
+        }
+    }
+    /// Attributes of the current start tag; empty for any other event.
+    fn attributes(&self) -> &[Attribute] {
+        match self.event() {
+            Some(AbxEvent::StartTag { attributes, .. }) => attributes,
+            _ => &[],
+        }
+    }
+    fn get_attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes()
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| &a.value)
+    }
+    fn get_attribute_value(&self, name: &str) -> Option<String> {
+        self.get_attribute(name).map(|v| v.to_string())
+    }
+
+    fn get_attribute_bytes_hex(&self, name: &str) -> Result<Vec<u8>, ConversionError> {
+        required(self, name)?.as_bytes_hex()
+    }
+    fn get_attribute_bytes_base64(&self, name: &str) -> Result<Vec<u8>, ConversionError> {
+        required(self, name)?.as_bytes_base64()
+    }
+    fn get_attribute_int(&self, name: &str) -> Result<i32, ConversionError> {
+        required(self, name)?.as_int()
+    }
+    fn get_attribute_int_hex(&self, name: &str) -> Result<i32, ConversionError> {
+        required(self, name)?.as_int_hex()
+    }
+    fn get_attribute_long(&self, name: &str) -> Result<i64, ConversionError> {
+        required(self, name)?.as_long()
+    }
+    fn get_attribute_long_hex(&self, name: &str) -> Result<i64, ConversionError> {
+        required(self, name)?.as_long_hex()
+    }
+    fn get_attribute_float(&self, name: &str) -> Result<f32, ConversionError> {
+        required(self, name)?.as_float()
+    }
+    fn get_attribute_double(&self, name: &str) -> Result<f64, ConversionError> {
+        required(self, name)?.as_double()
+    }
+    fn get_attribute_boolean(&self, name: &str) -> Result<bool, ConversionError> {
+        required(self, name)?.as_bool()
+    }
+
+    // Like the Java overloads taking a default, these fall back on a missing
+    // or unparsable value.
+
+    fn get_attribute_bytes_hex_or(&self, name: &str, default: Vec<u8>) -> Vec<u8> {
+        self.get_attribute_bytes_hex(name).unwrap_or(default)
+    }
+    fn get_attribute_bytes_base64_or(&self, name: &str, default: Vec<u8>) -> Vec<u8> {
+        self.get_attribute_bytes_base64(name).unwrap_or(default)
+    }
+    fn get_attribute_int_or(&self, name: &str, default: i32) -> i32 {
+        self.get_attribute_int(name).unwrap_or(default)
+    }
+    fn get_attribute_int_hex_or(&self, name: &str, default: i32) -> i32 {
+        self.get_attribute_int_hex(name).unwrap_or(default)
+    }
+    fn get_attribute_long_or(&self, name: &str, default: i64) -> i64 {
+        self.get_attribute_long(name).unwrap_or(default)
+    }
+    fn get_attribute_long_hex_or(&self, name: &str, default: i64) -> i64 {
+        self.get_attribute_long_hex(name).unwrap_or(default)
+    }
+    fn get_attribute_float_or(&self, name: &str, default: f32) -> f32 {
+        self.get_attribute_float(name).unwrap_or(default)
+    }
+    fn get_attribute_double_or(&self, name: &str, default: f64) -> f64 {
+        self.get_attribute_double(name).unwrap_or(default)
+    }
+    fn get_attribute_boolean_or(&self, name: &str, default: bool) -> bool {
+        self.get_attribute_boolean(name).unwrap_or(default)
+    }
+
+    /// Advances to the next direct child of the element at `outer_depth`,
+    /// returning `false` at its end tag. Equivalent to
+    /// `XmlUtils.nextElementWithin()`:
+    ///
+    /// ```ignore
+    /// let depth = parser.depth();
+    /// while parser.next_element_within(depth)? {
+    ///     // parser is on a child start tag
+    /// }
+    /// ```
+    fn next_element_within(&mut self, outer_depth: usize) -> Result<bool, ConversionError> {
+        loop {
+            let (is_start, is_end) = match self.next()? {
+                None | Some(AbxEvent::EndDocument) => return Ok(false),
+                Some(AbxEvent::StartTag { .. }) => (true, false),
+                Some(AbxEvent::EndTag(_)) => (false, true),
+                Some(_) => (false, false),
+            };
+            if is_end && self.depth() == outer_depth {
+                return Ok(false);
+            }
+            if is_start && self.depth() == outer_depth + 1 {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Skips to the end tag matching the current start tag.
+    fn skip_current_tag(&mut self) -> Result<(), ConversionError> {
+        let depth = self.depth();
+        while self.next_element_within(depth)? {}
+        Ok(())
+    }
+}
+
+fn required<'a, P: TypedXmlPullParser + ?Sized>(
+    parser: &'a P,
+    name: &str,
+) -> Result<&'a AttributeValue, ConversionError> {
+    parser
+        .get_attribute(name)
+        .ok_or_else(|| ConversionError::MissingAttribute(name.to_string()))
+}
+
+/// Current-event bookkeeping shared by both parsers.
+#[derive(Default)]
+struct Cursor {
+    current: Option<AbxEvent>,
+    lookahead: Option<AbxEvent>,
+    depth: usize,
+}
+
+impl Cursor {
+    fn advance(&mut self, event: Option<AbxEvent>) -> Option<&AbxEvent> {
+        if let Some(AbxEvent::EndTag(_)) = self.current {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        if let Some(AbxEvent::StartTag { .. }) = event {
+            self.depth += 1;
+        }
+        self.current = event;
+        self.current.as_ref()
+    }
+
+    fn next_token(
+        &mut self,
+        mut read: impl FnMut() -> Result<Option<AbxEvent>, ConversionError>,
+    ) -> Result<Option<&AbxEvent>, ConversionError> {
+        let event = match self.lookahead.take() {
+            Some(event) => Some(event),
+            None => read()?,
+        };
+        Ok(self.advance(event))
+    }
+
+    fn next(
+        &mut self,
+        mut read: impl FnMut() -> Result<Option<AbxEvent>, ConversionError>,
+    ) -> Result<Option<&AbxEvent>, ConversionError> {
+        let mut text: Option<String> = None;
+        loop {
+            let event = match self.lookahead.take() {
+                Some(event) => Some(event),
+                None => read()?,
+            };
+            match event {
+                Some(AbxEvent::Text(t))
+                | Some(AbxEvent::CdSect(t))
+                | Some(AbxEvent::IgnorableWhitespace(t)) => {
+                    text.get_or_insert_default().push_str(&t)
+                }
+                Some(AbxEvent::EntityRef(name)) => text
+                    .get_or_insert_default()
+                    .push_str(&resolve_entity(&name)),
+                Some(AbxEvent::StartDocument)
+                | Some(AbxEvent::Comment(_))
+                | Some(AbxEvent::ProcessingInstruction(_))
+                | Some(AbxEvent::DocDecl(_)) => {}
+                other => {
+                    return Ok(match text {
+                        Some(text) => {
+                            self.lookahead = other;
+                            self.advance(Some(AbxEvent::Text(text)))
+                        }
+                        None => self.advance(other),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Predefined and character references resolve to their text; anything else
+// is kept verbatim.
+pub(crate) fn resolve_entity(name: &str) -> String {
+    let resolved = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => name
+            .strip_prefix("#x")
+            .map(|hex| u32::from_str_radix(hex, 16).ok())
+            .or_else(|| name.strip_prefix('#').map(|dec| dec.parse().ok()))
+            .flatten()
+            .and_then(char::from_u32),
+    };
+    match resolved {
+        Some(c) => c.to_string(),
+        None => format!("&{};", name),
+    }
+}
+
+/// Pulls typed events from an ABX stream.
+pub struct AbxPullParser<R: Read> {
+    reader: AbxReader<R>,
+    cursor: Cursor,
+}
+
+impl<R: Read> AbxPullParser<R> {
+    pub fn new(input: R) -> Result<Self, ConversionError> {
+        Ok(Self {
+            reader: AbxReader::new(input)?,
+            cursor: Cursor::default(),
+        })
+    }
+}
+
+impl<R: Read> TypedXmlPullParser for AbxPullParser<R> {
+    fn next(&mut self) -> Result<Option<&AbxEvent>, ConversionError> {
+        self.cursor.next(|| self.reader.next_event())
+    }
+    fn next_token(&mut self) -> Result<Option<&AbxEvent>, ConversionError> {
+        self.cursor.next_token(|| self.reader.next_event())
+    }
+    fn event(&self) -> Option<&AbxEvent> {
+        self.cursor.current.as_ref()
+    }
+    fn depth(&self) -> usize {
+        self.cursor.depth
+    }
+}
+
+/// Pulls events from text XML. Every attribute is an
+/// [`AttributeValue::String`]; the typed getters parse it on demand.
+pub struct XmlPullParser<R: BufRead> {
+    source: XmlSource<R>,
+    cursor: Cursor,
+}
+
+struct XmlSource<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    started: bool,
+    finished: bool,
+    pending_end: Option<String>,
+}
+
+impl<R: BufRead> XmlPullParser<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            source: XmlSource {
+                reader: Reader::from_reader(input),
+                buf: Vec::new(),
+                started: false,
+                finished: false,
+                pending_end: None,
+            },
+            cursor: Cursor::default(),
+        }
+    }
+}
+
+impl<R: BufRead> XmlSource<R> {
+    fn read(&mut self) -> Result<Option<AbxEvent>, ConversionError> {
+        if !self.started {
+            self.started = true;
+            return Ok(Some(AbxEvent::StartDocument));
+        }
+        if let Some(name) = self.pending_end.take() {
+            return Ok(Some(AbxEvent::EndTag(name)));
+        }
+        if self.finished {
+            return Ok(None);
+        }
+        loop {
+            self.buf.clear();
+            let event = match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(e) => AbxEvent::StartTag {
+                    name: std::str::from_utf8(e.name().as_ref())?.to_string(),
+                    attributes: read_attributes(&e, self.reader.decoder())?,
+                },
+                Event::Empty(e) => {
+                    let name = std::str::from_utf8(e.name().as_ref())?.to_string();
+                    let attributes = read_attributes(&e, self.reader.decoder())?;
+                    self.pending_end = Some(name.clone());
+                    AbxEvent::StartTag { name, attributes }
+                }
+                Event::End(e) => {
+                    AbxEvent::EndTag(std::str::from_utf8(e.name().as_ref())?.to_string())
+                }
+                Event::Text(e) => AbxEvent::Text(std::str::from_utf8(&e)?.to_string()),
+                Event::CData(e) => AbxEvent::CdSect(std::str::from_utf8(&e)?.to_string()),
+                Event::Comment(e) => AbxEvent::Comment(std::str::from_utf8(&e)?.to_string()),
+                Event::PI(e) => {
+                    AbxEvent::ProcessingInstruction(std::str::from_utf8(&e)?.to_string())
+                }
+                Event::DocType(e) => AbxEvent::DocDecl(std::str::from_utf8(&e)?.to_string()),
+                Event::GeneralRef(e) => AbxEvent::EntityRef(std::str::from_utf8(&e)?.to_string()),
+                Event::Decl(_) => continue,
+                Event::Eof => {
+                    self.finished = true;
+                    AbxEvent::EndDocument
+                }
+            };
+            return Ok(Some(event));
+        }
+    }
+}
+
+fn read_attributes(
+    start: &BytesStart,
+    decoder: Decoder,
+) -> Result<Vec<Attribute>, ConversionError> {
+    let mut attributes = Vec::new();
+    for attr in start.attributes() {
+        let attr = attr?;
+        let name = std::str::from_utf8(attr.key.as_ref())?;
+        let value = attr.decode_and_unescape_value(decoder)?;
+        attributes.push(Attribute::new(
+            name,
+            AttributeValue::String(value.into_owned()),
+        ));
+    }
+    Ok(attributes)
+}
+
+impl<R: BufRead> TypedXmlPullParser for XmlPullParser<R> {
+    fn next(&mut self) -> Result<Option<&AbxEvent>, ConversionError> {
+        self.cursor.next(|| self.source.read())
+    }
+    fn next_token(&mut self) -> Result<Option<&AbxEvent>, ConversionError> {
+        self.cursor.next_token(|| self.source.read())
+    }
+    fn event(&self) -> Option<&AbxEvent> {
+        self.cursor.current.as_ref()
+    }
+    fn depth(&self) -> usize {
+        self.cursor.depth
+    }
+}
@@ -0,0 +1,238 @@
+//! Decoding of Android Binary XML into a stream of events.
+
+use crate::value::{Attribute, AttributeValue};
+use crate::{BinaryXmlSerializer, ConversionError};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+type S = BinaryXmlSerializer<Vec<u8>>;
+
+pub struct FastDataInput<R: Read> {
+    reader: R,
+    interned_strings: Vec<String>,
+}
+
+impl<R: Read> FastDataInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            interned_strings: Vec::new(),
+        }
+    }
+    /// Returns `None` when the stream ends cleanly before the next byte.
+    pub fn try_read_byte(&mut self) -> Result<Option<u8>, ConversionError> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    pub fn read_byte(&mut self) -> Result<u8, ConversionError> {
+        Ok(self.reader.read_u8()?)
+    }
+    pub fn read_short(&mut self) -> Result<u16, ConversionError> {
+        Ok(self.reader.read_u16::<BigEndian>()?)
+    }
+    pub fn read_int(&mut self) -> Result<i32, ConversionError> {
+        Ok(self.reader.read_i32::<BigEndian>()?)
+    }
+    pub fn read_long(&mut self) -> Result<i64, ConversionError> {
+        Ok(self.reader.read_i64::<BigEndian>()?)
+    }
+    pub fn read_float(&mut self) -> Result<f32, ConversionError> {
+        Ok(self.reader.read_f32::<BigEndian>()?)
+    }
+    pub fn read_double(&mut self) -> Result<f64, ConversionError> {
+        Ok(self.reader.read_f64::<BigEndian>()?)
+    }
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, ConversionError> {
+        let mut bytes = vec![0u8; len];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    pub fn read_utf(&mut self) -> Result<String, ConversionError> {
+        let len = self.read_short()? as usize;
+        let bytes = self.read_bytes(len)?;
+        Ok(std::str::from_utf8(&bytes)?.to_string())
+    }
+    pub fn read_interned_utf(&mut self) -> Result<String, ConversionError> {
+        let index = self.read_short()?;
+        if index == 0xFFFF {
+            let s = self.read_utf()?;
+            self.interned_strings.push(s.clone());
+            Ok(s)
+        } else {
+            self.interned_strings
+                .get(index as usize)
+                .cloned()
+                .ok_or(ConversionError::InvalidInternedIndex(index))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbxEvent {
+    StartDocument,
+    EndDocument,
+    StartTag {
+        name: String,
+        attributes: Vec<Attribute>,
+    },
+    EndTag(String),
+    Text(String),
+    CdSect(String),
+    EntityRef(String),
+    IgnorableWhitespace(String),
+    ProcessingInstruction(String),
+    Comment(String),
+    DocDecl(String),
+}
+
+/// Reads ABX tokens and groups the attributes that follow a start tag into
+/// a single [`AbxEvent::StartTag`].
+pub struct AbxReader<R: Read> {
+    input: FastDataInput<R>,
+    pending_token: Option<u8>,
+    finished: bool,
+}
+
+impl<R: Read> AbxReader<R> {
+    pub fn new(reader: R) -> Result<Self, ConversionError> {
+        let mut input = FastDataInput::new(reader);
+        let mut magic = [0u8; 4];
+        for byte in magic.iter_mut() {
+            *byte = input
+                .read_byte()
+                .map_err(|_| ConversionError::InvalidMagic)?;
+        }
+        if magic != S::PROTOCOL_MAGIC_VERSION_0 {
+            return Err(ConversionError::InvalidMagic);
+        }
+        Ok(Self {
+            input,
+            pending_token: None,
+            finished: false,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.input.reader
+    }
+
+    /// Bytes taken from the reader that belong to the next event: the token
+    /// read ahead to find the end of a start tag's attributes.
+    pub fn read_ahead(&self) -> usize {
+        usize::from(self.pending_token.is_some())
+    }
+
+    /// Returns the next event, or `None` once the document has ended. Input
+    /// that runs out before `END_DOCUMENT` is reported as malformed.
+    pub fn next_event(&mut self) -> Result<Option<AbxEvent>, ConversionError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let token = match self.pending_token.take() {
+            Some(token) => token,
+            None => match self.input.try_read_byte()? {
+                Some(token) => token,
+                None => {
+                    self.finished = true;
+                    return Err(ConversionError::Malformed(
+                        "document ends without END_DOCUMENT".into(),
+                    ));
+                }
+            },
+        };
+        let command = token & 0x0f;
+        let value_type = token & 0xf0;
+        let event = match command {
+            S::START_DOCUMENT => AbxEvent::StartDocument,
+            S::END_DOCUMENT => {
+                self.finished = true;
+                AbxEvent::EndDocument
+            }
+            S::START_TAG => {
+                let name = self.input.read_interned_utf()?;
+                let attributes = self.read_attributes()?;
+                AbxEvent::StartTag { name, attributes }
+            }
+            S::END_TAG => AbxEvent::EndTag(self.input.read_interned_utf()?),
+            S::TEXT => AbxEvent::Text(self.read_text(value_type)?),
+            S::CDSECT => AbxEvent::CdSect(self.read_text(value_type)?),
+            S::ENTITY_REF => AbxEvent::EntityRef(self.read_text(value_type)?),
+            S::IGNORABLE_WHITESPACE => AbxEvent::IgnorableWhitespace(self.read_text(value_type)?),
+            S::PROCESSING_INSTRUCTION => {
+                AbxEvent::ProcessingInstruction(self.read_text(value_type)?)
+            }
+            S::COMMENT => AbxEvent::Comment(self.read_text(value_type)?),
+            S::DOCDECL => AbxEvent::DocDecl(self.read_text(value_type)?),
+            _ => return Err(ConversionError::UnknownToken(token)),
+        };
+        Ok(Some(event))
+    }
+
+    fn read_text(&mut self, value_type: u8) -> Result<String, ConversionError> {
+        match value_type {
+            S::TYPE_STRING => self.input.read_utf(),
+            S::TYPE_STRING_INTERNED => self.input.read_interned_utf(),
+            S::TYPE_NULL => Ok(String::new()),
+            _ => Err(ConversionError::UnknownToken(value_type)),
+        }
+    }
+
+    fn read_attributes(&mut self) -> Result<Vec<Attribute>, ConversionError> {
+        let mut attributes = Vec::new();
+        while let Some(token) = self.input.try_read_byte()? {
+            if token & 0x0f != S::ATTRIBUTE {
+                self.pending_token = Some(token);
+                break;
+            }
+            let name = self.input.read_interned_utf()?;
+            let value = self.read_attribute_value(token & 0xf0)?;
+            attributes.push(Attribute { name, value });
+        }
+        Ok(attributes)
+    }
+
+    fn read_attribute_value(&mut self, value_type: u8) -> Result<AttributeValue, ConversionError> {
+        Ok(match value_type {
+            S::TYPE_STRING => AttributeValue::String(self.input.read_utf()?),
+            S::TYPE_STRING_INTERNED => AttributeValue::Interned(self.input.read_interned_utf()?),
+            S::TYPE_BYTES_HEX => {
+                let len = self.input.read_short()? as usize;
+                AttributeValue::BytesHex(self.input.read_bytes(len)?)
+            }
+            S::TYPE_BYTES_BASE64 => {
+                let len = self.input.read_short()? as usize;
+                AttributeValue::BytesBase64(self.input.read_bytes(len)?)
+            }
+            S::TYPE_INT => AttributeValue::Int(self.input.read_int()?),
+            S::TYPE_INT_HEX => AttributeValue::IntHex(self.input.read_int()?),
+            S::TYPE_LONG => AttributeValue::Long(self.input.read_long()?),
+            S::TYPE_LONG_HEX => AttributeValue::LongHex(self.input.read_long()?),
+            S::TYPE_FLOAT => AttributeValue::Float(self.input.read_float()?),
+            S::TYPE_DOUBLE => AttributeValue::Double(self.input.read_double()?),
+            S::TYPE_BOOLEAN_TRUE => AttributeValue::Bool(true),
+            S::TYPE_BOOLEAN_FALSE => AttributeValue::Bool(false),
+            _ => return Err(ConversionError::UnknownToken(S::ATTRIBUTE | value_type)),
+        })
+    }
+}
+
+impl<R: Read> Iterator for AbxReader<R> {
+    type Item = Result<AbxEvent, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_event() {
+            Ok(event) => event.map(Ok),
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
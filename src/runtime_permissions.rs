@@ -0,0 +1,307 @@
+//! Runtime permission state (`runtime-permissions.xml`, kept by the
+//! permission controller under
+//! `/data/misc_de/<user>/apexdata/com.android.permission/`).
+//!
+//! The root `<runtime-permissions version="N" fingerprint="...">` holds one
+//! `<package>` per app and one `<shared-user>` per shared uid, each listing
+//! its runtime permissions:
+//!
+//! ```xml
+//! <runtime-permissions version="10" fingerprint="google/...">
+//!     <package name="com.foo">
+//!         <permission name="android.permission.CAMERA" granted="true" flags="300" />
+//!     </package>
+//! </runtime-permissions>
+//! ```
+//!
+//! [`RuntimePermissionsFile`] grants and revokes permissions in place and
+//! writes every `<permission>` back the way
+//! `RuntimePermissionsPersistenceImpl` does: `granted` as a boolean, only
+//! when false, and `flags` as int-hex, only when not zero.
+//! [`RuntimePermissionsFile::bump_version`] marks the file as changed, as
+//! the `permissions` command does after every edit.
+//!
+//! ```rust
+//! use xml2abx::runtime_permissions::{Owner, RuntimePermissionsFile};
+//!
+//! let mut file = RuntimePermissionsFile::from_bytes(
+//!     br#"<runtime-permissions version="10"><package name="com.foo" /></runtime-permissions>"#,
+//! ).unwrap();
+//! file.set_granted(Owner::Package, "com.foo", "android.permission.CAMERA", true, None).unwrap();
+//! file.bump_version().unwrap();
+//! let entry = file.get(Owner::Package, "com.foo").unwrap().unwrap();
+//! assert!(entry.permissions[0].granted);
+//! assert_eq!(file.version().unwrap(), Some(11));
+//! ```
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element};
+use crate::value::AttributeValue;
+use std::io::Write;
+
+pub const TAG_RUNTIME_PERMISSIONS: &str = "runtime-permissions";
+pub const TAG_PACKAGE: &str = "package";
+pub const TAG_SHARED_USER: &str = "shared-user";
+pub const TAG_PERMISSION: &str = "permission";
+
+/// Whose permissions an entry lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    Package,
+    SharedUser,
+}
+
+impl Owner {
+    pub fn tag(self) -> &'static str {
+        match self {
+            Owner::Package => TAG_PACKAGE,
+            Owner::SharedUser => TAG_SHARED_USER,
+        }
+    }
+}
+
+/// One `<permission>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermissionState {
+    pub name: String,
+    pub granted: bool,
+    /// `PackageManager.FLAG_PERMISSION_*` bits.
+    pub flags: i32,
+}
+
+impl PermissionState {
+    /// Reads a `<permission>`; a missing `granted` means granted and missing
+    /// `flags` mean none, as on the device.
+    pub fn from_element(element: &Element) -> Result<Self, ConversionError> {
+        Ok(Self {
+            name: element
+                .attribute("name")
+                .ok_or_else(|| ConversionError::MissingAttribute("name".to_string()))?
+                .to_string(),
+            granted: element
+                .attribute("granted")
+                .map_or(Ok(true), AttributeValue::as_bool)?,
+            flags: element
+                .attribute("flags")
+                .map_or(Ok(0), AttributeValue::as_int_hex)?,
+        })
+    }
+
+    /// Writes a `<permission>` as `RuntimePermissionsPersistenceImpl` does,
+    /// leaving out `granted` when granted and `flags` when there are none.
+    pub fn to_element(&self) -> Element {
+        let mut element = Element::new(TAG_PERMISSION)
+            .with_attribute("name", AttributeValue::String(self.name.clone()));
+        if !self.granted {
+            element.set_attribute("granted", AttributeValue::Bool(false));
+        }
+        if self.flags != 0 {
+            element.set_attribute("flags", AttributeValue::IntHex(self.flags));
+        }
+        element
+    }
+}
+
+/// A `<package>` or `<shared-user>` and its permissions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermissionsEntry {
+    pub owner: Owner,
+    pub name: String,
+    pub permissions: Vec<PermissionState>,
+}
+
+/// A runtime-permissions document being edited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimePermissionsFile {
+    document: AbxDocument,
+}
+
+impl RuntimePermissionsFile {
+    /// Fails unless the root element is `<runtime-permissions>`.
+    pub fn from_document(document: AbxDocument) -> Result<Self, ConversionError> {
+        match document.root() {
+            Some(root) if root.name == TAG_RUNTIME_PERMISSIONS => Ok(Self { document }),
+            Some(root) => Err(ConversionError::Malformed(format!(
+                "expected <{}> root, found <{}>",
+                TAG_RUNTIME_PERMISSIONS, root.name
+            ))),
+            None => Err(ConversionError::Malformed(
+                "document has no root element".to_string(),
+            )),
+        }
+    }
+
+    /// Loads ABX or text XML.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ConversionError> {
+        Self::from_document(AbxDocument::from_bytes(data)?)
+    }
+
+    /// The `version` attribute, if present.
+    pub fn version(&self) -> Result<Option<i32>, ConversionError> {
+        self.root()
+            .attribute("version")
+            .map(AttributeValue::as_int)
+            .transpose()
+    }
+
+    pub fn set_version(&mut self, version: i32) {
+        self.root_mut()
+            .set_attribute("version", AttributeValue::Int(version));
+    }
+
+    /// Increments `version`, returning the new value. Files without one are
+    /// left alone.
+    pub fn bump_version(&mut self) -> Result<Option<i32>, ConversionError> {
+        let version = self.version()?.map(|v| v + 1);
+        if let Some(version) = version {
+            self.set_version(version);
+        }
+        Ok(version)
+    }
+
+    /// The build fingerprint the state was last written for.
+    pub fn fingerprint(&self) -> Option<String> {
+        self.root().attribute("fingerprint").map(|v| v.to_string())
+    }
+
+    /// Every package and shared user, in file order.
+    pub fn entries(&self) -> Result<Vec<PermissionsEntry>, ConversionError> {
+        self.root()
+            .elements()
+            .filter_map(|e| owner_of(e).map(|owner| (owner, e)))
+            .map(|(owner, e)| {
+                Ok(PermissionsEntry {
+                    owner,
+                    name: entry_name(e)?,
+                    permissions: e
+                        .elements()
+                        .filter(|p| p.name == TAG_PERMISSION)
+                        .map(PermissionState::from_element)
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get(
+        &self,
+        owner: Owner,
+        name: &str,
+    ) -> Result<Option<PermissionsEntry>, ConversionError> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .find(|e| e.owner == owner && e.name == name))
+    }
+
+    /// Grants or revokes `permission` for the package or shared user `name`,
+    /// adding either if missing. `flags` replace the existing ones when
+    /// given; new permissions otherwise start with none. Returns the previous
+    /// state.
+    pub fn set_granted(
+        &mut self,
+        owner: Owner,
+        name: &str,
+        permission: &str,
+        granted: bool,
+        flags: Option<i32>,
+    ) -> Result<Option<PermissionState>, ConversionError> {
+        let root = self.root_mut();
+        let index = root
+            .elements()
+            .position(|e| e.name == owner.tag() && has_name(e, name));
+        let index = match index {
+            Some(index) => index,
+            None => {
+                root.push_child(
+                    Element::new(owner.tag())
+                        .with_attribute("name", AttributeValue::String(name.to_string())),
+                );
+                root.elements().count() - 1
+            }
+        };
+        let entry = root.elements_mut().nth(index).expect("index found above");
+        let existing = entry
+            .elements_mut()
+            .find(|e| e.name == TAG_PERMISSION && has_name(e, permission));
+        let previous = existing
+            .as_deref()
+            .map(PermissionState::from_element)
+            .transpose()?;
+        let state = PermissionState {
+            name: permission.to_string(),
+            granted,
+            flags: flags.unwrap_or(previous.as_ref().map_or(0, |p| p.flags)),
+        };
+        match existing {
+            Some(element) => *element = state.to_element(),
+            None => entry.push_child(state.to_element()),
+        }
+        Ok(previous)
+    }
+
+    /// The document as the permission controller would write it: whitespace
+    /// dropped, `version` stored as an int and every permission re-encoded.
+    pub fn to_document(&self) -> Result<AbxDocument, ConversionError> {
+        let version = self.version()?;
+        let mut document = self.document.clone();
+        document.strip_whitespace();
+        let root = document.root_mut().expect("checked in from_document");
+        if let Some(v) = version {
+            root.set_attribute("version", AttributeValue::Int(v));
+        }
+        if let Some(fingerprint) = root.attribute("fingerprint").map(|v| v.to_string()) {
+            root.set_attribute("fingerprint", AttributeValue::String(fingerprint));
+        }
+        for entry in root.elements_mut().filter(|e| owner_of(e).is_some()) {
+            let name = entry_name(entry)?;
+            entry.set_attribute("name", AttributeValue::String(name));
+            for permission in entry.elements_mut().filter(|e| e.name == TAG_PERMISSION) {
+                *permission = PermissionState::from_element(permission)?.to_element();
+            }
+        }
+        Ok(document)
+    }
+
+    pub fn write_abx<W: Write>(&self, writer: W) -> Result<(), ConversionError> {
+        self.to_document()?.write_abx(writer)
+    }
+
+    /// Writes [`Self::to_document`] as text XML, indented when `pretty`.
+    pub fn write_xml<W: Write>(&self, writer: W, pretty: bool) -> Result<(), ConversionError> {
+        self.to_document()?.write_xml(writer, pretty)
+    }
+
+    pub fn to_abx_bytes(&self) -> Result<Vec<u8>, ConversionError> {
+        self.to_document()?.to_abx_bytes()
+    }
+
+    fn root(&self) -> &Element {
+        self.document.root().expect("checked in from_document")
+    }
+
+    fn root_mut(&mut self) -> &mut Element {
+        self.document.root_mut().expect("checked in from_document")
+    }
+}
+
+fn owner_of(element: &Element) -> Option<Owner> {
+    match element.name.as_str() {
+        TAG_PACKAGE => Some(Owner::Package),
+        TAG_SHARED_USER => Some(Owner::SharedUser),
+        _ => None,
+    }
+}
+
+fn entry_name(element: &Element) -> Result<String, ConversionError> {
+    element
+        .attribute("name")
+        .map(|v| v.to_string())
+        .ok_or_else(|| ConversionError::MissingAttribute("name".to_string()))
+}
+
+fn has_name(element: &Element, name: &str) -> bool {
+    element
+        .attribute("name")
+        .is_some_and(|n| n.to_string() == name)
+}
@@ -0,0 +1,473 @@
+//! A small XPath-like selector language for addressing elements and
+//! attributes, e.g. `/packages/package[@name='com.foo']/@flags`.
+//!
+//! Supported syntax:
+//! - `/name` or `/*` child steps and `//name` descendant steps,
+//! - predicates `[@attr='value']`, `[@attr!='value']`, `[@attr]`,
+//!   `[text()='value']` and `[n]` (1-based position among the siblings left
+//!   by the predicates before it),
+//! - a trailing `/@attr`, `/@*` or `/text()` to address an attribute, every
+//!   attribute or the text content instead of the element.
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element, Node};
+use crate::value::{Attribute, AttributeValue};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    AttributeEquals(String, String),
+    AttributeNotEquals(String, String),
+    AttributeExists(String),
+    TextEquals(String),
+    Position(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Child,
+    Descendant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub axis: Axis,
+    /// Element name, or `None` for `*`.
+    pub name: Option<String>,
+    pub predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Element,
+    Attribute(String),
+    /// `@*`: every attribute of the element.
+    AllAttributes,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub steps: Vec<Step>,
+    pub target: Target,
+}
+
+impl FromStr for Selector {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Selector::parse(s)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ConversionError {
+        ConversionError::InvalidSelector(format!(
+            "{} at offset {} in {:?}",
+            message, self.pos, self.input
+        ))
+    }
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+    fn eat_str(&mut self, s: &str) -> bool {
+        if self.input[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, c: char) -> Result<(), ConversionError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+    fn name(&mut self) -> Result<String, ConversionError> {
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+    fn quoted(&mut self) -> Result<String, ConversionError> {
+        let quote = match self.peek() {
+            Some(q @ ('\'' | '"')) => q,
+            _ => return Err(self.error("expected a quoted string")),
+        };
+        self.pos += 1;
+        let rest = &self.input[self.pos..];
+        let end = rest
+            .find(quote)
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += end + 1;
+        Ok(rest[..end].to_string())
+    }
+    fn predicate(&mut self) -> Result<Predicate, ConversionError> {
+        if self.eat('@') {
+            let name = self.name()?;
+            if self.eat('=') {
+                Ok(Predicate::AttributeEquals(name, self.quoted()?))
+            } else if self.eat_str("!=") {
+                Ok(Predicate::AttributeNotEquals(name, self.quoted()?))
+            } else {
+                Ok(Predicate::AttributeExists(name))
+            }
+        } else if self.eat_str("text()") {
+            self.expect('=')?;
+            Ok(Predicate::TextEquals(self.quoted()?))
+        } else {
+            let rest = &self.input[self.pos..];
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let position: usize = rest[..len]
+                .parse()
+                .map_err(|_| self.error("expected a predicate"))?;
+            if position == 0 {
+                return Err(self.error("positions start at 1"));
+            }
+            self.pos += len;
+            Ok(Predicate::Position(position))
+        }
+    }
+    fn step(&mut self, axis: Axis) -> Result<Step, ConversionError> {
+        let name = if self.eat('*') {
+            None
+        } else {
+            Some(self.name()?)
+        };
+        let mut predicates = Vec::new();
+        while self.eat('[') {
+            predicates.push(self.predicate()?);
+            self.expect(']')?;
+        }
+        Ok(Step {
+            axis,
+            name,
+            predicates,
+        })
+    }
+}
+
+impl Selector {
+    pub fn parse(input: &str) -> Result<Self, ConversionError> {
+        let mut parser = Parser {
+            input: input.trim(),
+            pos: 0,
+        };
+        let mut steps = Vec::new();
+        let mut target = Target::Element;
+        while parser.pos < parser.input.len() {
+            parser.expect('/')?;
+            let axis = if parser.eat('/') {
+                Axis::Descendant
+            } else {
+                Axis::Child
+            };
+            let attribute = parser.eat('@');
+            if attribute || parser.eat_str("text()") {
+                // `//@attr` and `//text()` apply to every descendant element.
+                if axis == Axis::Descendant {
+                    steps.push(Step {
+                        axis,
+                        name: None,
+                        predicates: Vec::new(),
+                    });
+                }
+                target = if attribute && parser.eat('*') {
+                    Target::AllAttributes
+                } else if attribute {
+                    Target::Attribute(parser.name()?)
+                } else {
+                    Target::Text
+                };
+                break;
+            }
+            steps.push(parser.step(axis)?);
+        }
+        if parser.pos < parser.input.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        if steps.is_empty() {
+            return Err(parser.error("expected at least one element step"));
+        }
+        Ok(Self { steps, target })
+    }
+}
+
+fn step_matches(step: &Step, element: &Element) -> bool {
+    step.name.as_ref().is_none_or(|name| *name == element.name)
+}
+
+fn predicate_matches(predicate: &Predicate, element: &Element) -> bool {
+    match predicate {
+        Predicate::AttributeEquals(name, value) => element
+            .attribute(name)
+            .is_some_and(|v| v.to_string() == *value),
+        Predicate::AttributeNotEquals(name, value) => element
+            .attribute(name)
+            .is_some_and(|v| v.to_string() != *value),
+        Predicate::AttributeExists(name) => element.attribute(name).is_some(),
+        Predicate::TextEquals(value) => element.text().trim() == value,
+        Predicate::Position(_) => true,
+    }
+}
+
+fn match_children<'a>(
+    step: &Step,
+    path: &[usize],
+    nodes: &'a [Node],
+    out: &mut Vec<(Vec<usize>, &'a [Node])>,
+) {
+    let mut candidates: Vec<(usize, &Element)> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| match node {
+            Node::Element(element) if step_matches(step, element) => Some((i, element)),
+            _ => None,
+        })
+        .collect();
+    // As in XPath, each predicate filters the nodes left by the ones before
+    // it, so `[n]` counts only those.
+    for predicate in &step.predicates {
+        candidates = match predicate {
+            Predicate::Position(n) => candidates.into_iter().skip(n - 1).take(1).collect(),
+            _ => candidates
+                .into_iter()
+                .filter(|(_, element)| predicate_matches(predicate, element))
+                .collect(),
+        };
+    }
+    for (i, element) in candidates {
+        let mut child_path = path.to_vec();
+        child_path.push(i);
+        out.push((child_path, element.children.as_slice()));
+    }
+}
+
+// A descendant step applies the child step below the context node and below
+// each of its descendants, as XPath's `//` does.
+fn match_descendants<'a>(
+    step: &Step,
+    path: &[usize],
+    nodes: &'a [Node],
+    out: &mut Vec<(Vec<usize>, &'a [Node])>,
+) {
+    match_children(step, path, nodes, out);
+    for (i, node) in nodes.iter().enumerate() {
+        if let Node::Element(element) = node {
+            let mut child_path = path.to_vec();
+            child_path.push(i);
+            match_descendants(step, &child_path, &element.children, out);
+        }
+    }
+}
+
+// Returns child-index paths from the document's top-level nodes, so matches
+// can be resolved again mutably.
+fn select_indices(document: &AbxDocument, selector: &Selector) -> Vec<Vec<usize>> {
+    let mut current: Vec<(Vec<usize>, &[Node])> = vec![(Vec::new(), &document.children)];
+    for step in &selector.steps {
+        let mut next = Vec::new();
+        for (path, nodes) in current {
+            match step.axis {
+                Axis::Child => match_children(step, &path, nodes, &mut next),
+                Axis::Descendant => match_descendants(step, &path, nodes, &mut next),
+            }
+        }
+        // Nested contexts can reach the same element more than once.
+        let mut seen = HashSet::new();
+        next.retain(|(path, _)| seen.insert(path.clone()));
+        current = next;
+    }
+    let mut paths: Vec<Vec<usize>> = current.into_iter().map(|(path, _)| path).collect();
+    paths.sort();
+    paths
+}
+
+// Renders an index path as a positional path, e.g. `/packages/package[3]`.
+fn describe_path(document: &AbxDocument, path: &[usize]) -> String {
+    let mut out = String::new();
+    let mut nodes = &document.children;
+    for &i in path {
+        let Some(Node::Element(element)) = nodes.get(i) else {
+            break;
+        };
+        let same_name = |node: &Node| matches!(node, Node::Element(e) if e.name == element.name);
+        let position = nodes[..i].iter().filter(|n| same_name(n)).count() + 1;
+        out.push('/');
+        out.push_str(&element.name);
+        if nodes.iter().filter(|n| same_name(n)).count() > 1 {
+            out.push_str(&format!("[{}]", position));
+        }
+        nodes = &element.children;
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryMatch<'a> {
+    Element {
+        path: String,
+        element: &'a Element,
+    },
+    Attribute {
+        path: String,
+        attribute: &'a Attribute,
+    },
+    Text {
+        path: String,
+        text: String,
+    },
+}
+
+fn element_at<'a>(document: &'a AbxDocument, path: &[usize]) -> Option<&'a Element> {
+    let mut nodes = &document.children;
+    let mut element = None;
+    for &i in path {
+        match nodes.get(i) {
+            Some(Node::Element(e)) => {
+                nodes = &e.children;
+                element = Some(e);
+            }
+            _ => return None,
+        }
+    }
+    element
+}
+
+fn element_at_mut<'a>(document: &'a mut AbxDocument, path: &[usize]) -> Option<&'a mut Element> {
+    let (&first, rest) = path.split_first()?;
+    let Some(Node::Element(element)) = document.children.get_mut(first) else {
+        return None;
+    };
+    let mut element = element;
+    for &i in rest {
+        match element.children.get_mut(i) {
+            Some(Node::Element(e)) => element = e,
+            _ => return None,
+        }
+    }
+    Some(element)
+}
+
+impl AbxDocument {
+    /// Elements matched by the element steps of `selector`.
+    pub fn select(&self, selector: &Selector) -> Vec<&Element> {
+        select_indices(self, selector)
+            .iter()
+            .filter_map(|path| element_at(self, path))
+            .collect()
+    }
+
+    /// Evaluates `selector` and returns the addressed elements, attributes
+    /// or non-blank text contents in document order.
+    pub fn query(&self, selector: &Selector) -> Vec<QueryMatch<'_>> {
+        let mut matches = Vec::new();
+        for indices in select_indices(self, selector) {
+            let Some(element) = element_at(self, &indices) else {
+                continue;
+            };
+            let path = describe_path(self, &indices);
+            match &selector.target {
+                Target::Element => matches.push(QueryMatch::Element { path, element }),
+                Target::Attribute(name) => {
+                    if let Some(attribute) = element.attributes.iter().find(|a| a.name == *name) {
+                        matches.push(QueryMatch::Attribute { path, attribute });
+                    }
+                }
+                Target::AllAttributes => {
+                    for attribute in &element.attributes {
+                        matches.push(QueryMatch::Attribute {
+                            path: path.clone(),
+                            attribute,
+                        });
+                    }
+                }
+                Target::Text => {
+                    let text = element.text();
+                    if !text.trim().is_empty() {
+                        matches.push(QueryMatch::Text { path, text });
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    /// Values of the addressed attribute on every matched element that has
+    /// it, or of every attribute for `@*`.
+    pub fn get_attribute_values(&self, selector: &Selector) -> Vec<&AttributeValue> {
+        let elements = self.select(selector).into_iter();
+        match &selector.target {
+            Target::Attribute(name) => elements
+                .filter_map(|element| element.attribute(name))
+                .collect(),
+            Target::AllAttributes => elements
+                .flat_map(|element| element.attributes.iter().map(|a| &a.value))
+                .collect(),
+            Target::Element | Target::Text => Vec::new(),
+        }
+    }
+
+    /// Sets the addressed attribute on every matched element and returns how
+    /// many elements were changed.
+    ///
+    /// An existing attribute keeps its type unless `type_name` is given; a new
+    /// one is typed by `type_name` or, without it, like any converted XML
+    /// attribute. Nothing else in the document is touched.
+    pub fn set_attribute_value(
+        &mut self,
+        selector: &Selector,
+        text: &str,
+        type_name: Option<&str>,
+    ) -> Result<usize, ConversionError> {
+        let name = match &selector.target {
+            Target::Attribute(name) => name,
+            Target::AllAttributes => {
+                return Err(ConversionError::InvalidSelector(
+                    "cannot set @*; name the attribute to set".to_string(),
+                ));
+            }
+            Target::Element | Target::Text => {
+                return Err(ConversionError::InvalidSelector(
+                    "selector must end in /@attribute".to_string(),
+                ));
+            }
+        };
+        let paths = select_indices(self, selector);
+        for path in &paths {
+            let Some(element) = element_at_mut(self, path) else {
+                continue;
+            };
+            let value = match (type_name, element.attribute(name)) {
+                (Some(type_name), _) => AttributeValue::parse(type_name, text)?,
+                (None, Some(existing)) => existing.with_text(text)?,
+                (None, None) => AttributeValue::infer(text),
+            };
+            element.set_attribute(name.as_str(), value);
+        }
+        Ok(paths.len())
+    }
+}
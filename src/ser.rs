@@ -0,0 +1,342 @@
+//! Serde serialization into ABX.
+//!
+//! Struct fields follow the same naming convention as `quick-xml`'s serde
+//! support: a field renamed to `@name` becomes an attribute, `$text` becomes
+//! the element's text, and any other field becomes one child element (or one
+//! per item for sequences). The root element takes the struct's name, so use
+//! `#[serde(rename = "...")]` on the type to pick the tag.
+//!
+//! Primitive values keep their type: `bool` is written with
+//! `attribute_boolean`, `i8`..`i32`, `u8` and `u16` with `attribute_int`,
+//! `i64`, `u32` and `u64` with `attribute_long`, `f32` with `attribute_float`
+//! and `f64` with `attribute_double`. Byte buffers (e.g. via `serde_bytes`)
+//! use `attribute_bytes_base64`.
+//!
+//! ```rust
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! #[serde(rename = "setting")]
+//! struct Setting {
+//!     #[serde(rename = "@id")]
+//!     id: i64,
+//!     #[serde(rename = "@name")]
+//!     name: String,
+//!     #[serde(rename = "@defaultSysSet")]
+//!     default_sys_set: bool,
+//! }
+//!
+//! let mut abx = Vec::new();
+//! let setting = Setting { id: 42, name: "adb_enabled".into(), default_sys_set: true };
+//! xml2abx::to_writer(&mut abx, &setting).unwrap();
+//! ```
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element, Node};
+use crate::value::AttributeValue;
+use serde::ser::{self, Impossible, Serialize};
+use std::io::Write;
+
+/// Serializes `value` as an ABX document.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+) -> Result<(), ConversionError> {
+    match value.serialize(ContentSerializer)? {
+        Content::Element(root) if !root.name.is_empty() => AbxDocument::new(root).write_abx(writer),
+        _ => Err(unsupported("a root value other than a struct")),
+    }
+}
+
+impl ser::Error for ConversionError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        ConversionError::Serde(msg.to_string())
+    }
+}
+
+fn unsupported(what: &str) -> ConversionError {
+    ConversionError::Serde(format!("cannot serialize {}", what))
+}
+
+enum Content {
+    None,
+    Value(AttributeValue),
+    Element(Element),
+    Seq(Vec<Content>),
+}
+
+struct ContentSerializer;
+
+impl ser::Serializer for ContentSerializer {
+    type Ok = Content;
+    type Error = ConversionError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = Impossible<Content, ConversionError>;
+    type SerializeMap = ElementSerializer;
+    type SerializeStruct = ElementSerializer;
+    type SerializeStructVariant = Impossible<Content, ConversionError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::Bool(v)))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Content, ConversionError> {
+        self.serialize_i32(v as i32)
+    }
+    fn serialize_i16(self, v: i16) -> Result<Content, ConversionError> {
+        self.serialize_i32(v as i32)
+    }
+    fn serialize_i32(self, v: i32) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::Int(v)))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::Long(v)))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Content, ConversionError> {
+        self.serialize_i32(v as i32)
+    }
+    fn serialize_u16(self, v: u16) -> Result<Content, ConversionError> {
+        self.serialize_i32(v as i32)
+    }
+    fn serialize_u32(self, v: u32) -> Result<Content, ConversionError> {
+        self.serialize_i64(v as i64)
+    }
+    fn serialize_u64(self, v: u64) -> Result<Content, ConversionError> {
+        let v = i64::try_from(v)
+            .map_err(|_| ConversionError::Serde(format!("{} does not fit in a long", v)))?;
+        self.serialize_i64(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::Float(v)))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::Double(v)))
+    }
+    fn serialize_char(self, v: char) -> Result<Content, ConversionError> {
+        self.serialize_str(&v.to_string())
+    }
+    fn serialize_str(self, v: &str) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::String(v.to_string())))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Content, ConversionError> {
+        Ok(Content::Value(AttributeValue::BytesBase64(v.to_vec())))
+    }
+    fn serialize_none(self) -> Result<Content, ConversionError> {
+        Ok(Content::None)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Content, ConversionError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Content, ConversionError> {
+        Ok(Content::None)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Content, ConversionError> {
+        Ok(Content::None)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Content, ConversionError> {
+        self.serialize_str(variant)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Content, ConversionError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Content, ConversionError> {
+        Err(unsupported("an enum variant with data"))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, ConversionError> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, ConversionError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, ConversionError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, ConversionError> {
+        Err(unsupported("an enum variant with data"))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<ElementSerializer, ConversionError> {
+        Ok(ElementSerializer::new(""))
+    }
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<ElementSerializer, ConversionError> {
+        Ok(ElementSerializer::new(name))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, ConversionError> {
+        Err(unsupported("an enum variant with data"))
+    }
+}
+
+struct SeqSerializer(Vec<Content>);
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Content;
+    type Error = ConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ConversionError> {
+        self.0.push(value.serialize(ContentSerializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Content, ConversionError> {
+        Ok(Content::Seq(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Content;
+    type Error = ConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ConversionError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Content, ConversionError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Content;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Content, ConversionError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct ElementSerializer {
+    element: Element,
+    key: Option<String>,
+}
+
+impl ElementSerializer {
+    fn new(name: &str) -> Self {
+        Self {
+            element: Element::new(name),
+            key: None,
+        }
+    }
+
+    fn add(&mut self, key: &str, content: Content) -> Result<(), ConversionError> {
+        if let Some(name) = key.strip_prefix('@') {
+            match content {
+                Content::None => {}
+                Content::Value(value) => self.element.set_attribute(name, value),
+                _ => {
+                    return Err(unsupported(&format!(
+                        "a structured value as attribute {}",
+                        name
+                    )));
+                }
+            }
+        } else if key == "$text" {
+            match content {
+                Content::None => {}
+                Content::Value(value) => self.element.push_child(Node::Text(value.to_string())),
+                _ => return Err(unsupported("a structured value as text")),
+            }
+        } else {
+            match content {
+                Content::None => {}
+                Content::Value(value) => self
+                    .element
+                    .push_child(Element::new(key).with_child(Node::Text(value.to_string()))),
+                Content::Element(mut child) => {
+                    child.name = key.to_string();
+                    self.element.push_child(child);
+                }
+                Content::Seq(items) => {
+                    for item in items {
+                        self.add(key, item)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for ElementSerializer {
+    type Ok = Content;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), ConversionError> {
+        let content = value.serialize(ContentSerializer)?;
+        self.add(key, content)
+    }
+    fn end(self) -> Result<Content, ConversionError> {
+        Ok(Content::Element(self.element))
+    }
+}
+
+impl ser::SerializeMap for ElementSerializer {
+    type Ok = Content;
+    type Error = ConversionError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ConversionError> {
+        match key.serialize(ContentSerializer)? {
+            Content::Value(value) => {
+                self.key = Some(value.to_string());
+                Ok(())
+            }
+            _ => Err(unsupported("a map key that is not a string or number")),
+        }
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ConversionError::Serde("map value without a key".to_string()))?;
+        let content = value.serialize(ContentSerializer)?;
+        self.add(&key, content)
+    }
+    fn end(self) -> Result<Content, ConversionError> {
+        Ok(Content::Element(self.element))
+    }
+}
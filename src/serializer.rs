@@ -0,0 +1,389 @@
+//! The `TypedXmlSerializer` interface shared by the binary and text writers.
+//!
+//! Mirrors Android's `TypedXmlSerializer`, so the same writer logic can emit
+//! either ABX (through [`BinaryXmlSerializer`]) or text XML (through
+//! [`TextXmlSerializer`]). The typed attribute methods default to writing the
+//! value as a string formatted the way Android's text serializer does.
+
+use crate::value::AttributeValue;
+use crate::{BinaryXmlSerializer, ConversionError};
+use std::io::Write;
+
+pub trait TypedXmlSerializer {
+    fn start_document(&mut self) -> Result<(), ConversionError>;
+    fn end_document(&mut self) -> Result<(), ConversionError>;
+    fn start_tag(&mut self, name: &str) -> Result<(), ConversionError>;
+    fn end_tag(&mut self, name: &str) -> Result<(), ConversionError>;
+    fn attribute(&mut self, name: &str, value: &str) -> Result<(), ConversionError>;
+    fn text(&mut self, text: &str) -> Result<(), ConversionError>;
+    fn cdsect(&mut self, text: &str) -> Result<(), ConversionError>;
+    fn comment(&mut self, text: &str) -> Result<(), ConversionError>;
+    fn processing_instruction(
+        &mut self,
+        target: &str,
+        data: Option<&str>,
+    ) -> Result<(), ConversionError>;
+    fn docdecl(&mut self, text: &str) -> Result<(), ConversionError>;
+    fn ignorable_whitespace(&mut self, text: &str) -> Result<(), ConversionError>;
+    fn entity_ref(&mut self, text: &str) -> Result<(), ConversionError>;
+
+    fn attribute_interned(&mut self, name: &str, value: &str) -> Result<(), ConversionError> {
+        self.attribute(name, value)
+    }
+    fn attribute_bytes_hex(&mut self, name: &str, value: &[u8]) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::BytesHex(value.to_vec()).to_string())
+    }
+    fn attribute_bytes_base64(&mut self, name: &str, value: &[u8]) -> Result<(), ConversionError> {
+        self.attribute(
+            name,
+            &AttributeValue::BytesBase64(value.to_vec()).to_string(),
+        )
+    }
+    fn attribute_int(&mut self, name: &str, value: i32) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::Int(value).to_string())
+    }
+    fn attribute_int_hex(&mut self, name: &str, value: i32) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::IntHex(value).to_string())
+    }
+    fn attribute_long(&mut self, name: &str, value: i64) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::Long(value).to_string())
+    }
+    fn attribute_long_hex(&mut self, name: &str, value: i64) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::LongHex(value).to_string())
+    }
+    fn attribute_float(&mut self, name: &str, value: f32) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::Float(value).to_string())
+    }
+    fn attribute_double(&mut self, name: &str, value: f64) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::Double(value).to_string())
+    }
+    fn attribute_boolean(&mut self, name: &str, value: bool) -> Result<(), ConversionError> {
+        self.attribute(name, &AttributeValue::Bool(value).to_string())
+    }
+}
+
+impl<W: Write> TypedXmlSerializer for BinaryXmlSerializer<W> {
+    fn start_document(&mut self) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::start_document(self)
+    }
+    fn end_document(&mut self) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::end_document(self)
+    }
+    fn start_tag(&mut self, name: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::start_tag(self, name)
+    }
+    fn end_tag(&mut self, name: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::end_tag(self, name)
+    }
+    fn attribute(&mut self, name: &str, value: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute(self, name, value)
+    }
+    fn text(&mut self, text: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::text(self, text)
+    }
+    fn cdsect(&mut self, text: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::cdsect(self, text)
+    }
+    fn comment(&mut self, text: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::comment(self, text)
+    }
+    fn processing_instruction(
+        &mut self,
+        target: &str,
+        data: Option<&str>,
+    ) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::processing_instruction(self, target, data)
+    }
+    fn docdecl(&mut self, text: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::docdecl(self, text)
+    }
+    fn ignorable_whitespace(&mut self, text: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::ignorable_whitespace(self, text)
+    }
+    fn entity_ref(&mut self, text: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::entity_ref(self, text)
+    }
+    fn attribute_interned(&mut self, name: &str, value: &str) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_interned(self, name, value)
+    }
+    fn attribute_bytes_hex(&mut self, name: &str, value: &[u8]) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_bytes_hex(self, name, value)
+    }
+    fn attribute_bytes_base64(&mut self, name: &str, value: &[u8]) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_bytes_base64(self, name, value)
+    }
+    fn attribute_int(&mut self, name: &str, value: i32) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_int(self, name, value)
+    }
+    fn attribute_int_hex(&mut self, name: &str, value: i32) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_int_hex(self, name, value)
+    }
+    fn attribute_long(&mut self, name: &str, value: i64) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_long(self, name, value)
+    }
+    fn attribute_long_hex(&mut self, name: &str, value: i64) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_long_hex(self, name, value)
+    }
+    fn attribute_float(&mut self, name: &str, value: f32) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_float(self, name, value)
+    }
+    fn attribute_double(&mut self, name: &str, value: f64) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_double(self, name, value)
+    }
+    fn attribute_boolean(&mut self, name: &str, value: bool) -> Result<(), ConversionError> {
+        BinaryXmlSerializer::attribute_boolean(self, name, value)
+    }
+}
+
+/// Writes text XML the way Android's `FastXmlSerializer` does: the
+/// `standalone='yes'` declaration, double-quoted attributes and ` />` for
+/// empty elements.
+///
+/// Without indentation nothing but the events themselves is written, so the
+/// output parses back to the same events. With indentation every element
+/// starts on its own line; text content stays inline.
+///
+/// With type annotations, every attribute whose type would not be inferred
+/// from its text is followed by a `name:abx-type="type"` attribute (see
+/// [`AttributeValue::TYPE_ANNOTATION_SUFFIX`]), which `XmlToAbxConverter`
+/// honours, so the XML converts back to the original ABX typing.
+pub struct TextXmlSerializer<W: Write> {
+    writer: W,
+    indent: Option<String>,
+    annotate_types: bool,
+    depth: usize,
+    in_tag: bool,
+    line_start: bool,
+}
+
+impl<W: Write> TextXmlSerializer<W> {
+    pub const XML_DECLARATION: &'static str =
+        "<?xml version='1.0' encoding='utf-8' standalone='yes' ?>";
+
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            indent: None,
+            annotate_types: false,
+            depth: 0,
+            in_tag: false,
+            line_start: true,
+        }
+    }
+    pub fn with_indent(writer: W, indent: &str) -> Self {
+        Self {
+            indent: Some(indent.to_string()),
+            ..Self::new(writer)
+        }
+    }
+    pub fn with_type_annotations(mut self, annotate_types: bool) -> Self {
+        self.annotate_types = annotate_types;
+        self
+    }
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+    fn write_attribute(&mut self, name: &str, value: &str) -> Result<(), ConversionError> {
+        if !self.in_tag {
+            return Err(ConversionError::Malformed(format!(
+                "attribute {} written outside a start tag",
+                name
+            )));
+        }
+        write!(self.writer, " {}=\"{}\"", name, escape_attribute(value))?;
+        Ok(())
+    }
+    fn typed_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), ConversionError> {
+        self.write_attribute(name, &value.to_string())?;
+        if self.annotate_types && value.needs_type_annotation() {
+            let annotation = format!("{}{}", name, AttributeValue::TYPE_ANNOTATION_SUFFIX);
+            self.write_attribute(&annotation, value.type_name())?;
+        }
+        Ok(())
+    }
+    fn close_start_tag(&mut self) -> Result<(), ConversionError> {
+        if self.in_tag {
+            self.writer.write_all(b">")?;
+            self.in_tag = false;
+        }
+        Ok(())
+    }
+    // Moves to a fresh, indented line before block-level output.
+    fn begin_line(&mut self) -> Result<(), ConversionError> {
+        if let Some(indent) = &self.indent {
+            if !self.line_start {
+                self.writer.write_all(b"\n")?;
+            }
+            for _ in 0..self.depth {
+                self.writer.write_all(indent.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+    fn end_line(&mut self) -> Result<(), ConversionError> {
+        if self.indent.is_some() {
+            self.writer.write_all(b"\n")?;
+            self.line_start = true;
+        }
+        Ok(())
+    }
+    fn block(&mut self, content: &str) -> Result<(), ConversionError> {
+        self.close_start_tag()?;
+        self.begin_line()?;
+        self.writer.write_all(content.as_bytes())?;
+        self.line_start = false;
+        self.end_line()
+    }
+    fn inline(&mut self, content: &str) -> Result<(), ConversionError> {
+        self.close_start_tag()?;
+        self.writer.write_all(content.as_bytes())?;
+        self.line_start = content.ends_with('\n');
+        Ok(())
+    }
+}
+
+impl<W: Write> TypedXmlSerializer for TextXmlSerializer<W> {
+    fn start_document(&mut self) -> Result<(), ConversionError> {
+        self.writer.write_all(Self::XML_DECLARATION.as_bytes())?;
+        self.line_start = false;
+        self.end_line()
+    }
+    fn end_document(&mut self) -> Result<(), ConversionError> {
+        self.close_start_tag()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+    fn start_tag(&mut self, name: &str) -> Result<(), ConversionError> {
+        self.close_start_tag()?;
+        self.begin_line()?;
+        write!(self.writer, "<{}", name)?;
+        self.depth += 1;
+        self.in_tag = true;
+        self.line_start = false;
+        Ok(())
+    }
+    fn end_tag(&mut self, name: &str) -> Result<(), ConversionError> {
+        self.depth = self.depth.saturating_sub(1);
+        if self.in_tag {
+            self.writer.write_all(b" />")?;
+            self.in_tag = false;
+        } else {
+            if self.line_start {
+                self.begin_line()?;
+            }
+            write!(self.writer, "</{}>", name)?;
+        }
+        self.line_start = false;
+        self.end_line()
+    }
+    fn attribute(&mut self, name: &str, value: &str) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::String(value.to_string()))
+    }
+    fn text(&mut self, text: &str) -> Result<(), ConversionError> {
+        self.inline(&escape_text(text))
+    }
+    fn cdsect(&mut self, text: &str) -> Result<(), ConversionError> {
+        // A `]]>` in the text would end the section; split it across two.
+        let text = text.replace("]]>", "]]]]><![CDATA[>");
+        self.inline(&format!("<![CDATA[{}]]>", text))
+    }
+    fn comment(&mut self, text: &str) -> Result<(), ConversionError> {
+        // XML has no way to escape these inside a comment.
+        if text.contains("--") || text.ends_with('-') {
+            return Err(ConversionError::Malformed(format!(
+                "comment {:?} contains \"--\" or ends in \"-\"",
+                text
+            )));
+        }
+        self.block(&format!("<!--{}-->", text))
+    }
+    fn processing_instruction(
+        &mut self,
+        target: &str,
+        data: Option<&str>,
+    ) -> Result<(), ConversionError> {
+        match data {
+            Some(data) if !data.is_empty() => self.block(&format!("<?{} {}?>", target, data)),
+            _ => self.block(&format!("<?{}?>", target)),
+        }
+    }
+    fn docdecl(&mut self, text: &str) -> Result<(), ConversionError> {
+        self.block(&format!("<!DOCTYPE {}>", text.trim_start()))
+    }
+    fn ignorable_whitespace(&mut self, text: &str) -> Result<(), ConversionError> {
+        self.inline(text)
+    }
+    fn entity_ref(&mut self, text: &str) -> Result<(), ConversionError> {
+        self.inline(&format!("&{};", text))
+    }
+    fn attribute_interned(&mut self, name: &str, value: &str) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::Interned(value.to_string()))
+    }
+    fn attribute_bytes_hex(&mut self, name: &str, value: &[u8]) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::BytesHex(value.to_vec()))
+    }
+    fn attribute_bytes_base64(&mut self, name: &str, value: &[u8]) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::BytesBase64(value.to_vec()))
+    }
+    fn attribute_int(&mut self, name: &str, value: i32) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::Int(value))
+    }
+    fn attribute_int_hex(&mut self, name: &str, value: i32) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::IntHex(value))
+    }
+    fn attribute_long(&mut self, name: &str, value: i64) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::Long(value))
+    }
+    fn attribute_long_hex(&mut self, name: &str, value: i64) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::LongHex(value))
+    }
+    fn attribute_float(&mut self, name: &str, value: f32) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::Float(value))
+    }
+    fn attribute_double(&mut self, name: &str, value: f64) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::Double(value))
+    }
+    fn attribute_boolean(&mut self, name: &str, value: bool) -> Result<(), ConversionError> {
+        self.typed_attribute(name, AttributeValue::Bool(value))
+    }
+}
+
+/// Escapes `text` for use as element content.
+pub fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes `value` for use inside a double-quoted attribute, keeping
+/// whitespace characters that parsers would otherwise normalize.
+pub fn escape_attribute(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            '\t' => out.push_str("&#9;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
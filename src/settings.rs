@@ -0,0 +1,339 @@
+//! Settings-provider state files (`settings_global.xml`,
+//! `settings_secure.xml`, `settings_system.xml`, ...).
+//!
+//! The root `<settings version="N">` holds one `<setting>` per entry:
+//!
+//! ```xml
+//! <settings version="213">
+//!     <setting id="42" name="adb_enabled" value="1" package="android"
+//!              defaultValue="0" defaultSysSet="true" />
+//! </settings>
+//! ```
+//!
+//! [`SettingsFile`] edits such a document in place, keeping anything else in
+//! it, and writes settings back the way `SettingsState` does: attribute order
+//! and types follow `writeSingleSetting`, values that are not valid XML go to
+//! the `*Base64` attributes, and every inserted or updated setting gets the
+//! next free `id`. The `version` is the provider's schema version and is left
+//! as found unless set explicitly.
+//!
+//! ```rust
+//! use xml2abx::settings::{Setting, SettingsFile};
+//!
+//! let mut file = SettingsFile::from_bytes(
+//!     br#"<settings version="213"><setting id="7" name="a" value="1" package="android" /></settings>"#,
+//! ).unwrap();
+//! file.put(Setting::new("b", Some("2"), "com.android.shell")).unwrap();
+//! assert_eq!(file.get("b").unwrap().unwrap().id, 8);
+//! let abx = file.to_abx_bytes().unwrap();
+//! ```
+
+use crate::ConversionError;
+use crate::document::{AbxDocument, Element, Node};
+use crate::value::AttributeValue;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::io::Write;
+
+pub const TAG_SETTINGS: &str = "settings";
+pub const TAG_SETTING: &str = "setting";
+
+/// First `version` whose files store binary values in the `*Base64`
+/// attributes (`SETTINGS_VERSION_NEW_ENCODING`). Older files write a null
+/// value as the string `"null"`.
+pub const VERSION_NEW_ENCODING: i32 = 121;
+
+const NULL_VALUE_OLD_STYLE: &str = "null";
+
+/// One `<setting>` entry.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Setting {
+    pub id: i64,
+    pub name: String,
+    pub value: Option<String>,
+    pub package: String,
+    pub default_value: Option<String>,
+    /// Only stored when `default_value` is set.
+    pub default_sys_set: bool,
+    /// Only stored when `default_value` is set.
+    pub tag: Option<String>,
+    pub preserve_in_restore: bool,
+}
+
+impl Setting {
+    pub fn new(name: impl Into<String>, value: Option<&str>, package: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.map(str::to_string),
+            package: package.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Reads a `<setting>` element the way `SettingsState.parseStateLocked`
+    /// does for a file of the given `version`.
+    pub fn from_element(element: &Element, version: i32) -> Result<Self, ConversionError> {
+        let text = |name: &str| element.attribute(name).map(|v| v.to_string());
+        let required = |name: &str| {
+            text(name).ok_or_else(|| ConversionError::MissingAttribute(name.to_string()))
+        };
+        let flag = |name: &str| {
+            element
+                .attribute(name)
+                .map_or(Ok(false), AttributeValue::as_bool)
+        };
+        let id = required("id")?;
+        let default_value = value_attribute(element, "defaultValue", version)?;
+        let (default_sys_set, tag) = if default_value.is_some() {
+            (
+                flag("defaultSysSet")?,
+                value_attribute(element, "tag", version)?,
+            )
+        } else {
+            (false, None)
+        };
+        Ok(Self {
+            id: id
+                .trim()
+                .parse()
+                .map_err(|_| ConversionError::InvalidValue("long", id))?,
+            name: required("name")?,
+            value: value_attribute(element, "value", version)?,
+            package: required("package")?,
+            default_value,
+            default_sys_set,
+            tag,
+            preserve_in_restore: flag("preserve_in_restore")?,
+        })
+    }
+
+    /// Builds the element `SettingsState.writeSingleSetting` writes for a
+    /// file of the given `version`.
+    pub fn to_element(&self, version: i32) -> Element {
+        let mut element = Element::new(TAG_SETTING)
+            .with_attribute("id", AttributeValue::String(self.id.to_string()))
+            .with_attribute("name", AttributeValue::String(self.name.clone()));
+        set_value_attribute(&mut element, "value", self.value.as_deref(), version);
+        element.set_attribute("package", AttributeValue::String(self.package.clone()));
+        if self.default_value.is_some() {
+            set_value_attribute(
+                &mut element,
+                "defaultValue",
+                self.default_value.as_deref(),
+                version,
+            );
+            element.set_attribute("defaultSysSet", AttributeValue::Bool(self.default_sys_set));
+            set_value_attribute(&mut element, "tag", self.tag.as_deref(), version);
+        }
+        if self.preserve_in_restore {
+            element.set_attribute("preserve_in_restore", AttributeValue::Bool(true));
+        }
+        element
+    }
+}
+
+/// A settings document being edited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsFile {
+    document: AbxDocument,
+}
+
+impl SettingsFile {
+    /// Fails unless the root element is `<settings>`.
+    pub fn from_document(document: AbxDocument) -> Result<Self, ConversionError> {
+        match document.root() {
+            Some(root) if root.name == TAG_SETTINGS => Ok(Self { document }),
+            Some(root) => Err(ConversionError::Malformed(format!(
+                "expected <{}> root, found <{}>",
+                TAG_SETTINGS, root.name
+            ))),
+            None => Err(ConversionError::Malformed(
+                "document has no root element".to_string(),
+            )),
+        }
+    }
+
+    /// Loads ABX or text XML.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ConversionError> {
+        Self::from_document(AbxDocument::from_bytes(data)?)
+    }
+
+    /// The `version` attribute, if present.
+    pub fn version(&self) -> Result<Option<i32>, ConversionError> {
+        self.root()
+            .attribute("version")
+            .map(AttributeValue::as_int)
+            .transpose()
+    }
+
+    pub fn set_version(&mut self, version: i32) {
+        self.root_mut()
+            .set_attribute("version", AttributeValue::Int(version));
+    }
+
+    /// All settings, in file order.
+    pub fn settings(&self) -> Result<Vec<Setting>, ConversionError> {
+        let version = self.encoding_version()?;
+        self.root()
+            .elements()
+            .filter(|e| e.name == TAG_SETTING)
+            .map(|e| Setting::from_element(e, version))
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Setting>, ConversionError> {
+        Ok(self.settings()?.into_iter().find(|s| s.name == name))
+    }
+
+    /// One more than the highest `id` in use, as `SettingsState` computes it
+    /// on load.
+    pub fn next_id(&self) -> Result<i64, ConversionError> {
+        Ok(self.settings()?.iter().map(|s| s.id + 1).max().unwrap_or(0))
+    }
+
+    /// Inserts `setting`, replacing an existing one with the same name in
+    /// place. The setting gets the next free `id`, as every update does on
+    /// the device. Returns the replaced setting.
+    pub fn put(&mut self, mut setting: Setting) -> Result<Option<Setting>, ConversionError> {
+        let version = self.encoding_version()?;
+        let previous = self.get(&setting.name)?;
+        setting.id = self.next_id()?;
+        let element = Node::Element(setting.to_element(version));
+        let children = &mut self.root_mut().children;
+        match children
+            .iter()
+            .position(|n| is_setting(n, Some(&setting.name)))
+        {
+            Some(index) => children[index] = element,
+            None => {
+                // New settings go after the last existing one, ahead of
+                // trailing sections such as <namespaceHashes>.
+                let index = children
+                    .iter()
+                    .rposition(|n| is_setting(n, None))
+                    .map_or(0, |i| i + 1);
+                children.insert(index, element);
+            }
+        }
+        Ok(previous)
+    }
+
+    /// Removes the setting called `name`, returning it.
+    pub fn delete(&mut self, name: &str) -> Result<Option<Setting>, ConversionError> {
+        let previous = self.get(name)?;
+        if previous.is_some() {
+            self.root_mut()
+                .children
+                .retain(|n| !is_setting(n, Some(name)));
+        }
+        Ok(previous)
+    }
+
+    /// The document as `SettingsState` would write it: whitespace dropped,
+    /// `version` stored as an int and every setting re-encoded.
+    pub fn to_document(&self) -> Result<AbxDocument, ConversionError> {
+        let version = self.encoding_version()?;
+        let mut document = self.document.clone();
+        document.strip_whitespace();
+        let root = document.root_mut().expect("checked in from_document");
+        if let Some(v) = self.version()? {
+            root.set_attribute("version", AttributeValue::Int(v));
+        }
+        for element in root.elements_mut().filter(|e| e.name == TAG_SETTING) {
+            *element = Setting::from_element(element, version)?.to_element(version);
+        }
+        Ok(document)
+    }
+
+    pub fn write_abx<W: Write>(&self, writer: W) -> Result<(), ConversionError> {
+        self.to_document()?.write_abx(writer)
+    }
+
+    pub fn to_abx_bytes(&self) -> Result<Vec<u8>, ConversionError> {
+        self.to_document()?.to_abx_bytes()
+    }
+
+    fn root(&self) -> &Element {
+        self.document.root().expect("checked in from_document")
+    }
+
+    fn root_mut(&mut self) -> &mut Element {
+        self.document.root_mut().expect("checked in from_document")
+    }
+
+    // Files without a version predate the base64 encoding.
+    fn encoding_version(&self) -> Result<i32, ConversionError> {
+        Ok(self.version()?.unwrap_or(-1))
+    }
+}
+
+// A `<setting>` element, called `name` if given.
+fn is_setting(node: &Node, name: Option<&str>) -> bool {
+    match node {
+        Node::Element(e) if e.name == TAG_SETTING => {
+            name.is_none_or(|name| e.attribute("name").is_some_and(|n| n.to_string() == name))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `value` holds characters that cannot be stored in XML, in which
+/// case `SettingsState` stores it base64-encoded (`SettingsState.isBinary`).
+pub fn is_binary(value: &str) -> bool {
+    value
+        .chars()
+        .any(|c| !matches!(c, '\u{20}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}'))
+}
+
+// `getValueAttribute`: the plain attribute, else its base64 form.
+fn value_attribute(
+    element: &Element,
+    name: &str,
+    version: i32,
+) -> Result<Option<String>, ConversionError> {
+    let value = element.attribute(name).map(|v| v.to_string());
+    if version < VERSION_NEW_ENCODING {
+        return Ok(value.filter(|v| v != NULL_VALUE_OLD_STYLE));
+    }
+    if value.is_some() {
+        return Ok(value);
+    }
+    match element.attribute(&format!("{}Base64", name)) {
+        Some(encoded) => decode_base64(&encoded.to_string()).map(Some),
+        None => Ok(None),
+    }
+}
+
+// `setValueAttribute`
+fn set_value_attribute(element: &mut Element, name: &str, value: Option<&str>, version: i32) {
+    match value {
+        _ if version < VERSION_NEW_ENCODING => element.set_attribute(
+            name,
+            AttributeValue::String(value.unwrap_or(NULL_VALUE_OLD_STYLE).to_string()),
+        ),
+        None => {}
+        Some(value) if is_binary(value) => element.set_attribute(
+            format!("{}Base64", name),
+            AttributeValue::String(encode_base64(value)),
+        ),
+        Some(value) => element.set_attribute(name, AttributeValue::String(value.to_string())),
+    }
+}
+
+// Java strings are base64-encoded as their big-endian UTF-16 code units.
+fn encode_base64(value: &str) -> String {
+    let bytes: Vec<u8> = value.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    BASE64.encode(bytes)
+}
+
+fn decode_base64(encoded: &str) -> Result<String, ConversionError> {
+    let bytes = BASE64
+        .decode(encoded.trim())
+        .map_err(|_| ConversionError::InvalidBase64)?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units)
+        .map_err(|_| ConversionError::InvalidValue("UTF-16 string", encoded.to_string()))
+}
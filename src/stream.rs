@@ -0,0 +1,308 @@
+//! Streaming adapters between text XML and ABX.
+//!
+//! [`XmlToAbxWriter`] accepts XML in arbitrary chunks through
+//! [`std::io::Write`] and emits ABX as soon as complete events are available.
+//! [`AbxToXmlReader`] goes the other way, decoding ABX lazily behind
+//! [`std::io::Read`].
+//!
+//! ```rust
+//! use std::io::{Read, Write};
+//! use xml2abx::XmlToAbxWriter;
+//!
+//! let mut writer = XmlToAbxWriter::new(Vec::new()).unwrap();
+//! writer.write_all(b"<packages><package name=\"com.").unwrap();
+//! writer.write_all(b"foo\" /></packages>").unwrap();
+//! let abx = writer.finish().unwrap();
+//! assert!(abx.starts_with(b"ABX\0"));
+//!
+//! let mut xml = String::new();
+//! xml2abx::AbxToXmlReader::new(&abx[..]).unwrap().read_to_string(&mut xml).unwrap();
+//! assert!(xml.ends_with(r#"<packages><package name="com.foo" /></packages>"#));
+//! ```
+
+use crate::reader::{AbxEvent, AbxReader};
+use crate::serializer::{TextXmlSerializer, TypedXmlSerializer};
+use crate::{BinaryXmlSerializer, ConversionError, XmlToAbxConverter};
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::io::{self, Read, Write};
+
+/// Converts XML written to it into ABX on the inner writer.
+///
+/// Input is buffered only until the event it belongs to is complete: markup
+/// up to its closing `>`, text up to the next `<`. The output is the same as
+/// [`XmlToAbxConverter`] produces for the whole document, however the input
+/// is split. Call [`Self::finish`] at the end; dropping the writer leaves the
+/// ABX document unterminated.
+pub struct XmlToAbxWriter<W: Write> {
+    serializer: BinaryXmlSerializer<W>,
+    pending: Vec<u8>,
+    scanner: BoundaryScanner,
+    open_tags: Vec<String>,
+    preserve_whitespace: bool,
+}
+
+impl<W: Write> XmlToAbxWriter<W> {
+    pub fn new(writer: W) -> Result<Self, ConversionError> {
+        Self::with_options(writer, true)
+    }
+
+    pub fn with_options(writer: W, preserve_whitespace: bool) -> Result<Self, ConversionError> {
+        let mut serializer = BinaryXmlSerializer::with_options(writer, preserve_whitespace)?;
+        serializer.start_document()?;
+        Ok(Self {
+            serializer,
+            pending: Vec::new(),
+            scanner: BoundaryScanner::default(),
+            open_tags: Vec::new(),
+            preserve_whitespace,
+        })
+    }
+
+    /// Converts any trailing text, checks that every element was closed and
+    /// ends the ABX document. Returns the inner writer.
+    pub fn finish(mut self) -> Result<W, ConversionError> {
+        self.convert(self.pending.len())?;
+        if let Some(open) = self.open_tags.last() {
+            return Err(ConversionError::Malformed(format!(
+                "unclosed element <{}>",
+                open
+            )));
+        }
+        self.serializer.end_document()?;
+        Ok(self.serializer.into_inner())
+    }
+
+    // Converts and drops the first `len` pending bytes, which must hold
+    // complete events only.
+    fn convert(&mut self, len: usize) -> Result<(), ConversionError> {
+        if len == 0 {
+            return Ok(());
+        }
+        let mut reader = Reader::from_reader(&self.pending[..len]);
+        // Chunks may close elements opened by earlier ones, so nesting is
+        // checked here rather than by quick-xml.
+        let config = reader.config_mut();
+        config.trim_text(!self.preserve_whitespace);
+        config.check_end_names = false;
+        config.allow_unmatched_ends = true;
+        loop {
+            let event = reader.read_event()?;
+            match &event {
+                Event::Eof => break,
+                Event::Start(e) => {
+                    let name = std::str::from_utf8(e.name().as_ref())?.to_string();
+                    self.open_tags.push(name);
+                }
+                Event::End(e) => {
+                    let name = std::str::from_utf8(e.name().as_ref())?.to_string();
+                    match self.open_tags.pop() {
+                        Some(open) if open == name => {}
+                        Some(open) => {
+                            return Err(ConversionError::Malformed(format!(
+                                "end tag </{}> does not match <{}>",
+                                name, open
+                            )));
+                        }
+                        None => {
+                            return Err(ConversionError::Malformed(format!(
+                                "unexpected end tag </{}>",
+                                name
+                            )));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            XmlToAbxConverter::write_event(&mut self.serializer, event, reader.decoder())?;
+        }
+        self.pending.drain(..len);
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for XmlToAbxWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let len = self.scanner.complete_prefix(&self.pending);
+        self.convert(len)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.serializer.flush()?;
+        Ok(())
+    }
+}
+
+/// Decodes ABX from the inner reader into text XML, one event at a time, so
+/// only the XML of the current event is held in memory.
+///
+/// The output matches [`AbxDocument::write_xml_with_options`]. The inner
+/// reader is read a byte at a time; wrap files and sockets in a
+/// [`std::io::BufReader`].
+///
+/// [`AbxDocument::write_xml_with_options`]: crate::AbxDocument::write_xml_with_options
+pub struct AbxToXmlReader<R: Read> {
+    reader: AbxReader<R>,
+    serializer: TextXmlSerializer<Vec<u8>>,
+    // Read position in the serializer's buffer.
+    pos: usize,
+    pretty: bool,
+    done: bool,
+}
+
+impl<R: Read> AbxToXmlReader<R> {
+    /// Fails if the input does not start with the ABX magic header.
+    pub fn new(input: R) -> Result<Self, ConversionError> {
+        Self::with_options(input, false, false)
+    }
+
+    /// `pretty` and `annotate_types` are as for
+    /// [`AbxDocument::write_xml_with_options`](crate::AbxDocument::write_xml_with_options).
+    pub fn with_options(
+        input: R,
+        pretty: bool,
+        annotate_types: bool,
+    ) -> Result<Self, ConversionError> {
+        let serializer = if pretty {
+            TextXmlSerializer::with_indent(Vec::new(), "  ")
+        } else {
+            TextXmlSerializer::new(Vec::new())
+        };
+        Ok(Self {
+            reader: AbxReader::new(input)?,
+            serializer: serializer.with_type_annotations(annotate_types),
+            pos: 0,
+            pretty,
+            done: false,
+        })
+    }
+
+    // Decodes the next event into the buffer.
+    fn fill(&mut self) -> Result<(), ConversionError> {
+        let serializer = &mut self.serializer;
+        match self.reader.next_event()? {
+            None | Some(AbxEvent::EndDocument) => {
+                serializer.end_document()?;
+                self.done = true;
+            }
+            Some(AbxEvent::StartDocument) => serializer.start_document()?,
+            Some(AbxEvent::StartTag { name, attributes }) => {
+                serializer.start_tag(&name)?;
+                for attr in &attributes {
+                    attr.value.write(serializer, &attr.name)?;
+                }
+            }
+            Some(AbxEvent::EndTag(name)) => serializer.end_tag(&name)?,
+            Some(AbxEvent::Text(t)) if self.pretty && t.trim().is_empty() => {}
+            Some(AbxEvent::Text(t)) => serializer.text(&t)?,
+            Some(AbxEvent::CdSect(t)) => serializer.cdsect(&t)?,
+            Some(AbxEvent::EntityRef(t)) => serializer.entity_ref(&t)?,
+            Some(AbxEvent::IgnorableWhitespace(_)) if self.pretty => {}
+            Some(AbxEvent::IgnorableWhitespace(t)) => serializer.ignorable_whitespace(&t)?,
+            Some(AbxEvent::ProcessingInstruction(t)) => {
+                serializer.processing_instruction(&t, None)?
+            }
+            Some(AbxEvent::Comment(t)) => serializer.comment(&t)?,
+            Some(AbxEvent::DocDecl(t)) => serializer.docdecl(&t)?,
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for AbxToXmlReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.serializer.get_mut().len() {
+            self.serializer.get_mut().clear();
+            self.pos = 0;
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let pending = &self.serializer.get_mut()[self.pos..];
+        let len = pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&pending[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// Finds event boundaries in XML that arrives in pieces. The state of the
+// incomplete event at the end of the input is kept, so a large event
+// written in small chunks is scanned once rather than once per chunk.
+#[derive(Default)]
+struct BoundaryScanner {
+    // Bytes of the incomplete event already examined.
+    checked: usize,
+    // Open attribute quote and `[` nesting of a tag or declaration.
+    quote: Option<u8>,
+    depth: usize,
+}
+
+impl BoundaryScanner {
+    // Length of the longest prefix of `data` that ends on an event boundary.
+    // Each call must pass the previous input minus the prefix returned for
+    // it, plus any new bytes.
+    fn complete_prefix(&mut self, data: &[u8]) -> usize {
+        let mut pos = 0;
+        while pos < data.len() {
+            match self.event_len(&data[pos..]) {
+                Some(len) => {
+                    pos += len;
+                    *self = Self::default();
+                }
+                None => break,
+            }
+        }
+        pos
+    }
+
+    fn event_len(&mut self, rest: &[u8]) -> Option<usize> {
+        let from = self.checked;
+        self.checked = rest.len();
+        if rest[0] != b'<' {
+            // Text is complete once the next markup starts.
+            rest[from..]
+                .iter()
+                .position(|&b| b == b'<')
+                .map(|i| from + i)
+        } else if rest.starts_with(b"<!--") {
+            find_from(rest, b"-->", from.max(4))
+        } else if rest.starts_with(b"<![CDATA[") {
+            find_from(rest, b"]]>", from.max(9))
+        } else if b"<!--".starts_with(rest) || b"<![CDATA[".starts_with(rest) {
+            // Not yet known what kind of markup this is.
+            self.checked = 0;
+            None
+        } else if rest.starts_with(b"<?") {
+            find_from(rest, b"?>", from.max(2))
+        } else {
+            // A start or end tag, or a `<!DOCTYPE ...>` that may carry an
+            // internal subset in brackets, up to the `>` outside quotes.
+            let declaration = rest.starts_with(b"<!");
+            for (i, &b) in rest.iter().enumerate().skip(from) {
+                match (self.quote, b) {
+                    (None, b'"' | b'\'') => self.quote = Some(b),
+                    (Some(q), _) if q == b => self.quote = None,
+                    (None, b'[') if declaration => self.depth += 1,
+                    (None, b']') if declaration => self.depth = self.depth.saturating_sub(1),
+                    (None, b'>') if self.depth == 0 => return Some(i + 1),
+                    _ => {}
+                }
+            }
+            None
+        }
+    }
+}
+
+// End of the first `needle` in `haystack` starting at or after `from`. The
+// search backs up so a needle split across chunks is still found.
+fn find_from(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    let start = from.saturating_sub(needle.len() - 1);
+    haystack[start..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| start + i + needle.len())
+}
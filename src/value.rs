@@ -0,0 +1,406 @@
+//! Typed attribute values mirroring the `TYPE_*` encodings of the ABX format.
+
+use crate::serializer::TypedXmlSerializer;
+use crate::{BinaryXmlSerializer, ConversionError};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Interned(String),
+    BytesHex(Vec<u8>),
+    BytesBase64(Vec<u8>),
+    Int(i32),
+    IntHex(i32),
+    Long(i64),
+    LongHex(i64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: AttributeValue,
+}
+
+impl Attribute {
+    pub fn new(name: impl Into<String>, value: AttributeValue) -> Self {
+        Self {
+            name: name.into(),
+            value,
+        }
+    }
+}
+
+impl AttributeValue {
+    /// Names accepted by [`Self::parse`], as returned by [`Self::type_name`].
+    pub const TYPE_NAMES: [&'static str; 11] = [
+        "string",
+        "interned",
+        "bytes-hex",
+        "bytes-base64",
+        "int",
+        "int-hex",
+        "long",
+        "long-hex",
+        "float",
+        "double",
+        "boolean",
+    ];
+
+    /// Suffix of the attributes carrying types in annotated XML, e.g.
+    /// `ft:abx-type="long-hex"` for the attribute `ft`.
+    pub const TYPE_ANNOTATION_SUFFIX: &'static str = ":abx-type";
+
+    /// Types a plain XML attribute value the same way `XmlToAbxConverter` does.
+    pub fn infer(value: &str) -> Self {
+        if value == "true" || value == "false" {
+            AttributeValue::Bool(value == "true")
+        } else if value.len() < 50 && !value.contains(' ') {
+            AttributeValue::Interned(value.to_string())
+        } else {
+            AttributeValue::String(value.to_string())
+        }
+    }
+
+    /// Whether converting the text form back to ABX would pick another type,
+    /// so annotated XML has to spell out the type.
+    pub fn needs_type_annotation(&self) -> bool {
+        Self::infer(&self.to_string()).type_name() != self.type_name()
+    }
+
+    /// Parses the text form of a value of the given type (see [`Self::type_name`]).
+    pub fn parse(type_name: &str, text: &str) -> Result<Self, ConversionError> {
+        let invalid = |name: &'static str| ConversionError::InvalidValue(name, text.to_string());
+        Ok(match type_name {
+            "string" => AttributeValue::String(text.to_string()),
+            "interned" => AttributeValue::Interned(text.to_string()),
+            "bytes-hex" => AttributeValue::BytesHex(decode_hex(text)?),
+            "bytes-base64" => AttributeValue::BytesBase64(decode_base64(text)?),
+            "int" => AttributeValue::Int(text.trim().parse().map_err(|_| invalid("int"))?),
+            "int-hex" => {
+                AttributeValue::IntHex(parse_int_hex(text).ok_or_else(|| invalid("int-hex"))?)
+            }
+            "long" => AttributeValue::Long(text.trim().parse().map_err(|_| invalid("long"))?),
+            "long-hex" => {
+                AttributeValue::LongHex(parse_long_hex(text).ok_or_else(|| invalid("long-hex"))?)
+            }
+            "float" => AttributeValue::Float(text.trim().parse().map_err(|_| invalid("float"))?),
+            "double" => AttributeValue::Double(text.trim().parse().map_err(|_| invalid("double"))?),
+            "boolean" => match text.trim() {
+                "true" => AttributeValue::Bool(true),
+                "false" => AttributeValue::Bool(false),
+                _ => return Err(invalid("boolean")),
+            },
+            _ => return Err(ConversionError::UnknownType(type_name.to_string())),
+        })
+    }
+
+    /// Parses `text` as a value of the same type as `self`.
+    pub fn with_text(&self, text: &str) -> Result<Self, ConversionError> {
+        Self::parse(self.type_name(), text)
+    }
+
+    // The `as_*` accessors follow `BinaryXmlPullParser`: a value stored with a
+    // compatible encoding is returned directly, anything else is parsed from
+    // its text form.
+
+    pub fn as_bytes_hex(&self) -> Result<Vec<u8>, ConversionError> {
+        match self {
+            AttributeValue::BytesHex(v) | AttributeValue::BytesBase64(v) => Ok(v.clone()),
+            _ => decode_hex(&self.to_string()),
+        }
+    }
+    pub fn as_bytes_base64(&self) -> Result<Vec<u8>, ConversionError> {
+        match self {
+            AttributeValue::BytesHex(v) | AttributeValue::BytesBase64(v) => Ok(v.clone()),
+            _ => decode_base64(&self.to_string()),
+        }
+    }
+    pub fn as_int(&self) -> Result<i32, ConversionError> {
+        match self {
+            AttributeValue::Int(v) | AttributeValue::IntHex(v) => Ok(*v),
+            AttributeValue::Long(v) | AttributeValue::LongHex(v) => narrow(*v, "int"),
+            _ => parse_text(self, "int", |t| t.trim().parse().ok()),
+        }
+    }
+    pub fn as_int_hex(&self) -> Result<i32, ConversionError> {
+        match self {
+            AttributeValue::Int(v) | AttributeValue::IntHex(v) => Ok(*v),
+            AttributeValue::Long(v) | AttributeValue::LongHex(v) => narrow(*v, "int-hex"),
+            _ => parse_text(self, "int-hex", parse_int_hex),
+        }
+    }
+    pub fn as_long(&self) -> Result<i64, ConversionError> {
+        match self {
+            AttributeValue::Int(v) | AttributeValue::IntHex(v) => Ok(*v as i64),
+            AttributeValue::Long(v) | AttributeValue::LongHex(v) => Ok(*v),
+            _ => parse_text(self, "long", |t| t.trim().parse().ok()),
+        }
+    }
+    pub fn as_long_hex(&self) -> Result<i64, ConversionError> {
+        match self {
+            AttributeValue::Int(v) | AttributeValue::IntHex(v) => Ok(*v as i64),
+            AttributeValue::Long(v) | AttributeValue::LongHex(v) => Ok(*v),
+            _ => parse_text(self, "long-hex", parse_long_hex),
+        }
+    }
+    pub fn as_float(&self) -> Result<f32, ConversionError> {
+        match self {
+            AttributeValue::Float(v) => Ok(*v),
+            _ => parse_text(self, "float", |t| t.trim().parse().ok()),
+        }
+    }
+    pub fn as_double(&self) -> Result<f64, ConversionError> {
+        match self {
+            AttributeValue::Float(v) => Ok(*v as f64),
+            AttributeValue::Double(v) => Ok(*v),
+            _ => parse_text(self, "double", |t| t.trim().parse().ok()),
+        }
+    }
+    pub fn as_bool(&self) -> Result<bool, ConversionError> {
+        match self {
+            AttributeValue::Bool(v) => Ok(*v),
+            _ => parse_text(self, "boolean", |t| match t.trim() {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => None,
+            }),
+        }
+    }
+
+    /// Re-encodes the value as the given type (see [`Self::type_name`]),
+    /// using the `as_*` accessors.
+    pub fn convert(&self, type_name: &str) -> Result<Self, ConversionError> {
+        Ok(match type_name {
+            "string" => AttributeValue::String(self.to_string()),
+            "interned" => AttributeValue::Interned(self.to_string()),
+            "bytes-hex" => AttributeValue::BytesHex(self.as_bytes_hex()?),
+            "bytes-base64" => AttributeValue::BytesBase64(self.as_bytes_base64()?),
+            "int" => AttributeValue::Int(self.as_int()?),
+            "int-hex" => AttributeValue::IntHex(self.as_int_hex()?),
+            "long" => AttributeValue::Long(self.as_long()?),
+            "long-hex" => AttributeValue::LongHex(self.as_long_hex()?),
+            "float" => AttributeValue::Float(self.as_float()?),
+            "double" => AttributeValue::Double(self.as_double()?),
+            "boolean" => AttributeValue::Bool(self.as_bool()?),
+            _ => return Err(ConversionError::UnknownType(type_name.to_string())),
+        })
+    }
+
+    pub fn type_code(&self) -> u8 {
+        type S = BinaryXmlSerializer<Vec<u8>>;
+        match self {
+            AttributeValue::String(_) => S::TYPE_STRING,
+            AttributeValue::Interned(_) => S::TYPE_STRING_INTERNED,
+            AttributeValue::BytesHex(_) => S::TYPE_BYTES_HEX,
+            AttributeValue::BytesBase64(_) => S::TYPE_BYTES_BASE64,
+            AttributeValue::Int(_) => S::TYPE_INT,
+            AttributeValue::IntHex(_) => S::TYPE_INT_HEX,
+            AttributeValue::Long(_) => S::TYPE_LONG,
+            AttributeValue::LongHex(_) => S::TYPE_LONG_HEX,
+            AttributeValue::Float(_) => S::TYPE_FLOAT,
+            AttributeValue::Double(_) => S::TYPE_DOUBLE,
+            AttributeValue::Bool(true) => S::TYPE_BOOLEAN_TRUE,
+            AttributeValue::Bool(false) => S::TYPE_BOOLEAN_FALSE,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            AttributeValue::String(_) => "string",
+            AttributeValue::Interned(_) => "interned",
+            AttributeValue::BytesHex(_) => "bytes-hex",
+            AttributeValue::BytesBase64(_) => "bytes-base64",
+            AttributeValue::Int(_) => "int",
+            AttributeValue::IntHex(_) => "int-hex",
+            AttributeValue::Long(_) => "long",
+            AttributeValue::LongHex(_) => "long-hex",
+            AttributeValue::Float(_) => "float",
+            AttributeValue::Double(_) => "double",
+            AttributeValue::Bool(_) => "boolean",
+        }
+    }
+
+    pub fn write<S: TypedXmlSerializer + ?Sized>(
+        &self,
+        serializer: &mut S,
+        name: &str,
+    ) -> Result<(), ConversionError> {
+        match self {
+            AttributeValue::String(v) => serializer.attribute(name, v),
+            AttributeValue::Interned(v) => serializer.attribute_interned(name, v),
+            AttributeValue::BytesHex(v) => serializer.attribute_bytes_hex(name, v),
+            AttributeValue::BytesBase64(v) => serializer.attribute_bytes_base64(name, v),
+            AttributeValue::Int(v) => serializer.attribute_int(name, *v),
+            AttributeValue::IntHex(v) => serializer.attribute_int_hex(name, *v),
+            AttributeValue::Long(v) => serializer.attribute_long(name, *v),
+            AttributeValue::LongHex(v) => serializer.attribute_long_hex(name, *v),
+            AttributeValue::Float(v) => serializer.attribute_float(name, *v),
+            AttributeValue::Double(v) => serializer.attribute_double(name, *v),
+            AttributeValue::Bool(v) => serializer.attribute_boolean(name, *v),
+        }
+    }
+}
+
+/// Renders the value the way `BinaryXmlPullParser.getAttributeValue()` does.
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::String(v) | AttributeValue::Interned(v) => f.write_str(v),
+            AttributeValue::BytesHex(v) => f.write_str(&encode_hex(v)),
+            AttributeValue::BytesBase64(v) => f.write_str(&BASE64.encode(v)),
+            AttributeValue::Int(v) => write!(f, "{}", v),
+            AttributeValue::IntHex(v) => f.write_str(&java_hex(*v as i64)),
+            AttributeValue::Long(v) => write!(f, "{}", v),
+            AttributeValue::LongHex(v) => f.write_str(&java_hex(*v)),
+            AttributeValue::Float(v) => f.write_str(&java_float(*v as f64, v.to_string())),
+            AttributeValue::Double(v) => f.write_str(&java_float(*v, v.to_string())),
+            AttributeValue::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for &b in bytes {
+        out.push(DIGITS[(b >> 4) as usize] as char);
+        out.push(DIGITS[(b & 0x0f) as usize] as char);
+    }
+    out
+}
+
+pub(crate) fn decode_hex(s: &str) -> Result<Vec<u8>, ConversionError> {
+    let s = s.trim();
+    if !s.len().is_multiple_of(2) {
+        return Err(ConversionError::InvalidHex);
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16);
+            let lo = (pair[1] as char).to_digit(16);
+            match (hi, lo) {
+                (Some(hi), Some(lo)) => Ok((hi * 16 + lo) as u8),
+                _ => Err(ConversionError::InvalidHex),
+            }
+        })
+        .collect()
+}
+
+fn decode_base64(s: &str) -> Result<Vec<u8>, ConversionError> {
+    BASE64
+        .decode(s.trim())
+        .map_err(|_| ConversionError::InvalidBase64)
+}
+
+// Parses the text form of `value`, reporting `type_name` on failure.
+fn parse_text<T>(
+    value: &AttributeValue,
+    type_name: &'static str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<T, ConversionError> {
+    let text = value.to_string();
+    parse(&text).ok_or(ConversionError::InvalidValue(type_name, text))
+}
+
+// Accepts the signed and the unsigned 32-bit range, like `parse_int_hex`.
+fn narrow(value: i64, type_name: &'static str) -> Result<i32, ConversionError> {
+    i32::try_from(value)
+        .ok()
+        .or_else(|| u32::try_from(value).ok().map(|u| u as i32))
+        .ok_or_else(|| ConversionError::InvalidValue(type_name, value.to_string()))
+}
+
+fn parse_int_hex(text: &str) -> Option<i32> {
+    parse_hex(text).and_then(|v| {
+        i32::try_from(v)
+            .ok()
+            .or_else(|| u32::try_from(v).ok().map(|u| u as i32))
+    })
+}
+
+fn parse_long_hex(text: &str) -> Option<i64> {
+    parse_hex(text).and_then(|v| {
+        i64::try_from(v)
+            .ok()
+            .or_else(|| u64::try_from(v).ok().map(|u| u as i64))
+    })
+}
+
+// Accepts Java's signed `Long.toString(value, 16)` form as well as the
+// unsigned two's complement form, with an optional `0x` prefix.
+fn parse_hex(text: &str) -> Option<i128> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let digits = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+        .unwrap_or(digits);
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    let value = i128::from_str_radix(digits, 16).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+// Equivalent of Java's `Long.toString(value, 16)`.
+fn java_hex(value: i64) -> String {
+    if value < 0 {
+        format!("-{:x}", value.unsigned_abs())
+    } else {
+        format!("{:x}", value)
+    }
+}
+
+// Equivalent of Java's `Float.toString` / `Double.toString`: plain notation for
+// magnitudes in [1e-3, 1e7), computerized scientific notation otherwise, and
+// always at least one fractional digit. `shortest` is Rust's shortest
+// round-tripping rendering of the original value.
+fn java_float(value: f64, shortest: String) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() {
+            "-0.0"
+        } else {
+            "0.0"
+        }
+        .to_string();
+    }
+    let (sign, body) = match shortest.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", shortest.as_str()),
+    };
+    let (int_part, frac_part) = body.split_once('.').unwrap_or((body, ""));
+    let magnitude = value.abs();
+    if (1e-3..1e7).contains(&magnitude) {
+        let frac = if frac_part.is_empty() { "0" } else { frac_part };
+        return format!("{}{}.{}", sign, int_part, frac);
+    }
+    let (digits, exponent) = if int_part != "0" {
+        (
+            format!("{}{}", int_part, frac_part),
+            int_part.len() as i32 - 1,
+        )
+    } else {
+        let zeros = frac_part.len() - frac_part.trim_start_matches('0').len();
+        (frac_part[zeros..].to_string(), -(zeros as i32) - 1)
+    };
+    let digits = digits.trim_end_matches('0');
+    let (first, rest) = digits.split_at(1);
+    let rest = if rest.is_empty() { "0" } else { rest };
+    format!("{}{}.{}E{}", sign, first, rest, exponent)
+}
@@ -0,0 +1,349 @@
+//! `#[derive(AbxWrite, AbxRead)]` for `xml2abx`.
+//!
+//! See the `xml2abx::codec` module for the supported `#[abx(...)]`
+//! attributes. Use the macros through `xml2abx` with the `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Data, DeriveInput, Fields, GenericArgument, Ident, LitStr, PathArguments, Type,
+    parse_macro_input,
+};
+
+#[proc_macro_derive(AbxWrite, attributes(abx))]
+pub fn derive_abx_write(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_write(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(AbxRead, attributes(abx))]
+pub fn derive_abx_read(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_read(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+enum Kind {
+    Attr,
+    Child,
+    Text,
+    Skip,
+}
+
+struct FieldSpec<'a> {
+    ident: &'a Ident,
+    ty: &'a Type,
+    name: String,
+    kind: Kind,
+    // ABX type name, e.g. "long-hex"; `None` uses the field type's default.
+    encoding: Option<&'static str>,
+}
+
+const ENCODINGS: [(&str, &str); 11] = [
+    ("string", "string"),
+    ("interned", "interned"),
+    ("bytes_hex", "bytes-hex"),
+    ("bytes_base64", "bytes-base64"),
+    ("int", "int"),
+    ("int_hex", "int-hex"),
+    ("long", "long"),
+    ("long_hex", "long-hex"),
+    ("float", "float"),
+    ("double", "double"),
+    ("boolean", "boolean"),
+];
+
+fn container_tag(input: &DeriveInput) -> syn::Result<String> {
+    let mut tag = input.ident.to_string();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("abx")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `tag = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(tag)
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<FieldSpec<'_>>> {
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => &named.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "AbxWrite/AbxRead require a struct with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "AbxWrite/AbxRead can only be derived for structs",
+            ));
+        }
+    };
+    let mut specs = Vec::new();
+    for field in named {
+        let ident = field.ident.as_ref().expect("named field");
+        let mut spec = FieldSpec {
+            ident,
+            ty: &field.ty,
+            name: ident.to_string(),
+            kind: Kind::Attr,
+            encoding: None,
+        };
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("abx")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("attr") {
+                    spec.kind = Kind::Attr;
+                } else if meta.path.is_ident("child") {
+                    spec.kind = Kind::Child;
+                } else if meta.path.is_ident("text") {
+                    spec.kind = Kind::Text;
+                } else if meta.path.is_ident("skip") {
+                    spec.kind = Kind::Skip;
+                } else if meta.path.is_ident("rename") {
+                    spec.name = meta.value()?.parse::<LitStr>()?.value();
+                } else if let Some((_, encoding)) =
+                    ENCODINGS.iter().find(|(key, _)| meta.path.is_ident(key))
+                {
+                    spec.encoding = Some(encoding);
+                } else {
+                    return Err(meta.error("unknown abx attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        if spec.encoding.is_some() && !matches!(spec.kind, Kind::Attr) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "an ABX type can only be given for attributes",
+            ));
+        }
+        specs.push(spec);
+    }
+    Ok(specs)
+}
+
+// Returns `T` for a type written as `wrapper<T>` (e.g. `Option<T>`).
+fn inner_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+fn write_attribute(name: &str, encoding: Option<&str>) -> TokenStream2 {
+    let call = match encoding {
+        None => return quote! { value.write(serializer, #name)?; },
+        Some("string") => quote! { attribute(#name, &value.to_string()) },
+        Some("interned") => quote! { attribute_interned(#name, &value.to_string()) },
+        Some("bytes-hex") => quote! { attribute_bytes_hex(#name, &value.as_bytes_hex()?) },
+        Some("bytes-base64") => {
+            quote! { attribute_bytes_base64(#name, &value.as_bytes_base64()?) }
+        }
+        Some("int") => quote! { attribute_int(#name, value.as_int()?) },
+        Some("int-hex") => quote! { attribute_int_hex(#name, value.as_int_hex()?) },
+        Some("long") => quote! { attribute_long(#name, value.as_long()?) },
+        Some("long-hex") => quote! { attribute_long_hex(#name, value.as_long_hex()?) },
+        Some("float") => quote! { attribute_float(#name, value.as_float()?) },
+        Some("double") => quote! { attribute_double(#name, value.as_double()?) },
+        Some(_) => quote! { attribute_boolean(#name, value.as_bool()?) },
+    };
+    quote! { serializer.#call?; }
+}
+
+fn expand_write(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let tag = container_tag(input)?;
+    let fields = fields(input)?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut attributes = Vec::new();
+    let mut text = Vec::new();
+    let mut children = Vec::new();
+    for field in &fields {
+        let member = field.ident;
+        let name = &field.name;
+        match field.kind {
+            Kind::Attr => {
+                let write = write_attribute(name, field.encoding);
+                attributes.push(if inner_type(field.ty, "Option").is_some() {
+                    quote! {
+                        if let Some(field) = &self.#member {
+                            let value = ::xml2abx::AttributeField::to_value(field);
+                            #write
+                        }
+                    }
+                } else {
+                    quote! {
+                        let value = ::xml2abx::AttributeField::to_value(&self.#member);
+                        #write
+                    }
+                });
+            }
+            Kind::Text => text.push(quote! {
+                let text = ::xml2abx::AttributeField::to_value(&self.#member).to_string();
+                if !text.is_empty() {
+                    serializer.text(&text)?;
+                }
+            }),
+            Kind::Child => children.push(if inner_type(field.ty, "Vec").is_some() {
+                quote! {
+                    for child in &self.#member {
+                        ::xml2abx::AbxWrite::write_element(child, serializer, #name)?;
+                    }
+                }
+            } else if inner_type(field.ty, "Option").is_some() {
+                quote! {
+                    if let Some(child) = &self.#member {
+                        ::xml2abx::AbxWrite::write_element(child, serializer, #name)?;
+                    }
+                }
+            } else {
+                quote! {
+                    ::xml2abx::AbxWrite::write_element(&self.#member, serializer, #name)?;
+                }
+            }),
+            Kind::Skip => {}
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::xml2abx::AbxWrite for #ident #ty_generics #where_clause {
+            const TAG: &'static str = #tag;
+
+            fn write_element<W: ::std::io::Write>(
+                &self,
+                serializer: &mut ::xml2abx::BinaryXmlSerializer<W>,
+                tag: &str,
+            ) -> ::std::result::Result<(), ::xml2abx::ConversionError> {
+                serializer.start_tag(tag)?;
+                #(#attributes)*
+                #(#text)*
+                #(#children)*
+                serializer.end_tag(tag)
+            }
+        }
+    })
+}
+
+fn expand_read(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let fields = fields(input)?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut reads = Vec::new();
+    let mut on_child = Vec::new();
+    let mut finish = Vec::new();
+    let mut text_field = None;
+    for field in &fields {
+        let member = field.ident;
+        let name = &field.name;
+        let ty = field.ty;
+        match field.kind {
+            Kind::Attr => {
+                let optional = inner_type(ty, "Option");
+                let value_ty = optional.unwrap_or(ty);
+                let value = match field.encoding {
+                    Some(encoding) => quote! { &value.convert(#encoding)? },
+                    None => quote! { value },
+                };
+                reads.push(quote! {
+                    let #member = match ::xml2abx::TypedXmlPullParser::get_attribute(parser, #name) {
+                        Some(value) => Some(
+                            <#value_ty as ::xml2abx::AttributeField>::from_value(#value)?,
+                        ),
+                        None => None,
+                    };
+                });
+                if optional.is_none() {
+                    finish.push(quote! {
+                        let #member = #member.ok_or_else(|| {
+                            ::xml2abx::ConversionError::MissingAttribute(#name.to_string())
+                        })?;
+                    });
+                }
+            }
+            Kind::Child => {
+                if let Some(item) = inner_type(ty, "Vec") {
+                    reads.push(quote! { let mut #member: #ty = Vec::new(); });
+                    on_child.push(quote! {
+                        if name == #name {
+                            #member.push(<#item as ::xml2abx::AbxRead>::read_element(parser)?);
+                            return Ok(true);
+                        }
+                    });
+                } else {
+                    let optional = inner_type(ty, "Option");
+                    let item = optional.unwrap_or(ty);
+                    reads.push(quote! { let mut #member: Option<#item> = None; });
+                    on_child.push(quote! {
+                        if name == #name {
+                            #member = Some(<#item as ::xml2abx::AbxRead>::read_element(parser)?);
+                            return Ok(true);
+                        }
+                    });
+                    if optional.is_none() {
+                        let message = format!("missing <{}> element", name);
+                        finish.push(quote! {
+                            let #member = #member.ok_or_else(|| {
+                                ::xml2abx::ConversionError::Malformed(#message.to_string())
+                            })?;
+                        });
+                    }
+                }
+            }
+            Kind::Text => text_field = Some((member, ty)),
+            Kind::Skip => finish.push(quote! {
+                let #member: #ty = ::std::default::Default::default();
+            }),
+        }
+    }
+
+    let text = match text_field {
+        Some((member, ty)) => quote! {
+            let #member = <#ty as ::xml2abx::AttributeField>::from_value(
+                &::xml2abx::AttributeValue::String(text),
+            )?;
+        },
+        None => quote! { let _ = text; },
+    };
+    let members = fields.iter().map(|f| f.ident);
+
+    Ok(quote! {
+        impl #impl_generics ::xml2abx::AbxRead for #ident #ty_generics #where_clause {
+            fn read_element<P: ::xml2abx::TypedXmlPullParser>(
+                parser: &mut P,
+            ) -> ::std::result::Result<Self, ::xml2abx::ConversionError> {
+                #(#reads)*
+                let text = ::xml2abx::codec::read_children(parser, |parser, name| {
+                    #(#on_child)*
+                    let _ = (parser, name);
+                    Ok(false)
+                })?;
+                #text
+                #(#finish)*
+                Ok(Self { #(#members),* })
+            }
+        }
+    })
+}
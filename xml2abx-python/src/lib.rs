@@ -0,0 +1,236 @@
+//! Python bindings, built with maturin as the `xml2abx` module.
+//!
+//! ```python
+//! import xml2abx
+//!
+//! abx = xml2abx.xml_to_abx('<packages><package name="com.foo" /></packages>')
+//! print(xml2abx.abx_to_xml(abx, pretty=True))
+//!
+//! s = xml2abx.BinaryXmlSerializer()
+//! s.start_document()
+//! s.start_tag("package")
+//! s.attribute_interned("name", "com.foo")
+//! s.attribute_long_hex("ft", 0x18c5a3f1e20)
+//! s.end_tag("package")
+//! s.end_document()
+//! fixture = s.getvalue()
+//!
+//! try:
+//!     xml2abx.abx_to_xml(b"not abx")
+//! except xml2abx.ConversionError as e:
+//!     assert e.kind == "InvalidMagic"
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use xml2abx::{AbxDocument, BinaryXmlSerializer, ConversionError, XmlToAbxConverter};
+
+mod exceptions {
+    use super::*;
+
+    create_exception!(
+        xml2abx,
+        ConversionError,
+        PyException,
+        "Raised when a conversion fails. `kind` names the Rust `ConversionError` variant."
+    );
+}
+
+fn to_py_err(error: ConversionError) -> PyErr {
+    Python::attach(|py| {
+        let err = exceptions::ConversionError::new_err(error.to_string());
+        // Exception instances accept new attributes, so this cannot fail.
+        let _ = err.value(py).setattr("kind", error.kind());
+        err
+    })
+}
+
+fn io_err(error: std::io::Error) -> PyErr {
+    to_py_err(ConversionError::Io(error))
+}
+
+/// Converts an XML document to ABX.
+#[pyfunction]
+#[pyo3(signature = (xml, preserve_whitespace = true))]
+fn xml_to_abx<'py>(
+    py: Python<'py>,
+    xml: &str,
+    preserve_whitespace: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let mut output = Vec::new();
+    XmlToAbxConverter::convert_from_string_with_options(xml, &mut output, preserve_whitespace)
+        .map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &output))
+}
+
+/// Converts an ABX document to XML.
+#[pyfunction]
+#[pyo3(signature = (abx, pretty = false, annotate_types = false))]
+fn abx_to_xml(abx: &[u8], pretty: bool, annotate_types: bool) -> PyResult<String> {
+    let mut output = Vec::new();
+    AbxDocument::from_abx(abx)
+        .and_then(|doc| doc.write_xml_with_options(&mut output, pretty, annotate_types))
+        .map_err(to_py_err)?;
+    String::from_utf8(output).map_err(|e| to_py_err(e.utf8_error().into()))
+}
+
+/// Converts the XML file at `input` to an ABX file at `output`.
+#[pyfunction]
+#[pyo3(signature = (input, output, preserve_whitespace = true))]
+fn xml_file_to_abx(input: &str, output: &str, preserve_whitespace: bool) -> PyResult<()> {
+    let writer = BufWriter::new(File::create(output).map_err(io_err)?);
+    XmlToAbxConverter::convert_from_file_with_options(input, writer, preserve_whitespace)
+        .map_err(to_py_err)
+}
+
+/// Converts the ABX file at `input` to an XML file at `output`.
+#[pyfunction]
+#[pyo3(signature = (input, output, pretty = false, annotate_types = false))]
+fn abx_file_to_xml(input: &str, output: &str, pretty: bool, annotate_types: bool) -> PyResult<()> {
+    let reader = BufReader::new(File::open(input).map_err(io_err)?);
+    let doc = AbxDocument::from_abx(reader).map_err(to_py_err)?;
+    let writer = BufWriter::new(File::create(output).map_err(io_err)?);
+    doc.write_xml_with_options(writer, pretty, annotate_types)
+        .map_err(to_py_err)
+}
+
+/// In-memory [`BinaryXmlSerializer`]; `getvalue()` returns the ABX written
+/// so far.
+#[pyclass(name = "BinaryXmlSerializer", module = "xml2abx")]
+struct PyBinaryXmlSerializer {
+    inner: BinaryXmlSerializer<Vec<u8>>,
+}
+
+#[pymethods]
+impl PyBinaryXmlSerializer {
+    #[new]
+    #[pyo3(signature = (preserve_whitespace = true))]
+    fn new(preserve_whitespace: bool) -> PyResult<Self> {
+        Ok(Self {
+            inner: BinaryXmlSerializer::with_options(Vec::new(), preserve_whitespace)
+                .map_err(to_py_err)?,
+        })
+    }
+
+    fn getvalue<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.inner.get_mut())
+    }
+
+    fn start_document(&mut self) -> PyResult<()> {
+        self.inner.start_document().map_err(to_py_err)
+    }
+
+    fn end_document(&mut self) -> PyResult<()> {
+        self.inner.end_document().map_err(to_py_err)
+    }
+
+    fn start_tag(&mut self, name: &str) -> PyResult<()> {
+        self.inner.start_tag(name).map_err(to_py_err)
+    }
+
+    fn end_tag(&mut self, name: &str) -> PyResult<()> {
+        self.inner.end_tag(name).map_err(to_py_err)
+    }
+
+    fn attribute(&mut self, name: &str, value: &str) -> PyResult<()> {
+        self.inner.attribute(name, value).map_err(to_py_err)
+    }
+
+    fn attribute_interned(&mut self, name: &str, value: &str) -> PyResult<()> {
+        self.inner
+            .attribute_interned(name, value)
+            .map_err(to_py_err)
+    }
+
+    fn attribute_bytes_hex(&mut self, name: &str, value: &[u8]) -> PyResult<()> {
+        self.inner
+            .attribute_bytes_hex(name, value)
+            .map_err(to_py_err)
+    }
+
+    fn attribute_bytes_base64(&mut self, name: &str, value: &[u8]) -> PyResult<()> {
+        self.inner
+            .attribute_bytes_base64(name, value)
+            .map_err(to_py_err)
+    }
+
+    fn attribute_int(&mut self, name: &str, value: i32) -> PyResult<()> {
+        self.inner.attribute_int(name, value).map_err(to_py_err)
+    }
+
+    fn attribute_int_hex(&mut self, name: &str, value: i32) -> PyResult<()> {
+        self.inner.attribute_int_hex(name, value).map_err(to_py_err)
+    }
+
+    fn attribute_long(&mut self, name: &str, value: i64) -> PyResult<()> {
+        self.inner.attribute_long(name, value).map_err(to_py_err)
+    }
+
+    fn attribute_long_hex(&mut self, name: &str, value: i64) -> PyResult<()> {
+        self.inner
+            .attribute_long_hex(name, value)
+            .map_err(to_py_err)
+    }
+
+    fn attribute_float(&mut self, name: &str, value: f32) -> PyResult<()> {
+        self.inner.attribute_float(name, value).map_err(to_py_err)
+    }
+
+    fn attribute_double(&mut self, name: &str, value: f64) -> PyResult<()> {
+        self.inner.attribute_double(name, value).map_err(to_py_err)
+    }
+
+    fn attribute_boolean(&mut self, name: &str, value: bool) -> PyResult<()> {
+        self.inner.attribute_boolean(name, value).map_err(to_py_err)
+    }
+
+    fn text(&mut self, text: &str) -> PyResult<()> {
+        self.inner.text(text).map_err(to_py_err)
+    }
+
+    fn cdsect(&mut self, text: &str) -> PyResult<()> {
+        self.inner.cdsect(text).map_err(to_py_err)
+    }
+
+    fn comment(&mut self, text: &str) -> PyResult<()> {
+        self.inner.comment(text).map_err(to_py_err)
+    }
+
+    #[pyo3(signature = (target, data = None))]
+    fn processing_instruction(&mut self, target: &str, data: Option<&str>) -> PyResult<()> {
+        self.inner
+            .processing_instruction(target, data)
+            .map_err(to_py_err)
+    }
+
+    fn docdecl(&mut self, text: &str) -> PyResult<()> {
+        self.inner.docdecl(text).map_err(to_py_err)
+    }
+
+    fn ignorable_whitespace(&mut self, text: &str) -> PyResult<()> {
+        self.inner.ignorable_whitespace(text).map_err(to_py_err)
+    }
+
+    fn entity_ref(&mut self, text: &str) -> PyResult<()> {
+        self.inner.entity_ref(text).map_err(to_py_err)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "xml2abx")]
+fn xml2abx_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add(
+        "ConversionError",
+        m.py().get_type::<exceptions::ConversionError>(),
+    )?;
+    m.add_class::<PyBinaryXmlSerializer>()?;
+    m.add_function(wrap_pyfunction!(xml_to_abx, m)?)?;
+    m.add_function(wrap_pyfunction!(abx_to_xml, m)?)?;
+    m.add_function(wrap_pyfunction!(xml_file_to_abx, m)?)?;
+    m.add_function(wrap_pyfunction!(abx_file_to_xml, m)?)?;
+    Ok(())
+}